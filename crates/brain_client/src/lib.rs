@@ -0,0 +1,211 @@
+//! A typed client for `serve_brain`
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), brain_client::Error> {
+//! use brain_client::{request, BrainClient};
+//!
+//! let client = BrainClient::new("http://localhost:50000");
+//! client.train("museun", "hello world").await?;
+//! let resp = client.generate("museun", &request::Generate::default()).await?;
+//! println!("{}", resp.data);
+//! # Ok(())
+//! # }
+//! ```
+use std::time::Duration;
+
+use hyper::{body::Buf, client::HttpConnector, header, Body, Method, Request};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use brain_types::{request, response};
+pub use hyper::StatusCode;
+
+/// Talks to a single server, cloning it shares its connections
+#[derive(Clone)]
+pub struct BrainClient {
+    base: String,
+    key: Option<String>,
+    timeout: Option<Duration>,
+    client: hyper::Client<HttpConnector>,
+}
+
+impl BrainClient {
+    /// `base` is where the server is, like `http://localhost:50000`
+    ///
+    /// Only plain `http` is supported.
+    pub fn new(base: impl Into<String>) -> Self {
+        let mut base = base.into();
+        while base.ends_with('/') {
+            base.pop();
+        }
+        Self {
+            base,
+            key: None,
+            timeout: None,
+            client: hyper::Client::new(),
+        }
+    }
+
+    /// Sends `token` as the API key of every request
+    pub fn with_key(mut self, token: impl Into<String>) -> Self {
+        self.key.replace(token.into());
+        self
+    }
+
+    /// Gives up on any request that takes longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout.replace(timeout);
+        self
+    }
+
+    pub async fn generate(
+        &self,
+        name: &str,
+        opts: &request::Generate,
+    ) -> Result<response::Generate, Error> {
+        let path = format!("/{}/generate", segment(name));
+        decode(&self.send(Method::GET, &path, Some(opts)).await?)
+    }
+
+    /// Trains a single line, which the server filters first
+    pub async fn train(&self, name: &str, data: impl Into<String>) -> Result<(), Error> {
+        let path = format!("/{}/train", segment(name));
+        let body = request::Train { data: data.into() };
+        self.send(Method::POST, &path, Some(&body)).await.map(drop)
+    }
+
+    /// Creates an empty brain, using the server's default depth if `depth` isn't given
+    ///
+    /// A brain that already exists is a `409 Conflict`, see [`Error::status`].
+    pub async fn create(&self, name: &str, depth: Option<usize>) -> Result<(), Error> {
+        let path = format!("/{}/create", segment(name));
+        let body = request::Create { depth };
+        self.send(Method::POST, &path, Some(&body)).await.map(drop)
+    }
+
+    /// Every brain the key can use
+    pub async fn list(&self) -> Result<response::Brains, Error> {
+        decode(&self.send::<()>(Method::GET, "/brains", None).await?)
+    }
+
+    async fn send<T: Serialize + Sync>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&T>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(format!("{}{path}", self.base));
+        if let Some(key) = &self.key {
+            req = req.header("x-api-key", key);
+        }
+
+        let req = match body {
+            Some(body) => req
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(body).map_err(Error::Json)?)),
+            None => req.body(Body::empty()),
+        }
+        .map_err(Error::Request)?;
+
+        let send = async {
+            let resp = self.client.request(req).await?;
+            let status = resp.status();
+            let body = hyper::body::aggregate(resp.into_body()).await?;
+            Ok::<_, hyper::Error>((status, body))
+        };
+
+        let (status, body) = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, send)
+                .await
+                .map_err(|_| Error::TimedOut(timeout))?,
+            None => send.await,
+        }
+        .map_err(Error::Http)?;
+
+        let mut data = Vec::with_capacity(body.remaining());
+        std::io::copy(&mut body.reader(), &mut data).expect("reading from memory");
+
+        if !status.is_success() {
+            return Err(Error::server(status, &data));
+        }
+        Ok(data)
+    }
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(data).map_err(Error::Json)
+}
+
+// brain names are checked by the server, but they still shouldn't change the path.
+// dots are escaped too, so a name like `..` can't become a relative segment
+fn segment(name: &str) -> String {
+    name.bytes().fold(String::new(), |mut out, byte| {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'~' => out.push(byte as char),
+            byte => out.push_str(&format!("%{byte:02X}")),
+        }
+        out
+    })
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server turned the request down, with why
+    Server {
+        status: StatusCode,
+        msg: String,
+    },
+    /// The server couldn't be reached, or hung up
+    Http(hyper::Error),
+    /// The request couldn't be made, usually because the base url is invalid
+    Request(hyper::http::Error),
+    /// A body couldn't be encoded, or the server sent one that couldn't be decoded
+    Json(serde_json::Error),
+    TimedOut(Duration),
+}
+
+impl Error {
+    /// The status the server responded with, if it did
+    pub const fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Server { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    // errors from the server are a `response::Error`, unless something before it rejected the request
+    fn server(status: StatusCode, data: &[u8]) -> Self {
+        let msg = match serde_json::from_slice::<response::Error>(data) {
+            Ok(response::Error { msg }) => msg,
+            Err(..) => String::from_utf8_lossy(data).into_owned(),
+        };
+        Self::Server { status, msg }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Server { status, msg } => write!(f, "{status}: {msg}"),
+            Self::Http(err) => write!(f, "cannot reach the server: {err}"),
+            Self::Request(err) => write!(f, "cannot make the request: {err}"),
+            Self::Json(err) => write!(f, "invalid json: {err}"),
+            Self::TimedOut(timeout) => write!(f, "timed out after {timeout:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(err) => Some(err),
+            Self::Request(err) => Some(err),
+            Self::Json(err) => Some(err),
+            Self::Server { .. } | Self::TimedOut(..) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
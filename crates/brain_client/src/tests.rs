@@ -0,0 +1,78 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use brain::state::{Limits, Shutdown, State};
+use markov::format::SaveOptions;
+
+use super::*;
+
+async fn serve(dir: &std::path::Path) -> SocketAddr {
+    let state = State {
+        directory: dir.to_path_buf(),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits: Limits::default(),
+        shutdown: Shutdown::default(),
+    };
+    let server = axum::Server::bind(&([127, 0, 0, 1], 0).into())
+        .serve(brain::router(state).into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = BrainClient::new(format!("http://{}/", serve(dir.path()).await));
+
+    client.create("museun", Some(2)).await.unwrap();
+    let err = client.create("museun", None).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::CONFLICT));
+
+    client.train("museun", "hello world").await.unwrap();
+    let opts = request::Generate {
+        min: 1,
+        max: 2,
+        ..request::Generate::default()
+    };
+    let resp = client.generate("museun", &opts).await.unwrap();
+    assert_eq!(resp.data, "hello world");
+
+    let resp = client.list().await.unwrap();
+    assert_eq!(resp.brains, ["museun"]);
+}
+
+#[tokio::test]
+async fn decodes_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = BrainClient::new(format!("http://{}", serve(dir.path()).await));
+
+    match client.train("nobody", "hello").await.unwrap_err() {
+        Error::Server { status, msg } => {
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            assert_eq!(msg, "cannot find nobody");
+        }
+        err => panic!("unexpected error: {err}"),
+    }
+
+    let opts = request::Generate {
+        min: 0,
+        ..request::Generate::default()
+    };
+    let err = client
+        .generate("nobody/../brains", &opts)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+}
+
+#[test]
+fn segment() {
+    assert_eq!(super::segment("museun_2-a~"), "museun_2-a~");
+    assert_eq!(super::segment("a.b"), "a%2Eb");
+    assert_eq!(super::segment(".."), "%2E%2E");
+    assert_eq!(super::segment("a/b c"), "a%2Fb%20c");
+}
@@ -0,0 +1,12 @@
+//! The JSON bodies `serve_brain` accepts and sends
+//!
+//! This is shared by `serve_brain`, `brain_client` and `shaken`, so they can't
+//! disagree about what a request looks like.
+pub mod request;
+pub mod response;
+
+/// The most words a generated sentence can have
+pub const MAX_WORDS: usize = 256;
+
+/// The most times a sentence can be generated again, for a single request
+pub const MAX_RETRIES: usize = 16;
@@ -0,0 +1,161 @@
+use crate::{MAX_RETRIES, MAX_WORDS};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Train {
+    pub data: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Forget {
+    pub data: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Prune {
+    pub min_count: usize,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Decay {
+    pub factor: f64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Generate {
+    pub min: usize,
+    pub max: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub sampling: markov::SamplingConfig,
+    /// Words the sentence must contain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+    /// Words the sentence must not contain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden: Vec<String>,
+    /// Words that are never picked while generating
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocklist: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chars: Option<usize>,
+    /// How many more times to try, see `markov::GenerateRequest::retries`
+    #[serde(default)]
+    pub retries: usize,
+    #[serde(default)]
+    pub sentence_end: bool,
+}
+
+impl Default for Generate {
+    fn default() -> Self {
+        Self {
+            min: 3,
+            max: 5,
+            query: None,
+            seed: None,
+            sampling: markov::SamplingConfig::default(),
+            required: Vec::new(),
+            forbidden: Vec::new(),
+            blocklist: Vec::new(),
+            max_chars: None,
+            retries: 0,
+            sentence_end: false,
+        }
+    }
+}
+
+impl Generate {
+    /// Checks the options make sense, before the brain tries them
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min == 0 {
+            return Err("min must be at least 1".into());
+        }
+        if self.min > self.max {
+            return Err("min must not be more than max".into());
+        }
+        if self.max > MAX_WORDS {
+            return Err(format!("max must not be more than {MAX_WORDS}"));
+        }
+        if self.max_chars == Some(0) {
+            return Err("max_chars must be at least 1".into());
+        }
+        if self.retries > MAX_RETRIES {
+            return Err(format!("retries must not be more than {MAX_RETRIES}"));
+        }
+        if self.required.len() > self.max {
+            return Err("required must not have more words than max".into());
+        }
+
+        for (name, words) in [
+            ("required", &self.required),
+            ("forbidden", &self.forbidden),
+            ("blocklist", &self.blocklist),
+        ] {
+            if words.len() > MAX_WORDS {
+                return Err(format!("{name} must not have more than {MAX_WORDS} words"));
+            }
+            if let Some(word) = words
+                .iter()
+                .find(|word| word.is_empty() || word.contains(char::is_whitespace))
+            {
+                return Err(format!("{name} must only have single words, not {word:?}"));
+            }
+        }
+
+        let excluded = |word: &str| {
+            self.forbidden
+                .iter()
+                .chain(&self.blocklist)
+                .any(|other| other.eq_ignore_ascii_case(word))
+        };
+        let query = self.query.iter().flat_map(|query| query.split_whitespace());
+        if let Some(word) = self
+            .required
+            .iter()
+            .map(String::as_str)
+            .chain(query)
+            .find(|word| excluded(word))
+        {
+            return Err(format!("{word} cannot be both wanted and forbidden"));
+        }
+        Ok(())
+    }
+}
+
+/// A JSON body for `/:name/train/batch`, either `["a", "b"]` or `{"data": ["a", "b"]}`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Batch {
+    Lines(Vec<String>),
+    Data { data: Vec<String> },
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct Candidates {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    #[serde(flatten)]
+    pub opts: Generate,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Create {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Info {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top: Option<usize>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Rename {
+    pub name: String,
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,78 @@
+use super::*;
+
+#[test]
+fn validate() {
+    assert!(Generate::default().validate().is_ok());
+
+    let generate = |min, max| Generate {
+        min,
+        max,
+        ..Generate::default()
+    };
+    assert!(generate(1, 1).validate().is_ok());
+    assert!(generate(1, MAX_WORDS).validate().is_ok());
+
+    assert!(generate(0, 5).validate().is_err());
+    assert!(generate(6, 5).validate().is_err());
+    assert!(generate(1, MAX_WORDS + 1).validate().is_err());
+}
+
+#[test]
+fn validate_options() {
+    let ok = |generate: Generate| generate.validate().is_ok();
+    let words = |words: &[&str]| words.iter().map(|&s| s.to_string()).collect::<Vec<_>>();
+
+    assert!(ok(Generate {
+        query: Some("hello world".into()),
+        required: words(&["rust"]),
+        forbidden: words(&["python"]),
+        blocklist: words(&["java"]),
+        max_chars: Some(100),
+        retries: MAX_RETRIES,
+        sentence_end: true,
+        ..Generate::default()
+    }));
+
+    assert!(!ok(Generate {
+        max_chars: Some(0),
+        ..Generate::default()
+    }));
+    assert!(!ok(Generate {
+        retries: MAX_RETRIES + 1,
+        ..Generate::default()
+    }));
+    assert!(!ok(Generate {
+        required: words(&["a", "b", "c", "d", "e", "f"]),
+        ..Generate::default()
+    }));
+    assert!(!ok(Generate {
+        forbidden: words(&["two words"]),
+        ..Generate::default()
+    }));
+    assert!(!ok(Generate {
+        blocklist: words(&[""]),
+        ..Generate::default()
+    }));
+    assert!(!ok(Generate {
+        required: words(&["Rust"]),
+        blocklist: words(&["rust"]),
+        ..Generate::default()
+    }));
+    assert!(!ok(Generate {
+        query: Some("hello world".into()),
+        forbidden: words(&["world"]),
+        ..Generate::default()
+    }));
+}
+
+#[test]
+fn options_are_optional() {
+    let generate: Generate = serde_json::from_str(r#"{"min": 1, "max": 2}"#).unwrap();
+    assert!(generate.required.is_empty());
+    assert_eq!(generate.retries, 0);
+    assert!(!generate.sentence_end);
+
+    let json = serde_json::to_value(Generate::default()).unwrap();
+    assert!(json.get("required").is_none());
+    assert!(json.get("max_chars").is_none());
+}
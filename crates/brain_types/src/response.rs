@@ -0,0 +1,71 @@
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Generate {
+    pub data: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Candidates {
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Candidate {
+    pub data: String,
+    /// Generating with this seed produces the same sentence again
+    pub seed: u64,
+    pub words: usize,
+    /// How many of the query's words the sentence contains
+    pub matched: usize,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Trained {
+    pub trained: usize,
+    /// Lines that were empty once they were filtered
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Removed {
+    pub links: usize,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Error {
+    pub msg: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Brains {
+    pub brains: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Info {
+    pub metadata: markov::Metadata,
+    pub path: String,
+    /// Whether the brain is memory-mapped
+    pub mapped: bool,
+    /// Lines trained (or forgotten) since the brain was last saved
+    pub journaled: usize,
+    /// Only available for brains that aren't memory-mapped
+    pub stats: Option<markov::BrainStats>,
+}
+
+/// Something a brain learned or said, sent to `/:name/stream`
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    Trained { data: String },
+    Generated { data: String },
+}
+
+impl Event {
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Trained { .. } => "trained",
+            Self::Generated { .. } => "generated",
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Cleaning up chat messages before a brain is trained on them
+//!
+//! This is shared by `train_brain`, `serve_brain`'s train endpoint and `shaken`.
+//!
+//! ```
+//! let filters = filters::Filters {
+//!     names: vec!["shaken_bot".into()],
+//!     min_words: 2,
+//!     ..filters::Filters::standard()
+//! };
+//! assert_eq!(
+//!     filters.apply("@museun look at https://example.com shaken_bot!").as_deref(),
+//!     Some("look at")
+//! );
+//! assert_eq!(filters.apply("!uptime"), None);
+//! ```
+use std::collections::HashSet;
+
+/// Which filters are applied to each line
+///
+/// Words are removed first, then the whole line is dropped if what's left
+/// doesn't pass the line filters.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Filters {
+    /// Remove these names (e.g. the bot's), ignoring case and surrounding punctuation
+    pub names: Vec<String>,
+    /// Remove links
+    pub links: bool,
+    /// Remove `@mentions`
+    pub mentions: bool,
+    /// Remove `!commands`
+    pub commands: bool,
+    /// Remove words with non-ASCII characters
+    pub non_ascii: bool,
+    /// Drop lines that are only emotes
+    pub emote_only: bool,
+    /// The emotes known to [`Filters::apply`]
+    pub emotes: HashSet<String>,
+    /// Drop lines where a character repeats more than this many times in a row
+    pub max_repeat: Option<usize>,
+    /// Drop lines with fewer words than this
+    pub min_words: usize,
+}
+
+impl Filters {
+    /// The filters `shaken` has always used: links, mentions, commands and non-ASCII words
+    pub fn standard() -> Self {
+        Self {
+            links: true,
+            mentions: true,
+            commands: true,
+            non_ascii: true,
+            ..Self::default()
+        }
+    }
+
+    /// Filters `input`, returning nothing if the line should be skipped entirely
+    pub fn apply(&self, input: &str) -> Option<String> {
+        self.apply_with(input, |word| self.emotes.contains(word))
+    }
+
+    /// Like [`Filters::apply`], but with a different source of emotes
+    pub fn apply_with(&self, input: &str, is_emote: impl Fn(&str) -> bool) -> Option<String> {
+        let words = input
+            .split_ascii_whitespace()
+            .filter(|word| self.keep(word))
+            .collect::<Vec<_>>();
+
+        if words.is_empty() || words.len() < self.min_words {
+            return None;
+        }
+
+        if self.emote_only && words.iter().all(|word| is_emote(word)) {
+            return None;
+        }
+
+        if let Some(max) = self.max_repeat {
+            if words.iter().any(|word| longest_run(word) > max) {
+                return None;
+            }
+        }
+
+        Some(words.join(" "))
+    }
+
+    fn keep(&self, word: &str) -> bool {
+        !((self.commands && word.starts_with('!'))
+            || (self.mentions && word.starts_with('@'))
+            || (self.non_ascii && !word.is_ascii())
+            || (self.links && url::Url::parse(word).is_ok())
+            || self.is_name(word))
+    }
+
+    fn is_name(&self, word: &str) -> bool {
+        const HEAD: [char; 6] = ['(', '[', '\'', '\"', '#', '@'];
+        const TAIL: [char; 9] = ['.', '?', '!', '\"', '\'', ']', ')', ',', ':'];
+
+        if self.names.is_empty() {
+            return false;
+        }
+        let word = word.trim_start_matches(HEAD).trim_end_matches(TAIL);
+        self.names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(word))
+    }
+}
+
+/// Parses a comma separated list of filters
+///
+/// | filter          |                                   |
+/// | --------------- | --------------------------------- |
+/// | `standard`      | see [`Filters::standard`]         |
+/// | `links`         | remove links                      |
+/// | `mentions`      | remove `@mentions`                |
+/// | `commands`      | remove `!commands`                |
+/// | `non-ascii`     | remove words with non-ASCII characters |
+/// | `emote-only`    | drop lines that are only emotes   |
+/// | `name=<name>`   | remove `name`, this can be repeated |
+/// | `max-repeat=<n>`| drop lines with a character repeated more than `n` times in a row |
+/// | `min-words=<n>` | drop lines with fewer than `n` words |
+///
+/// `none` (or nothing) is no filtering at all.
+impl std::str::FromStr for Filters {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |key: &str, val: &str| {
+            val.parse::<usize>()
+                .map_err(|_| format!("{key} must be a number, not {val}"))
+        };
+
+        let mut filters = Self::default();
+        for part in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match part.split_once('=') {
+                None => match part {
+                    "none" => {}
+                    "standard" => {
+                        filters.links = true;
+                        filters.mentions = true;
+                        filters.commands = true;
+                        filters.non_ascii = true;
+                    }
+                    "links" => filters.links = true,
+                    "mentions" => filters.mentions = true,
+                    "commands" => filters.commands = true,
+                    "non-ascii" => filters.non_ascii = true,
+                    "emote-only" => filters.emote_only = true,
+                    part => return Err(format!("unknown filter: {part}")),
+                },
+                Some(("name", name)) => filters.names.push(name.to_string()),
+                Some((key @ "max-repeat", val)) => filters.max_repeat = Some(number(key, val)?),
+                Some((key @ "min-words", val)) => filters.min_words = number(key, val)?,
+                Some((key, _)) => return Err(format!("unknown filter: {key}")),
+            }
+        }
+        Ok(filters)
+    }
+}
+
+fn longest_run(word: &str) -> usize {
+    let mut chars = word.chars();
+    let mut last = match chars.next() {
+        Some(ch) => ch,
+        None => return 0,
+    };
+
+    let (mut longest, mut current) = (1, 1);
+    for ch in chars {
+        current = if ch == last { current + 1 } else { 1 };
+        longest = longest.max(current);
+        last = ch;
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests;
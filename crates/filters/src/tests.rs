@@ -0,0 +1,74 @@
+use super::*;
+
+#[test]
+fn standard() {
+    let filters = Filters::standard();
+    assert_eq!(
+        filters
+            .apply("hey @museun check https://example.com !song now")
+            .as_deref(),
+        Some("hey check now")
+    );
+    assert_eq!(filters.apply("caf\u{e9} time").as_deref(), Some("time"));
+    assert_eq!(filters.apply("!song"), None);
+    assert_eq!(filters.apply("   "), None);
+}
+
+#[test]
+fn names() {
+    let filters = Filters {
+        names: vec!["shaken_bot".into(), "shaken".into()],
+        ..Filters::default()
+    };
+    assert_eq!(
+        filters
+            .apply("(Shaken_Bot) hello @shaken, shakenly")
+            .as_deref(),
+        Some("hello shakenly")
+    );
+}
+
+#[test]
+fn lines() {
+    let filters = Filters {
+        emote_only: true,
+        emotes: ["Kappa".to_string(), "LUL".to_string()]
+            .into_iter()
+            .collect(),
+        max_repeat: Some(4),
+        min_words: 2,
+        ..Filters::default()
+    };
+    assert_eq!(filters.apply("Kappa LUL Kappa"), None);
+    assert_eq!(filters.apply("Kappa"), None);
+    assert_eq!(
+        filters.apply("that was funny LUL").as_deref(),
+        Some("that was funny LUL")
+    );
+    assert_eq!(filters.apply("nooooooooo way"), None);
+    assert_eq!(filters.apply("nooooo").as_deref(), None);
+    assert_eq!(filters.apply("noooo way").as_deref(), Some("noooo way"));
+
+    assert_eq!(filters.apply_with("pog pog", |word| word == "pog"), None);
+}
+
+#[test]
+fn parse() {
+    let filters: Filters = "standard, name=shaken, max-repeat=3, min-words=2"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        filters,
+        Filters {
+            names: vec!["shaken".into()],
+            max_repeat: Some(3),
+            min_words: 2,
+            ..Filters::standard()
+        }
+    );
+
+    assert_eq!("none".parse::<Filters>().unwrap(), Filters::default());
+    assert_eq!("".parse::<Filters>().unwrap(), Filters::default());
+    assert!("links,bogus".parse::<Filters>().is_err());
+    assert!("min-words=many".parse::<Filters>().is_err());
+}
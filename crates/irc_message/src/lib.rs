@@ -0,0 +1,10 @@
+//! Parsing for lines of the (Twitch flavored) IRC protocol
+//!
+//! This is shared by `shaken`, which reads them from a connection, and
+//! `train_brain`, which reads them from logs.
+
+mod tags;
+pub use tags::Tags;
+
+mod message;
+pub use message::Message;
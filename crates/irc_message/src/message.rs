@@ -0,0 +1,78 @@
+use crate::Tags;
+
+/// A single line of the protocol
+#[derive(Debug, Clone)]
+pub struct Message<'a> {
+    pub tags: Tags,
+    /// The nickname part of the prefix, if there was one
+    pub prefix: Option<&'a str>,
+    pub command: &'a str,
+    pub args: Vec<&'a str>,
+    pub data: Option<&'a str>,
+}
+
+impl<'a> Message<'a> {
+    /// Parses a line, returning `None` if it's malformed
+    pub fn parse(mut line: &'a str) -> Option<Self> {
+        let line = &mut line;
+        let tags = if line.starts_with('@') {
+            Tags::parse(line)?
+        } else {
+            Tags::default()
+        };
+
+        let prefix = if line.starts_with(':') {
+            Some(Self::prefix(line)?)
+        } else {
+            None
+        };
+
+        let command = Self::command(line)?;
+        let args = Self::args(line);
+        let data = Self::data(line);
+
+        Some(Self {
+            tags,
+            prefix,
+            command,
+            args,
+            data,
+        })
+    }
+
+    /// The target and the text, if this is a `PRIVMSG`
+    pub fn privmsg(&self) -> Option<(&'a str, &'a str)> {
+        if self.command != "PRIVMSG" {
+            return None;
+        }
+        Some((self.args.first()?, self.data?))
+    }
+
+    fn prefix(input: &mut &'a str) -> Option<&'a str> {
+        let (head, tail) = input.split_once(' ')?;
+        *input = tail;
+        head[1..].split_terminator('!').next()
+    }
+
+    fn command(input: &mut &'a str) -> Option<&'a str> {
+        let (head, tail) = input.split_once(' ').unwrap_or((input.trim_end(), ""));
+        *input = tail;
+        Some(head).filter(|s| !s.is_empty())
+    }
+
+    fn args(input: &mut &'a str) -> Vec<&'a str> {
+        if let Some((head, tail)) = input.split_once(':') {
+            *input = tail;
+            head.split_ascii_whitespace().collect()
+        } else {
+            vec![]
+        }
+    }
+
+    fn data(input: &mut &'a str) -> Option<&'a str> {
+        Some(input.trim_end()).filter(|s| !s.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,34 @@
+use super::*;
+
+#[test]
+fn privmsg() {
+    let line = "@badges=broadcaster/1;display-name=Museun :museun!museun@museun.tmi.twitch.tv PRIVMSG #museun :hello world\r\n";
+    let msg = Message::parse(line).unwrap();
+    assert_eq!(msg.tags.get("display-name").unwrap(), "Museun");
+    assert_eq!(msg.prefix, Some("museun"));
+    assert_eq!(msg.command, "PRIVMSG");
+    assert_eq!(msg.privmsg(), Some(("#museun", "hello world")));
+}
+
+#[test]
+fn other() {
+    let msg = Message::parse("PING :tmi.twitch.tv\r\n").unwrap();
+    assert_eq!(msg.command, "PING");
+    assert_eq!(msg.data, Some("tmi.twitch.tv"));
+    assert_eq!(msg.privmsg(), None);
+
+    let msg = Message::parse(":tmi.twitch.tv RECONNECT").unwrap();
+    assert_eq!(msg.command, "RECONNECT");
+
+    let line = "@display-name=Shaken;user-id=42 :tmi.twitch.tv GLOBALUSERSTATE\r\n";
+    let msg = Message::parse(line).unwrap();
+    assert_eq!(msg.command, "GLOBALUSERSTATE");
+    assert_eq!(msg.tags.get("user-id").unwrap(), "42");
+}
+
+#[test]
+fn malformed() {
+    for line in ["", ":museun", "@badges=", "\r\n"] {
+        assert!(Message::parse(line).is_none(), "{line:?}");
+    }
+}
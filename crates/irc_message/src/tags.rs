@@ -4,7 +4,7 @@ use anyhow::Context;
 
 #[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Tags {
-    pub(crate) map: HashMap<Box<str>, Box<str>>,
+    map: HashMap<Box<str>, Box<str>>,
 }
 
 impl Tags {
@@ -25,6 +25,10 @@ impl Tags {
         Some(Self { map })
     }
 
+    pub fn insert(&mut self, key: impl Into<Box<str>>, val: impl Into<Box<str>>) {
+        self.map.insert(key.into(), val.into());
+    }
+
     pub fn get<K>(&self, k: &K) -> anyhow::Result<&str>
     where
         K: Hash + Eq + ?Sized + std::fmt::Display,
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use markov::{Brain, GenerateRequest};
+
+const WORDS: &[&str] = &[
+    "the", "a", "chat", "is", "stream", "kappa", "pog", "rust", "bot", "hello", "what", "why",
+    "this", "that", "compiler", "borrow", "checker", "love", "hate", "yes", "no", "maybe", "lol",
+    "game", "music", "song", "today", "tomorrow", "never", "always", "good", "bad",
+];
+
+fn corpus(lines: usize) -> Vec<String> {
+    let rng = fastrand::Rng::with_seed(0xDEAD_BEEF);
+    (0..lines)
+        .map(|_| {
+            (0..rng.usize(3..20))
+                .map(|_| WORDS[rng.usize(0..WORDS.len())])
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+fn trained(lines: &[String]) -> Brain {
+    let mut brain = Brain::new("bench", 5);
+    lines.iter().for_each(|line| brain.train(line));
+    brain
+}
+
+fn training(c: &mut Criterion) {
+    let lines = corpus(2_000);
+    let mut group = c.benchmark_group("train");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(lines.len() as _));
+    group.bench_function("lines", |b| {
+        b.iter_batched(
+            || Brain::new("bench", 5),
+            |mut brain| {
+                lines.iter().for_each(|line| brain.train(line));
+                brain
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn generation(c: &mut Criterion) {
+    let brain = trained(&corpus(10_000));
+    let req = |min, max, query: Option<&str>| GenerateRequest {
+        min,
+        max,
+        query: query.map(String::from),
+        seed: Some(42),
+        timeout: Duration::from_secs(1),
+        ..GenerateRequest::default()
+    };
+    let (short, long, query) = (
+        req(3, 5, None),
+        req(20, 30, None),
+        req(3, 10, Some("rust compiler")),
+    );
+
+    let mut group = c.benchmark_group("generate");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("short", |b| b.iter(|| brain.generate(&short)));
+    group.bench_function("long", |b| b.iter(|| brain.generate(&long)));
+    group.bench_function("query", |b| b.iter(|| brain.generate(&query)));
+    group.finish();
+}
+
+criterion_group!(benches, training, generation);
+criterion_main!(benches);
@@ -1,137 +1,352 @@
-use std::time::Duration;
+use std::collections::BTreeSet;
 
-use fastrand_ext::IterExt as _;
 use hashbrown::{HashMap, HashSet};
 
-use crate::{Link, Set, Token, Word};
+use crate::{
+    generate::{self, Source},
+    sampling::Candidates,
+    BrainDiff, BrainStats, ContextLinks, GenerateOutcome, GenerateRequest, Link, Metadata, Set,
+    Token, Word,
+};
+
+/// Why two brains couldn't be merged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// The other brain doesn't have enough context for every width this brain uses
+    DepthMismatch { expected: usize, found: usize },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DepthMismatch { expected, found } => {
+                write!(f, "expected a depth of at least {expected}, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// Why a brain couldn't be decayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecayError {
+    /// The factor isn't a number from 0 to 1
+    InvalidFactor(f64),
+}
+
+impl std::fmt::Display for DecayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFactor(factor) => {
+                write!(f, "expected a factor from 0 to 1, found {factor}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecayError {}
+
+pub(crate) type Chain = HashMap<Vec<Word>, Set>;
+// the head is sampled by position, so its order can't depend on how it was built (or loaded) for seeded generation
+pub(crate) type Head = BTreeSet<Word>;
+// the fingerprint of every distinct line the brain was trained on, see `fingerprint`
+// this grows by 8 bytes (plus the table's overhead) for every new line, and is included in `estimate_memory`
+pub(crate) type Lines = HashSet<u64>;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Brain {
-    name: String,
-    depth: usize,
-    chain: HashMap<Vec<Word>, Set>,
-    head: HashSet<Word>,
+    meta: Metadata,
+    chain: Chain,
+    head: Head,
+    lines: Lines,
 }
 
 impl Brain {
     pub fn new(name: impl Into<String>, depth: usize) -> Self {
+        let now = Metadata::now();
+        Self::from_parts(
+            Metadata {
+                name: name.into(),
+                depth,
+                created_at: now,
+                trained_at: now,
+                lines: 0,
+            },
+            Chain::default(),
+            Head::default(),
+            Lines::default(),
+        )
+    }
+
+    pub(crate) const fn from_parts(meta: Metadata, chain: Chain, head: Head, lines: Lines) -> Self {
         Self {
-            name: name.into(),
-            depth,
-            chain: HashMap::default(),
-            head: HashSet::default(),
+            meta,
+            chain,
+            head,
+            lines,
         }
     }
 
+    pub(crate) const fn parts(&self) -> (&Metadata, &Chain, &Head) {
+        (&self.meta, &self.chain, &self.head)
+    }
+
     pub fn name(&self) -> &str {
-        &self.name
+        &self.meta.name
+    }
+
+    pub fn rename(&mut self, name: impl Into<String>) {
+        self.meta.name = name.into()
     }
 
     pub const fn depth(&self) -> usize {
-        self.depth
-    }
-
-    #[tracing::instrument(skip(self, rng))]
-    pub fn generate(
-        &self,
-        rng: &fastrand::Rng,
-        min: usize,
-        max: usize,
-        query: Option<&str>,
-        time_out: Duration,
-    ) -> Option<String> {
-        let mut words = <Vec<Word>>::new();
-        let mut indices = Adjacent::new();
-
-        let mut base = Self::base_words(query);
-        rng.shuffle(&mut base);
-
-        let mut pick = |max: usize| loop {
-            let t = if max == 1 { max } else { rng.usize(1..max) };
-            if indices.create_adjacency(t) {
-                break t;
-            }
-        };
+        self.meta.depth
+    }
 
-        let mut choose = |words: &mut Vec<Word>| {
-            if !base.is_empty() && words.len() > 1 && rng.f64() > rng.f64() {
-                let next = base.pop().unwrap();
-                let n = pick(words.len());
-                words.insert(n, next) // TODO this would be better as a linked list
-            }
-        };
+    pub const fn metadata(&self) -> &Metadata {
+        &self.meta
+    }
 
-        let now = std::time::Instant::now();
-        'outer: loop {
-            if now.elapsed() > time_out {
-                return None;
-            }
+    pub(crate) const fn lines(&self) -> &Lines {
+        &self.lines
+    }
 
-            if words.len() >= min {
-                break;
-            }
+    /// Whether the brain was trained on exactly `text`, ignoring how the words were spaced
+    pub fn was_trained_on(&self, text: &str) -> bool {
+        self.lines.contains(&line(text))
+    }
 
-            choose(&mut words);
-            words.push(self.head.iter().choose(rng)?.clone());
-            if words.len() >= max {
-                break;
-            }
+    /// Adds everything `other` was trained on to this brain
+    ///
+    /// `other` must be at least as deep as this brain, any longer contexts it has are skipped.
+    #[tracing::instrument(skip(self, other))]
+    pub fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        use hashbrown::hash_map::RawEntryMut::*;
 
-            while let Token::Word(word) =
-                self.select_token(rng, Self::context(words.as_slice(), self.depth))
-            {
-                choose(&mut words);
-                words.push(word);
-                if words.len() >= max {
-                    break 'outer;
+        if other.depth() < self.depth() {
+            return Err(MergeError::DepthMismatch {
+                expected: self.depth(),
+                found: other.depth(),
+            });
+        }
+
+        for (context, set) in other
+            .chain
+            .iter()
+            .filter(|(k, _)| k.len() <= self.meta.depth)
+        {
+            match self.chain.raw_entry_mut().from_key(context.as_slice()) {
+                Occupied(e) => e.into_mut().merge(set),
+                Vacant(e) => {
+                    e.insert(context.clone(), set.clone());
                 }
             }
         }
 
-        while let Some(word) = base.pop() {
-            let n = pick(words.len());
-            words.insert(n, word)
+        self.head.extend(other.head.iter().cloned());
+        self.lines.extend(other.lines.iter().copied());
+        self.meta.lines += other.meta.lines;
+        self.meta.trained_at = self.meta.trained_at.max(other.meta.trained_at);
+        Ok(())
+    }
+
+    /// Summarizes the brain, including the `top` most frequently seen words
+    #[tracing::instrument(skip(self))]
+    pub fn stats(&self, top: usize) -> BrainStats {
+        let words = || self.chain.iter().filter(|(k, _)| k.len() == 1);
+
+        let unique_words = words().count()
+            + self
+                .head
+                .iter()
+                .filter(|word| !self.chain.contains_key(std::slice::from_ref(*word)))
+                .count();
+
+        // every time a word is seen it's followed by exactly one link (or the end)
+        let mut top_words = words()
+            .map(|(k, set)| (String::from_utf8_lossy(&k[0]).into_owned(), set.total()))
+            .collect::<Vec<_>>();
+        top_words.sort_unstable_by(|(lw, lc), (rw, rc)| rc.cmp(lc).then_with(|| lw.cmp(rw)));
+        top_words.truncate(top);
+
+        BrainStats {
+            name: self.meta.name.clone(),
+            depth: self.meta.depth,
+            contexts: self.chain.len(),
+            unique_words,
+            total_links: self.chain.values().map(Set::size).sum(),
+            heads: self.head.len(),
+            lines: self.lines.len(),
+            top_words,
+            memory: self.estimate_memory(),
         }
+    }
+
+    /// Compares this brain with a `newer` version of it, including the `top` most frequently seen words added and removed
+    #[tracing::instrument(skip(self, newer))]
+    pub fn diff(&self, newer: &Self, top: usize) -> BrainDiff {
+        BrainDiff::between(self, newer, top)
+    }
 
-        let capacity = words.iter().map(|s| s.len() + 1).sum();
-        let mut out = String::with_capacity(capacity);
+    /// Every context that `word` appears in, with what can follow it
+    ///
+    /// Shorter contexts come first.
+    pub fn contexts(&self, word: &str) -> Vec<ContextLinks> {
+        fn lossy(word: &[u8]) -> String {
+            String::from_utf8_lossy(word).into_owned()
+        }
 
-        for word in words.into_iter().take(max) {
-            if let Ok(word) = std::str::from_utf8(&word) {
-                if !out.is_empty() {
-                    out.push(' ');
+        let mut contexts = self
+            .chain
+            .iter()
+            .filter(|(context, _)| context.iter().any(|w| **w == *word.as_bytes()))
+            .collect::<Vec<_>>();
+        contexts.sort_unstable_by(|(l, _), (r, _)| l.len().cmp(&r.len()).then_with(|| l.cmp(r)));
+
+        contexts
+            .into_iter()
+            .map(|(context, set)| {
+                let mut links = set
+                    .links()
+                    .iter()
+                    .map(|link| match &link.token {
+                        Token::Word(word) => (Some(lossy(word)), link.count),
+                        Token::End => (None, link.count),
+                    })
+                    .collect::<Vec<_>>();
+                links.sort_by(|(_, l), (_, r)| r.cmp(l));
+
+                ContextLinks {
+                    context: context.iter().map(|w| lossy(w)).collect(),
+                    links,
                 }
-                out.push_str(word);
-            }
+            })
+            .collect()
+    }
+
+    fn estimate_memory(&self) -> usize {
+        use std::mem::size_of;
+
+        fn word(word: &Word) -> usize {
+            size_of::<Word>() + word.len()
+        }
+
+        // hashbrown stores an extra control byte per bucket, and keeps the table at most 7/8ths full
+        fn table<K, V>(len: usize) -> usize {
+            len * (size_of::<(K, V)>() + 1) * 8 / 7
         }
 
-        out.shrink_to_fit();
-        Some(out)
+        let chain = self
+            .chain
+            .iter()
+            .map(|(context, set)| {
+                let context = context.iter().map(word).sum::<usize>();
+                let links = set
+                    .links()
+                    .iter()
+                    .map(|link| match &link.token {
+                        Token::Word(data) => data.len(),
+                        Token::End => 0,
+                    })
+                    .sum::<usize>();
+                let spilled = if set.size() > 1 {
+                    set.size() * size_of::<Link>()
+                } else {
+                    0
+                };
+                context + links + spilled
+            })
+            .sum::<usize>();
+
+        let head = self.head.iter().map(|w| w.len()).sum::<usize>();
+
+        size_of::<Self>()
+            + table::<Vec<Word>, Set>(self.chain.len())
+            + chain
+            + table::<Word, ()>(self.head.len())
+            + head
+            + table::<u64, ()>(self.lines.len())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn generate(&self, req: &GenerateRequest) -> GenerateOutcome {
+        generate::generate(self, req)
     }
 
     #[tracing::instrument(skip(self))]
     pub fn train(&mut self, text: &str) {
-        let text = text.trim();
-        if text.is_empty() {
-            return;
+        let words = match Self::split_words(text) {
+            Some(words) => words,
+            None => return,
+        };
+
+        self.head.insert(words[0].clone());
+        self.lines.insert(fingerprint(words.iter().map(|w| &**w)));
+
+        for (context, token) in Self::links(&words, self.meta.depth) {
+            self.train_link(context, token)
         }
 
-        let words = text
-            .split_whitespace()
-            .filter_map(|s| (!s.is_empty()).then(|| s.as_bytes().into()))
-            .collect::<Vec<Word>>();
+        self.meta.lines += 1;
+        self.meta.trained_at = Metadata::now();
+    }
 
-        self.head.insert(words[0].clone());
+    /// Reverses a previous [`Brain::train`] of the same `text`, returning whether it was trained on it
+    ///
+    /// A line the brain doesn't remember is left alone, including every line trained
+    /// before brains remembered their lines. A starting word is only removed once no
+    /// remaining line can start with it. The line is no longer remembered, even if it
+    /// was trained more than once.
+    #[tracing::instrument(skip(self))]
+    pub fn forget(&mut self, text: &str) -> bool {
+        let words = match Self::split_words(text) {
+            Some(words) => words,
+            None => return false,
+        };
+        if !self.lines.remove(&fingerprint(words.iter().map(|w| &**w))) {
+            return false;
+        }
 
-        let depth = std::cmp::min(self.depth, words.len() - 1);
-        for width in 1..=depth {
-            // TODO take ownership to remove the extra clone
-            for window in words.windows(width + 1) {
-                let tail = window.last().cloned().unwrap();
-                self.train_link(&window[..window.len() - 1], Token::Word(tail));
+        for (context, token) in Self::links(&words, self.meta.depth) {
+            self.forget_link(context, &token)
+        }
+        self.meta.lines = self.meta.lines.saturating_sub(1);
+
+        // a remaining line could only start with a word if the word leads a link (as every
+        // longer line's first word does), or it's a line of its own
+        for word in &words {
+            if !self.chain.contains_key(std::slice::from_ref(word))
+                && !self.lines.contains(&fingerprint([&**word]))
+            {
+                self.head.remove(word);
             }
-            self.train_link(&words[words.len() - width..], Token::End)
         }
+        true
+    }
+
+    /// Removes every link seen fewer than `min_count` times, returning how many were removed
+    ///
+    /// Contexts and starting words that are left without any links are removed as well.
+    #[tracing::instrument(skip(self))]
+    pub fn prune(&mut self, min_count: usize) -> usize {
+        self.retain_links(|link| link.count >= min_count)
+    }
+
+    /// Scales every count by `factor` (rounding down), returning how many links fell to zero and were removed
+    ///
+    /// Applying this once per epoch lets older data fade as newer data is trained. The
+    /// factor has to be from 0 to 1.
+    #[tracing::instrument(skip(self))]
+    pub fn decay(&mut self, factor: f64) -> Result<usize, DecayError> {
+        if !(0.0..=1.0).contains(&factor) {
+            return Err(DecayError::InvalidFactor(factor));
+        }
+
+        Ok(self.retain_links(|link| {
+            link.count = (link.count as f64 * factor) as usize;
+            link.count > 0
+        }))
     }
 
     #[tracing::instrument(skip(self))]
@@ -147,80 +362,110 @@ impl Brain {
         };
     }
 
-    #[tracing::instrument(skip(self, rng))]
-    fn select_token(&self, rng: &fastrand::Rng, context: &[Word]) -> Token {
-        let upper = std::cmp::min(self.depth, context.len());
-        let mut sets = (1..=upper)
-            .filter_map(|w| {
-                self.chain
-                    .get(&context[context.len() - w..])
-                    .map(|set| (w, set.clone()))
-            })
-            .peekable();
-
-        let mut links: Vec<Link> = match sets.peek() {
-            Some((_, set)) => Vec::with_capacity(set.size()),
-            None => return Token::End,
-        };
-
-        for (width, set) in sets {
-            for mut link in set.0 {
-                link.count *= width;
-                match links.iter_mut().find(|left| left.token == link.token) {
-                    Some(e) => e.merge(&link),
-                    None => links.push(link),
-                }
+    #[tracing::instrument(skip(self))]
+    fn forget_link(&mut self, context: &[Word], token: &Token) {
+        if let Some(set) = self.chain.get_mut(context) {
+            if set.remove(token, 1) {
+                self.chain.remove(context);
             }
         }
-
-        Self::weighted_select(&links, rng).token.clone()
     }
 
-    fn weighted_select<'a>(links: &'a [Link], rng: &fastrand::Rng) -> &'a Link {
-        let mut sum = links.iter().map(|Link { count, .. }| count).sum();
-        for link in links.iter().cycle().skip(rng.usize(0..sum)) {
-            match sum.checked_sub(link.count) {
-                Some(n) => sum = n,
-                None => return link,
-            }
-        }
-        unreachable!("DAG illformed")
+    fn retain_links(&mut self, mut keep: impl FnMut(&mut Link) -> bool) -> usize {
+        let mut removed = 0;
+        self.chain.retain(|_, set| {
+            removed += set.retain(&mut keep);
+            set.size() > 0
+        });
+
+        let chain = &self.chain;
+        self.head
+            .retain(|word| chain.contains_key(std::slice::from_ref(word)));
+        removed
     }
 
-    #[tracing::instrument]
-    fn base_words(input: Option<&str>) -> Vec<Word> {
-        input
-            .map(|data| {
-                data.split_ascii_whitespace()
-                    .map(|s| s.bytes().collect())
-                    .collect()
-            })
-            .unwrap_or_default()
+    fn split_words(text: &str) -> Option<Vec<Word>> {
+        let words = text
+            .split_whitespace()
+            .map(|s| s.as_bytes().into())
+            .collect::<Vec<Word>>();
+        (!words.is_empty()).then_some(words)
     }
 
-    #[inline(always)]
-    fn context(words: &[Word], depth: usize) -> &[Word] {
-        &words[words.len().saturating_sub(depth)..]
+    // every (context, token) pair that training `words` produces
+    fn links(words: &[Word], depth: usize) -> impl Iterator<Item = (&[Word], Token)> {
+        let depth = std::cmp::min(depth, words.len() - 1);
+        (1..=depth).flat_map(move |width| {
+            // TODO take ownership to remove the extra clone
+            let links = words.windows(width + 1).map(|window| {
+                let (tail, context) = window.split_last().unwrap();
+                (context, Token::Word(tail.clone()))
+            });
+            let end = (&words[words.len() - width..], Token::End);
+            links.chain(std::iter::once(end))
+        })
     }
 }
 
-struct Adjacent(Vec<usize>);
-impl Adjacent {
-    const fn new() -> Self {
-        Self(Vec::new())
-    }
+// the fingerprint of `text` as it would be trained
+pub(crate) fn line(text: &str) -> u64 {
+    fingerprint(text.split_whitespace().map(str::as_bytes))
 }
 
-impl Adjacent {
-    fn create_adjacency(&mut self, index: usize) -> bool {
-        if self.0.contains(&index) {
-            return false;
+/// A stable hash of a line's words, so it doesn't change between runs (or builds)
+///
+/// This is 64-bit FNV-1a, with a space between each word.
+pub(crate) fn fingerprint<'a>(words: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = OFFSET;
+    for (i, word) in words.into_iter().enumerate() {
+        let space = (i > 0).then_some(&b' ');
+        for &byte in space.into_iter().chain(word) {
+            hash = (hash ^ byte as u64).wrapping_mul(PRIME);
         }
+    }
+    hash
+}
+
+impl Source for Brain {
+    fn depth(&self) -> usize {
+        self.meta.depth
+    }
+
+    fn heads(&self) -> usize {
+        self.head.len()
+    }
+
+    fn head(&self, index: usize) -> &[u8] {
+        self.head
+            .iter()
+            .nth(index)
+            .expect("head should be in range")
+    }
+
+    fn knows(&self, word: &[u8]) -> bool {
+        self.head.contains(word)
+            || self
+                .chain
+                .contains_key(std::slice::from_ref(&Word::from(word)))
+    }
 
-        self.0.reserve(0);
-        for index in [index.saturating_sub(1), index, index + 1] {
-            self.0.push(index)
+    fn trained_on(&self, fingerprint: u64) -> bool {
+        self.lines.contains(&fingerprint)
+    }
+
+    fn candidates<'a>(&'a self, context: &[Word], weight: f64, candidates: &mut Candidates<'a>) {
+        if let Some(set) = self.chain.get(context) {
+            let links = set
+                .links()
+                .iter()
+                .map(|link| (link.token.as_ref(), link.count));
+            candidates.merge(links, weight)
         }
-        true
     }
 }
+
+#[cfg(test)]
+mod tests;
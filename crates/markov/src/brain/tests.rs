@@ -0,0 +1,472 @@
+use std::time::Duration;
+
+use super::*;
+use crate::SamplingConfig;
+
+const CORPUS: &[&str] = &[
+    "the borrow checker is my friend",
+    "the borrow checker hates me today",
+    "rust is a systems programming language",
+    "rust makes the compiler my friend",
+    "is the stream live today",
+    "the stream is live and the chat is happy",
+    "my friend writes rust on stream",
+    "today the compiler is happy with me",
+];
+
+fn brain() -> Brain {
+    let mut brain = Brain::new("test", 3);
+    CORPUS.iter().for_each(|line| brain.train(line));
+    brain
+}
+
+fn request(seed: u64) -> GenerateRequest {
+    GenerateRequest {
+        min: 3,
+        max: 12,
+        seed: Some(seed),
+        ..GenerateRequest::default()
+    }
+}
+
+#[test]
+fn golden() {
+    let brain = brain();
+    let expected = [
+        (1, "today rust is a systems programming language"),
+        (2, "the stream live and the chat is the stream live today"),
+        (3, "is happy today"),
+        (4, "today today is a systems programming language"),
+    ];
+
+    for (seed, expected) in expected {
+        let outcome = brain.generate(&request(seed));
+        assert_eq!(outcome, GenerateOutcome::Generated(expected.into()));
+    }
+}
+
+#[test]
+fn golden_with_query() {
+    let brain = brain();
+    let req = GenerateRequest {
+        query: Some("compiler".into()),
+        ..request(7)
+    };
+    assert_eq!(
+        brain.generate(&req),
+        GenerateOutcome::Generated("today compiler my friend".into())
+    );
+}
+
+#[test]
+fn golden_with_sampling() {
+    let brain = brain();
+    let req = GenerateRequest {
+        sampling: SamplingConfig {
+            temperature: 0.0,
+            min_context: 2,
+            ..SamplingConfig::default()
+        },
+        ..request(11)
+    };
+    assert_eq!(
+        brain.generate(&req),
+        GenerateOutcome::Generated("my friend the".into())
+    );
+}
+
+#[test]
+fn same_seed_same_output() {
+    let (left, right) = (brain(), brain());
+    for seed in 0..32 {
+        assert_eq!(
+            left.generate(&request(seed)),
+            right.generate(&request(seed))
+        );
+    }
+}
+
+#[test]
+fn empty_brain() {
+    let brain = Brain::new("test", 3);
+    assert_eq!(brain.generate(&request(1)), GenerateOutcome::EmptyBrain);
+}
+
+#[test]
+fn unknown_query() {
+    let req = GenerateRequest {
+        query: Some("kappa pogchamp".into()),
+        ..request(1)
+    };
+    assert_eq!(brain().generate(&req), GenerateOutcome::UnknownQuery);
+}
+
+#[test]
+fn timed_out() {
+    let req = GenerateRequest {
+        timeout: Duration::ZERO,
+        ..request(1)
+    };
+    assert_eq!(brain().generate(&req), GenerateOutcome::TimedOut);
+}
+
+#[test]
+fn more_required_words_than_slots() {
+    let mut brain = Brain::new("test", 3);
+    brain.train("hello");
+
+    let req = GenerateRequest {
+        min: 1,
+        max: 5,
+        required: vec!["a".into(), "b".into(), "c".into()],
+        timeout: Duration::from_secs(1),
+        ..GenerateRequest::default()
+    };
+
+    // a request that never finishes would hang the test, so it runs on its own thread
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for seed in 0..32 {
+            let req = GenerateRequest {
+                seed: Some(seed),
+                ..req.clone()
+            };
+            let _ = tx.send(brain.generate(&req));
+        }
+    });
+
+    for _ in 0..32 {
+        let outcome = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(
+            outcome,
+            GenerateOutcome::Generated(..) | GenerateOutcome::Unsatisfied
+        ));
+    }
+}
+
+// every sentence generated from the first `count` seeds
+fn sentences(brain: &Brain, req: GenerateRequest, count: u64) -> Vec<String> {
+    (0..count)
+        .filter_map(|seed| {
+            brain
+                .generate(&GenerateRequest {
+                    seed: Some(seed),
+                    ..req.clone()
+                })
+                .into_option()
+        })
+        .collect()
+}
+
+fn words(sentence: &str) -> Vec<String> {
+    sentence.split(' ').map(str::to_ascii_lowercase).collect()
+}
+
+#[test]
+fn required_and_forbidden() {
+    let brain = brain();
+    let req = GenerateRequest {
+        required: vec!["Happy".into()],
+        forbidden: vec!["today".into()],
+        retries: 8,
+        ..request(0)
+    };
+
+    let out = sentences(&brain, req, 16);
+    assert!(!out.is_empty());
+    for sentence in out {
+        let words = words(&sentence);
+        assert!(words.iter().any(|w| w == "happy"), "{sentence}");
+        assert!(!words.iter().any(|w| w == "today"), "{sentence}");
+    }
+}
+
+#[test]
+fn blocklist() {
+    let brain = brain();
+    let req = GenerateRequest {
+        blocklist: vec!["the".into(), "IS".into()],
+        ..request(0)
+    };
+
+    let out = sentences(&brain, req, 16);
+    assert_eq!(out.len(), 16);
+    for sentence in out {
+        let words = words(&sentence);
+        assert!(!words.iter().any(|w| w == "the" || w == "is"), "{sentence}");
+    }
+}
+
+#[test]
+fn max_chars() {
+    let brain = brain();
+    let req = GenerateRequest {
+        max_chars: Some(20),
+        retries: 4,
+        ..request(0)
+    };
+
+    let out = sentences(&brain, req, 16);
+    assert!(!out.is_empty());
+    for sentence in out {
+        assert!(sentence.chars().count() <= 20, "{sentence}");
+        assert!(sentence.split(' ').count() >= 3, "{sentence}");
+    }
+}
+
+#[test]
+fn sentence_end() {
+    let brain = brain();
+    let ends = CORPUS
+        .iter()
+        .filter_map(|line| line.split(' ').next_back())
+        .collect::<Vec<_>>();
+    let req = GenerateRequest {
+        sentence_end: true,
+        retries: 4,
+        ..request(0)
+    };
+
+    let out = sentences(&brain, req, 16);
+    assert!(!out.is_empty());
+    for sentence in out {
+        let last = sentence.split(' ').next_back().unwrap();
+        assert!(ends.contains(&last), "{sentence}");
+    }
+}
+
+#[test]
+fn retries_avoid_trained_lines() {
+    let brain = brain();
+    let repeats = |retries| {
+        let req = GenerateRequest {
+            retries,
+            ..request(0)
+        };
+        sentences(&brain, req, 64)
+            .iter()
+            .filter(|sentence| CORPUS.contains(&sentence.as_str()))
+            .count()
+    };
+    assert!(repeats(0) > 0);
+    assert_eq!(repeats(16), 0);
+
+    // a repeat is still better than nothing
+    let mut brain = Brain::new("test", 3);
+    brain.train("hello world");
+    let req = GenerateRequest {
+        min: 1,
+        max: 2,
+        retries: 2,
+        ..request(0)
+    };
+    assert_eq!(
+        brain.generate(&req),
+        GenerateOutcome::Generated("hello world".into())
+    );
+}
+
+#[test]
+fn unsatisfied() {
+    let req = GenerateRequest {
+        required: vec!["happy".into()],
+        max_chars: Some(3),
+        retries: 2,
+        ..request(1)
+    };
+    assert_eq!(brain().generate(&req), GenerateOutcome::Unsatisfied);
+}
+
+#[test]
+fn was_trained_on() {
+    let mut brain = brain();
+    assert!(brain.was_trained_on("rust is a  systems programming language"));
+    assert!(!brain.was_trained_on("rust is a systems programming"));
+
+    brain.forget("rust is a systems programming language");
+    assert!(!brain.was_trained_on("rust is a systems programming language"));
+
+    let mut other = Brain::new("other", 3);
+    other.train("hello world");
+    brain.merge(&other).unwrap();
+    assert!(brain.was_trained_on("hello world"));
+}
+
+#[test]
+fn forget_reverses_train() {
+    let mut brain = brain();
+    assert!(CORPUS.iter().all(|line| brain.forget(line)));
+    assert!(brain.chain.is_empty());
+    assert!(brain.head.is_empty());
+    assert!(brain.lines.is_empty());
+    assert_eq!(brain.generate(&request(1)), GenerateOutcome::EmptyBrain);
+}
+
+#[test]
+fn forget_keeps_shared_links() {
+    let mut brain = brain();
+    brain.forget("rust is a systems programming language");
+
+    let rust = Box::<[u8]>::from(&b"rust"[..]);
+    let systems = Box::<[u8]>::from(&b"systems"[..]);
+    assert!(brain.head.contains(&rust));
+    assert!(brain.chain.contains_key(std::slice::from_ref(&rust)));
+    assert!(!brain.chain.contains_key(std::slice::from_ref(&systems)));
+}
+
+#[test]
+fn forget_untrained_lines() {
+    let counts = |brain: &Brain| {
+        let links = brain.chain.values().flat_map(Set::links);
+        links.map(|link| link.count).sum::<usize>()
+    };
+    let mut brain = brain();
+    let (links, head) = (counts(&brain), brain.head.clone());
+
+    assert!(!brain.forget("rust is a systems programming"));
+    assert_eq!(counts(&brain), links);
+    assert_eq!(brain.head, head);
+    assert_eq!(brain.metadata().lines, CORPUS.len() as u64);
+}
+
+#[test]
+fn forget_keeps_heads_of_other_lines() {
+    let mut brain = Brain::new("test", 3);
+    brain.train("hello");
+    brain.train("say hello");
+
+    let hello = Box::<[u8]>::from(&b"hello"[..]);
+    let say = Box::<[u8]>::from(&b"say"[..]);
+    assert!(brain.forget("say hello"));
+    assert!(brain.head.contains(&hello));
+    assert!(!brain.head.contains(&say));
+
+    assert!(brain.forget("hello"));
+    assert!(brain.head.is_empty());
+}
+
+#[test]
+fn prune() {
+    let mut brain = brain();
+    let total = brain.chain.values().map(Set::size).sum::<usize>();
+    let removed = brain.prune(2);
+
+    assert!(removed > 0);
+    assert_eq!(
+        brain.chain.values().map(Set::size).sum::<usize>(),
+        total - removed
+    );
+    assert!(brain
+        .chain
+        .values()
+        .flat_map(Set::links)
+        .all(|link| link.count >= 2));
+    assert!(brain
+        .head
+        .iter()
+        .all(|word| brain.chain.contains_key(std::slice::from_ref(word))));
+}
+
+#[test]
+fn decay() {
+    let mut brain = brain();
+    brain.train("the borrow checker is my friend");
+    brain.decay(0.5).unwrap();
+    assert!(brain
+        .chain
+        .values()
+        .flat_map(Set::links)
+        .all(|link| link.count >= 1));
+
+    brain.decay(0.0).unwrap();
+    assert!(brain.chain.is_empty());
+    assert!(brain.head.is_empty());
+}
+
+#[test]
+fn decay_rejects_invalid_factors() {
+    let mut brain = brain();
+    for factor in [f64::NAN, f64::INFINITY, -0.5, 1.5] {
+        assert!(matches!(
+            brain.decay(factor),
+            Err(DecayError::InvalidFactor(..))
+        ));
+    }
+    assert!(!brain.chain.is_empty());
+}
+
+#[test]
+fn merge() {
+    let (left, right) = CORPUS.split_at(CORPUS.len() / 2);
+
+    let mut merged = Brain::new("left", 3);
+    left.iter().for_each(|line| merged.train(line));
+
+    let mut other = Brain::new("right", 3);
+    right.iter().for_each(|line| other.train(line));
+
+    merged.merge(&other).unwrap();
+    let mut expected = brain();
+    expected.rename("left");
+    assert_eq!(merged.stats(10), expected.stats(10));
+    assert_eq!(merged.metadata().lines, expected.metadata().lines);
+    assert_eq!(merged.generate(&request(1)), brain().generate(&request(1)));
+}
+
+#[test]
+fn merge_depth() {
+    let mut deep = Brain::new("deep", 5);
+    CORPUS.iter().for_each(|line| deep.train(line));
+
+    let mut shallow = Brain::new("shallow", 3);
+    assert!(shallow.merge(&deep).is_ok());
+    assert!(shallow.chain.keys().all(|k| k.len() <= 3));
+    let mut expected = brain();
+    expected.rename("shallow");
+    assert_eq!(shallow.stats(10), expected.stats(10));
+
+    let mut deep = Brain::new("deep", 5);
+    assert_eq!(
+        deep.merge(&shallow),
+        Err(MergeError::DepthMismatch {
+            expected: 5,
+            found: 3
+        })
+    );
+}
+
+#[test]
+fn stats() {
+    let stats = brain().stats(3);
+    assert_eq!(stats.depth, 3);
+    assert_eq!(stats.heads, 5);
+    assert_eq!(
+        stats.top_words,
+        [("the", 7), ("is", 6), ("friend", 3)].map(|(w, c)| (w.to_string(), c))
+    );
+    assert_eq!(stats.unique_words, 24);
+    assert!(stats.contexts > stats.unique_words);
+    assert!(stats.total_links >= stats.contexts);
+}
+
+#[test]
+fn contexts() {
+    let mut brain = Brain::new("test", 2);
+    brain.train("the stream is live");
+    brain.train("the stream is live");
+    brain.train("the chat is happy");
+
+    let contexts = brain.contexts("is");
+    let shown = contexts.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        shown,
+        [
+            "is -> live (2), happy (1)",
+            "chat is -> happy (1)",
+            "is happy -> <end> (1)",
+            "is live -> <end> (2)",
+            "stream is -> live (2)",
+        ]
+    );
+    assert!(brain.contexts("nope").is_empty());
+}
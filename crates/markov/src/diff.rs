@@ -0,0 +1,155 @@
+use hashbrown::HashMap;
+
+use crate::{Brain, Set};
+
+/// What changed between two brains, from [`Brain::diff`]
+///
+/// Anything 'added' is only in the newer brain, anything 'removed' is only in the older one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BrainDiff {
+    pub words_added: usize,
+    pub words_removed: usize,
+    pub contexts_added: usize,
+    pub contexts_removed: usize,
+    /// Links in contexts both brains have, or every link of an added context
+    pub links_added: usize,
+    pub links_removed: usize,
+    /// Links both brains have, but with different counts
+    pub links_changed: usize,
+    pub heads_added: usize,
+    pub heads_removed: usize,
+    /// Lines the brain remembers being trained on
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    /// The most frequently seen words that were added, with how often they were seen
+    pub top_added: Vec<(String, usize)>,
+    /// The most frequently seen words that were removed, with how often they were seen
+    pub top_removed: Vec<(String, usize)>,
+}
+
+impl BrainDiff {
+    pub(crate) fn between(old: &Brain, new: &Brain, top: usize) -> Self {
+        let (_, old_chain, old_head) = old.parts();
+        let (_, new_chain, new_head) = new.parts();
+
+        let mut diff = Self {
+            heads_added: new_head.difference(old_head).count(),
+            heads_removed: old_head.difference(new_head).count(),
+            lines_added: new.lines().difference(old.lines()).count(),
+            lines_removed: old.lines().difference(new.lines()).count(),
+            ..Self::default()
+        };
+
+        for (context, new_set) in new_chain {
+            match old_chain.get(context) {
+                Some(old_set) => diff.compare(old_set, new_set),
+                None => {
+                    diff.contexts_added += 1;
+                    diff.links_added += new_set.size();
+                }
+            }
+        }
+
+        for (context, old_set) in old_chain {
+            if !new_chain.contains_key(context) {
+                diff.contexts_removed += 1;
+                diff.links_removed += old_set.size();
+            }
+        }
+
+        let (old_words, new_words) = (words(old), words(new));
+        let only = |left: &HashMap<&[u8], usize>, right: &HashMap<&[u8], usize>| {
+            let mut words = left
+                .iter()
+                .filter(|(word, _)| !right.contains_key(*word))
+                .map(|(word, count)| (String::from_utf8_lossy(word).into_owned(), *count))
+                .collect::<Vec<_>>();
+            let len = words.len();
+            words.sort_unstable_by(|(lw, lc), (rw, rc)| rc.cmp(lc).then_with(|| lw.cmp(rw)));
+            words.truncate(top);
+            (len, words)
+        };
+
+        (diff.words_added, diff.top_added) = only(&new_words, &old_words);
+        (diff.words_removed, diff.top_removed) = only(&old_words, &new_words);
+        diff
+    }
+
+    // both sets are sorted by their tokens
+    fn compare(&mut self, old: &Set, new: &Set) {
+        let (mut old, mut new) = (old.links().iter().peekable(), new.links().iter().peekable());
+        loop {
+            match (old.peek(), new.peek()) {
+                (Some(l), Some(r)) => match l.token.cmp(&r.token) {
+                    std::cmp::Ordering::Less => {
+                        self.links_removed += 1;
+                        old.next();
+                    }
+                    std::cmp::Ordering::Greater => {
+                        self.links_added += 1;
+                        new.next();
+                    }
+                    std::cmp::Ordering::Equal => {
+                        self.links_changed += (l.count != r.count) as usize;
+                        old.next();
+                        new.next();
+                    }
+                },
+                (Some(..), None) => {
+                    self.links_removed += old.count();
+                    break;
+                }
+                (None, Some(..)) => {
+                    self.links_added += new.count();
+                    break;
+                }
+                (None, None) => break,
+            }
+        }
+    }
+}
+
+// every word the brain knows, with how often it was seen
+fn words(brain: &Brain) -> HashMap<&[u8], usize> {
+    let (_, chain, head) = brain.parts();
+    let mut words = chain
+        .iter()
+        .filter(|(context, _)| context.len() == 1)
+        .map(|(context, set)| (&*context[0], set.total()))
+        .collect::<HashMap<_, _>>();
+    for word in head {
+        words.entry(&**word).or_insert(0);
+    }
+    words
+}
+
+impl std::fmt::Display for BrainDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "words:    +{} -{}", self.words_added, self.words_removed)?;
+        writeln!(f, "heads:    +{} -{}", self.heads_added, self.heads_removed)?;
+        writeln!(f, "lines:    +{} -{}", self.lines_added, self.lines_removed)?;
+        writeln!(
+            f,
+            "contexts: +{} -{}",
+            self.contexts_added, self.contexts_removed
+        )?;
+        writeln!(
+            f,
+            "links:    +{} -{} ~{}",
+            self.links_added, self.links_removed, self.links_changed
+        )?;
+
+        for (name, words) in [("added", &self.top_added), ("removed", &self.top_removed)] {
+            if !words.is_empty() {
+                writeln!(f, "top {name} words:")?;
+            }
+            for (word, count) in words {
+                writeln!(f, "  {count:>8} {word}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
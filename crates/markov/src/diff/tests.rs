@@ -0,0 +1,46 @@
+use super::*;
+
+fn trained(lines: &[&str]) -> Brain {
+    let mut brain = Brain::new("test", 2);
+    for line in lines {
+        brain.train(line)
+    }
+    brain
+}
+
+#[test]
+fn same() {
+    let brain = trained(&["the stream is live", "the chat is happy"]);
+    assert_eq!(brain.diff(&brain, 5), BrainDiff::default());
+}
+
+#[test]
+fn changes() {
+    let old = trained(&["the stream is live", "the chat is happy"]);
+    let new = trained(&[
+        "the stream is live",
+        "the stream is live",
+        "a chat is happy",
+    ]);
+    let diff = old.diff(&new, 5);
+
+    assert_eq!((diff.words_added, diff.words_removed), (1, 0));
+    assert_eq!(diff.top_added, vec![("a".to_string(), 1)]);
+    assert!(diff.top_removed.is_empty());
+    assert_eq!((diff.heads_added, diff.heads_removed), (1, 0));
+    assert_eq!((diff.lines_added, diff.lines_removed), (1, 1));
+
+    // [a] and [a chat] were added, [the chat] was removed
+    assert_eq!((diff.contexts_added, diff.contexts_removed), (2, 1));
+    // [a] -> chat and [a chat] -> is
+    assert_eq!(diff.links_added, 2);
+    // [the] -> chat and [the chat] -> is
+    assert_eq!(diff.links_removed, 2);
+    // everything along "the stream is live" was seen twice
+    assert_eq!(diff.links_changed, 7);
+
+    let reverse = new.diff(&old, 5);
+    assert_eq!(reverse.words_added, diff.words_removed);
+    assert_eq!(reverse.contexts_removed, diff.contexts_added);
+    assert_eq!(reverse.links_removed, diff.links_added);
+}
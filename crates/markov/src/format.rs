@@ -0,0 +1,401 @@
+//! The on-disk format for a [`Brain`]
+//!
+//! A brain file starts with a small uncompressed header:
+//!
+//! | field       | size     |                                       |
+//! | ----------- | -------- | ------------------------------------- |
+//! | magic       | 4        | `SDB\0`                               |
+//! | version     | 2        | little endian                         |
+//! | compression | 1        | see [`Compression`]                   |
+//! | length      | 4        | little endian length of the metadata  |
+//! | metadata    | `length` | bincode encoded [`Metadata`]          |
+//! | body length | 8        | little endian                         |
+//! | checksum    | 4        | little endian crc32 of the body       |
+//!
+//! followed by the body: the (possibly compressed) bincode encoded brain.
+//!
+//! Version `1` had no body length or checksum, the body directly followed the metadata.
+//! Versions `1` and `2` didn't remember which lines the brain was trained on, brains
+//! loaded from them start without any.
+//!
+//! Files written before the header existed are detected and migrated on load:
+//! `serve_brain` wrote zstd compressed brains and `train_brain` wrote snappy framed brains.
+use std::io::{Read, Write};
+
+use crate::{
+    brain::{Chain, Head, Lines},
+    Brain,
+};
+
+pub const MAGIC: [u8; 4] = *b"SDB\0";
+pub const FORMAT_VERSION: u16 = 3;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const SNAPPY_MAGIC: [u8; 4] = [0xFF, 0x06, 0x00, 0x00];
+
+/// Information about a brain that can be read without decoding the whole brain
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Metadata {
+    pub name: String,
+    pub depth: usize,
+    /// Unix timestamp (in seconds) of when the brain was created, `0` if unknown
+    pub created_at: u64,
+    /// Unix timestamp (in seconds) of when the brain was last trained, `0` if unknown
+    pub trained_at: u64,
+    /// Number of lines the brain has been trained on
+    pub lines: u64,
+}
+
+impl Metadata {
+    pub(crate) fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// How the body of a brain file is compressed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    #[default]
+    Zstd,
+    Snappy,
+}
+
+impl Compression {
+    const fn as_byte(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+            Self::Snappy => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, FormatError> {
+        Ok(match byte {
+            0 => Self::None,
+            1 => Self::Zstd,
+            2 => Self::Snappy,
+            n => return Err(FormatError::UnknownCompression(n)),
+        })
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "none" => Self::None,
+            "zstd" => Self::Zstd,
+            "snappy" => Self::Snappy,
+            s => {
+                return Err(format!(
+                    "unknown compression: {s} (expected none, zstd or snappy)"
+                ))
+            }
+        })
+    }
+}
+
+/// How a loaded brain was encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    /// The current, self-describing format
+    Current {
+        version: u16,
+        compression: Compression,
+    },
+    /// zstd compressed bincode, without a header
+    LegacyZstd,
+    /// snappy framed bincode, without a header
+    LegacySnappy,
+}
+
+impl Encoding {
+    pub const fn is_legacy(&self) -> bool {
+        !matches!(self, Self::Current { .. })
+    }
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    Decode(bincode::Error),
+    UnknownFormat,
+    UnsupportedVersion(u16),
+    UnknownCompression(u8),
+    ChecksumMismatch { expected: u32, found: u32 },
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Decode(err) => write!(f, "cannot decode brain: {err}"),
+            Self::UnknownFormat => f.write_str("not a brain file"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version: {version} (expected at most {FORMAT_VERSION})"
+            ),
+            Self::UnknownCompression(byte) => write!(f, "unknown compression: {byte}"),
+            Self::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {expected:08x}, found {found:08x}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FormatError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<bincode::Error> for FormatError {
+    fn from(err: bincode::Error) -> Self {
+        Self::Decode(err)
+    }
+}
+
+/// Writes `brain` in the current format
+pub fn save(
+    brain: &Brain,
+    writer: impl Write,
+    compression: Compression,
+) -> Result<(), FormatError> {
+    let mut writer = writer;
+
+    let meta = bincode::serialize(brain.metadata())?;
+    let body = encode(brain, compression)?;
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&[compression.as_byte()])?;
+    writer.write_all(&(meta.len() as u32).to_le_bytes())?;
+    writer.write_all(&meta)?;
+    writer.write_all(&(body.len() as u64).to_le_bytes())?;
+    writer.write_all(&crc32fast::hash(&body).to_le_bytes())?;
+    writer.write_all(&body)?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a brain in any known format
+pub fn load(reader: impl Read) -> Result<Brain, FormatError> {
+    load_with_encoding(reader).map(|(brain, _)| brain)
+}
+
+/// Reads a brain in any known format, returning how it was encoded
+pub fn load_with_encoding(reader: impl Read) -> Result<(Brain, Encoding), FormatError> {
+    let mut reader = reader;
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+
+    match magic {
+        MAGIC => {
+            let Header {
+                version,
+                compression,
+                ..
+            } = read_header(&mut reader)?;
+
+            // the first version didn't have a checksum
+            let brain = match version {
+                1 => decode::<Unlined>(reader, compression)?.into(),
+                2 => decode::<Unlined>(&*read_body(&mut reader)?, compression)?.into(),
+                _ => decode(&*read_body(&mut reader)?, compression)?,
+            };
+
+            let encoding = Encoding::Current {
+                version,
+                compression,
+            };
+            Ok((brain, encoding))
+        }
+
+        ZSTD_MAGIC => {
+            let reader = zstd::Decoder::new((&magic[..]).chain(reader))?;
+            let brain = bincode::deserialize_from::<_, Legacy>(reader)?.into();
+            Ok((brain, Encoding::LegacyZstd))
+        }
+
+        SNAPPY_MAGIC => {
+            let reader = snap::read::FrameDecoder::new((&magic[..]).chain(reader));
+            let brain = bincode::deserialize_from::<_, Legacy>(reader)?.into();
+            Ok((brain, Encoding::LegacySnappy))
+        }
+
+        _ => Err(FormatError::UnknownFormat),
+    }
+}
+
+/// Reads only the metadata of a brain
+///
+/// Legacy files have no header, so the whole brain has to be decoded for them.
+pub fn read_metadata(reader: impl Read) -> Result<Metadata, FormatError> {
+    let mut reader = reader;
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+
+    if magic != MAGIC {
+        return load((&magic[..]).chain(reader)).map(|brain| brain.metadata().clone());
+    }
+
+    read_header(&mut reader).map(|header| header.meta)
+}
+
+fn encode(brain: &impl serde::Serialize, compression: Compression) -> Result<Vec<u8>, FormatError> {
+    let mut body = vec![];
+    match compression {
+        Compression::None => bincode::serialize_into(&mut body, brain)?,
+        Compression::Zstd => {
+            let mut enc = zstd::Encoder::new(&mut body, 0)?;
+            bincode::serialize_into(&mut enc, brain)?;
+            enc.finish()?;
+        }
+        Compression::Snappy => {
+            let mut enc = snap::write::FrameEncoder::new(&mut body);
+            bincode::serialize_into(&mut enc, brain)?;
+            enc.flush()?;
+        }
+    }
+    Ok(body)
+}
+
+fn decode<T>(body: impl Read, compression: Compression) -> Result<T, FormatError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    Ok(match compression {
+        Compression::None => bincode::deserialize_from(body)?,
+        Compression::Zstd => bincode::deserialize_from(zstd::Decoder::new(body)?)?,
+        Compression::Snappy => bincode::deserialize_from(snap::read::FrameDecoder::new(body))?,
+    })
+}
+
+struct Header {
+    version: u16,
+    compression: Compression,
+    meta: Metadata,
+}
+
+fn read_header(reader: &mut impl Read) -> Result<Header, FormatError> {
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+
+    let mut compression = [0];
+    reader.read_exact(&mut compression)?;
+    let compression = Compression::from_byte(compression[0])?;
+
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+
+    // like the body, the length isn't trusted for the allocation
+    let mut meta = vec![];
+    reader.take(len.into()).read_to_end(&mut meta)?;
+    if meta.len() != len as usize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    let meta = bincode::deserialize(&meta)?;
+
+    Ok(Header {
+        version,
+        compression,
+        meta,
+    })
+}
+
+fn read_body(reader: &mut impl Read) -> Result<Vec<u8>, FormatError> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+
+    let mut checksum = [0; 4];
+    reader.read_exact(&mut checksum)?;
+    let expected = u32::from_le_bytes(checksum);
+
+    // don't trust the length for the allocation, a corrupt file could claim anything
+    let mut body = vec![];
+    reader.take(len).read_to_end(&mut body)?;
+    if body.len() as u64 != len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let found = crc32fast::hash(&body);
+    if found != expected {
+        return Err(FormatError::ChecksumMismatch { expected, found });
+    }
+
+    Ok(body)
+}
+
+// the layout of a brain before the format had a header
+#[derive(serde::Deserialize)]
+struct Legacy {
+    name: String,
+    depth: usize,
+    chain: Chain,
+    head: Head,
+}
+
+impl From<Legacy> for Brain {
+    fn from(
+        Legacy {
+            name,
+            depth,
+            chain,
+            head,
+        }: Legacy,
+    ) -> Self {
+        let meta = Metadata {
+            name,
+            depth,
+            created_at: 0,
+            trained_at: 0,
+            lines: 0,
+        };
+        Self::from_parts(meta, chain, head, Lines::default())
+    }
+}
+
+// the layout of a brain in versions 1 and 2, before it remembered its lines
+#[derive(serde::Deserialize)]
+struct Unlined {
+    meta: Metadata,
+    chain: Chain,
+    head: Head,
+}
+
+impl From<Unlined> for Brain {
+    fn from(Unlined { meta, chain, head }: Unlined) -> Self {
+        Self::from_parts(meta, chain, head, Lines::default())
+    }
+}
+
+mod file;
+pub use file::{backups, load_file, save_file, SaveOptions};
+pub(crate) use file::{load_with_backups, write_atomic};
+
+pub mod journal;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,185 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{load, save, Compression, FormatError};
+use crate::Brain;
+
+/// How a brain is written to disk
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SaveOptions {
+    pub compression: Compression,
+    /// How many previous versions of the file are kept around
+    pub backups: usize,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::default(),
+            backups: 3,
+        }
+    }
+}
+
+/// Atomically writes `brain` to `path`
+///
+/// The brain is written to a temporary file which is synced and then renamed
+/// over `path`, so a crash while saving leaves the previous file intact.
+///
+/// When backups are enabled the previous file is kept as `<path>.<timestamp>.bak`,
+/// and only the newest `backups` of them are kept.
+pub fn save_file(
+    brain: &Brain,
+    path: impl AsRef<Path>,
+    options: &SaveOptions,
+) -> Result<(), FormatError> {
+    write_atomic(path.as_ref(), options.backups, |writer| {
+        save(brain, writer, options.compression)
+    })
+}
+
+/// Reads a brain from `path`, falling back to the newest valid backup if it can't be read
+pub fn load_file(path: impl AsRef<Path>) -> Result<Brain, FormatError> {
+    load_with_backups(path.as_ref(), load_one)
+}
+
+/// Loads `path` with `load`, falling back to the newest backup it can load
+pub(crate) fn load_with_backups<T>(
+    path: &Path,
+    load: impl Fn(&Path) -> Result<T, FormatError>,
+) -> Result<T, FormatError> {
+    let err = match load(path) {
+        Ok(brain) => return Ok(brain),
+        Err(err) => err,
+    };
+
+    for backup in backups(path) {
+        match load(&backup) {
+            Ok(brain) => {
+                tracing::warn!(?path, %err, ?backup, "cannot load brain, using a backup");
+                return Ok(brain);
+            }
+            Err(err) => tracing::warn!(?backup, %err, "cannot load backup"),
+        }
+    }
+
+    Err(err)
+}
+
+/// The backups of `path`, newest first
+pub fn backups(path: impl AsRef<Path>) -> Vec<PathBuf> {
+    let path = path.as_ref();
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+        _ => return vec![],
+    };
+
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(..) => return vec![],
+    };
+
+    let mut backups = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let timestamp = file_name
+                .to_str()?
+                .strip_prefix(&*name)?
+                .strip_prefix('.')?
+                .strip_suffix(".bak")?
+                .parse::<u128>()
+                .ok()?;
+            Some((timestamp, entry.path()))
+        })
+        .collect::<Vec<_>>();
+
+    backups.sort_unstable_by(|(left, _), (right, _)| right.cmp(left));
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+fn load_one(path: &Path) -> Result<Brain, FormatError> {
+    load(BufReader::new(File::open(path)?))
+}
+
+/// Atomically replaces `path` with whatever `write` writes, keeping `backups` previous versions
+pub(crate) fn write_atomic(
+    path: &Path,
+    backups: usize,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), FormatError>,
+) -> Result<(), FormatError> {
+    let temp = sibling(path, "tmp");
+
+    if let Err(err) = write_synced(&temp, write) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err);
+    }
+
+    if backups > 0 && path.exists() {
+        backup(path)?;
+    }
+
+    std::fs::rename(&temp, path)?;
+    sync_parent(path);
+
+    if backups > 0 {
+        for old in self::backups(path).into_iter().skip(backups) {
+            let _ = std::fs::remove_file(old);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_synced(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), FormatError>,
+) -> Result<(), FormatError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+    Ok(())
+}
+
+fn backup(path: &Path) -> Result<(), FormatError> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    let backup = sibling(path, &format!("{timestamp}.bak"));
+    // a hard link is free, but not every filesystem supports them
+    if std::fs::hard_link(path, &backup).is_err() {
+        std::fs::copy(path, &backup)?;
+    }
+    Ok(())
+}
+
+// the rename has to be synced as well, otherwise a crash could still lose it
+fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
@@ -0,0 +1,287 @@
+//! A write-ahead journal of the lines a brain was trained on (or forgot) since its last save
+//!
+//! | field    | size     |                                     |
+//! | -------- | -------- | ----------------------------------- |
+//! | magic    | 4        | `SDBJ`                              |
+//! | version  | 2        | little endian                       |
+//! | length   | 4        | little endian length of the metadata |
+//! | metadata | `length` | bincode encoded [`Metadata`] of the saved brain |
+//!
+//! followed by entries:
+//!
+//! | field    | size     |                                    |
+//! | -------- | -------- | ---------------------------------- |
+//! | kind     | 1        | `0` for train, `1` for forget      |
+//! | length   | 4        | little endian length of the line   |
+//! | checksum | 4        | little endian crc32 of the kind and line |
+//! | line     | `length` | utf-8                              |
+//!
+//! The metadata ties the journal to the saved brain it applies to. A journal
+//! for any other brain (e.g. when the process stopped after the brain was saved,
+//! but before the journal was reset) is discarded instead of replayed twice.
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{write_atomic, FormatError};
+use crate::Metadata;
+
+pub const MAGIC: [u8; 4] = *b"SDBJ";
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Something that happened to a brain after it was saved
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Entry<'a> {
+    Train(&'a str),
+    Forget(&'a str),
+}
+
+impl<'a> Entry<'a> {
+    const fn kind(&self) -> u8 {
+        match self {
+            Self::Train(..) => 0,
+            Self::Forget(..) => 1,
+        }
+    }
+
+    const fn line(&self) -> &'a str {
+        match self {
+            Self::Train(line) | Self::Forget(line) => line,
+        }
+    }
+}
+
+/// An append-only journal for a brain
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    entries: usize,
+}
+
+impl Journal {
+    /// The journal for the brain saved at `brain`: `<brain>.journal`
+    ///
+    /// The whole file name is kept, so `foo.sdb` and `foo.sdbm` have their own journals.
+    pub fn path_for(brain: impl AsRef<Path>) -> PathBuf {
+        super::file::sibling(brain.as_ref(), "journal")
+    }
+
+    /// Moves the journal of the brain saved at `brain` from where it used to be kept
+    ///
+    /// Journals used to replace the extension of the brain (`foo.journal`). One is
+    /// only moved if it belongs to the brain described by `meta`, and there's no
+    /// journal at the [current path](Self::path_for) yet.
+    pub fn migrate(brain: impl AsRef<Path>, meta: &Metadata) -> Result<(), FormatError> {
+        let (brain, path) = (brain.as_ref(), Self::path_for(brain.as_ref()));
+        let legacy = brain.with_extension("journal");
+        if legacy == path || path.exists() {
+            return Ok(());
+        }
+
+        let data = match std::fs::read(&legacy) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        if matches!(parse_header(&data), Ok((journal, ..)) if journal == *meta) {
+            tracing::info!(?legacy, ?path, "moving a journal");
+            std::fs::rename(&legacy, &path)?;
+        }
+        Ok(())
+    }
+
+    /// Opens the journal at `path`, replaying its entries through `apply`
+    ///
+    /// Entries are only replayed if the journal belongs to the brain described by `meta`,
+    /// otherwise (or if there is no journal) a new one is started. A partially
+    /// written entry at the end (e.g. from a crash) is dropped.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        meta: &Metadata,
+        mut apply: impl FnMut(Entry<'_>),
+    ) -> Result<Self, FormatError> {
+        let path = path.into();
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Self::create(path, meta)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut entries = match parse_header(&data) {
+            Ok((journal, entries)) if journal == *meta => entries,
+            Ok((journal, ..)) => {
+                tracing::warn!(
+                    ?path,
+                    ?journal,
+                    ?meta,
+                    "discarding a journal for another brain"
+                );
+                return Self::create(path, meta);
+            }
+            Err(err) => {
+                tracing::warn!(?path, %err, "discarding an unreadable journal");
+                return Self::create(path, meta);
+            }
+        };
+
+        let header = data.len() - entries.len();
+        let mut valid = header;
+        let mut count = 0;
+        while let Some((entry, len)) = parse_entry(entries) {
+            apply(entry);
+            entries = &entries[len..];
+            valid += len;
+            count += 1;
+        }
+
+        if valid < data.len() {
+            tracing::warn!(
+                ?path,
+                dropped = data.len() - valid,
+                "dropping a partial journal entry"
+            );
+        }
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        file.set_len(valid as u64)?;
+        Ok(Self {
+            path,
+            file,
+            entries: count,
+        })
+    }
+
+    /// Starts a new, empty journal for the brain described by `meta`
+    pub fn create(path: impl Into<PathBuf>, meta: &Metadata) -> Result<Self, FormatError> {
+        let path = path.into();
+        let header = header(meta)?;
+        write_atomic(&path, 0, |writer| Ok(writer.write_all(&header)?))?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            entries: 0,
+        })
+    }
+
+    /// Durably appends `entries` to the journal
+    pub fn append<'a>(
+        &mut self,
+        entries: impl IntoIterator<Item = Entry<'a>>,
+    ) -> Result<(), FormatError> {
+        let mut data = vec![];
+        let mut count = 0;
+        for entry in entries {
+            encode_entry(entry, &mut data);
+            count += 1;
+        }
+
+        if count == 0 {
+            return Ok(());
+        }
+
+        self.file.write_all(&data)?;
+        self.file.sync_data()?;
+        self.entries += count;
+        Ok(())
+    }
+
+    /// Empties the journal once the brain described by `meta` has been saved
+    pub fn reset(&mut self, meta: &Metadata) -> Result<(), FormatError> {
+        *self = Self::create(std::mem::take(&mut self.path), meta)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How many entries have been written since the brain was saved
+    pub const fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+}
+
+fn header(meta: &Metadata) -> Result<Vec<u8>, FormatError> {
+    let meta = bincode::serialize(meta)?;
+    let mut data = Vec::with_capacity(10 + meta.len());
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    data.extend_from_slice(&meta);
+    Ok(data)
+}
+
+// returns the metadata and the entries after the header
+fn parse_header(data: &[u8]) -> Result<(Metadata, &[u8]), FormatError> {
+    let mut reader = data;
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(FormatError::UnknownFormat);
+    }
+
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if reader.len() < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let (meta, entries) = reader.split_at(len);
+    Ok((bincode::deserialize(meta)?, entries))
+}
+
+fn encode_entry(entry: Entry<'_>, out: &mut Vec<u8>) {
+    let line = entry.line().as_bytes();
+    out.push(entry.kind());
+    out.extend_from_slice(&(line.len() as u32).to_le_bytes());
+    out.extend_from_slice(&checksum(entry.kind(), line).to_le_bytes());
+    out.extend_from_slice(line);
+}
+
+// returns the entry and how many bytes it took up, or nothing if it's incomplete or corrupt
+fn parse_entry(data: &[u8]) -> Option<(Entry<'_>, usize)> {
+    let (&kind, rest) = data.split_first()?;
+    let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    let expected = u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?);
+    let line = rest.get(8..8 + len)?;
+
+    if checksum(kind, line) != expected {
+        return None;
+    }
+
+    let line = std::str::from_utf8(line).ok()?;
+    let entry = match kind {
+        0 => Entry::Train(line),
+        1 => Entry::Forget(line),
+        _ => return None,
+    };
+    Some((entry, 9 + len))
+}
+
+fn checksum(kind: u8, line: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[kind]);
+    hasher.update(line);
+    hasher.finalize()
+}
@@ -0,0 +1,348 @@
+use super::*;
+use crate::GenerateRequest;
+
+fn brain() -> Brain {
+    let mut brain = Brain::new("test", 3);
+    for line in [
+        "the borrow checker is my friend",
+        "rust makes the compiler my friend",
+        "the stream is live and the chat is happy",
+    ] {
+        brain.train(line)
+    }
+    brain
+}
+
+fn assert_same(left: &Brain, right: &Brain) {
+    // older formats don't remember the lines, so they use less memory
+    let stats = |brain: &Brain| crate::BrainStats {
+        lines: 0,
+        memory: 0,
+        ..brain.stats(10)
+    };
+    assert_eq!(stats(left), stats(right));
+    for seed in 0..8 {
+        let req = GenerateRequest {
+            seed: Some(seed),
+            ..GenerateRequest::default()
+        };
+        assert_eq!(left.generate(&req), right.generate(&req));
+    }
+}
+
+// what serve_brain and train_brain used to write
+fn legacy(brain: &Brain) -> Vec<u8> {
+    #[derive(serde::Serialize)]
+    struct Legacy<'a> {
+        name: &'a str,
+        depth: usize,
+        chain: &'a Chain,
+        head: &'a Head,
+    }
+
+    let (meta, chain, head) = brain.parts();
+    bincode::serialize(&Legacy {
+        name: &meta.name,
+        depth: meta.depth,
+        chain,
+        head,
+    })
+    .unwrap()
+}
+
+// what versions 1 and 2 wrote as the body
+fn unlined(brain: &Brain) -> Vec<u8> {
+    #[derive(serde::Serialize)]
+    struct Unlined<'a> {
+        meta: &'a Metadata,
+        chain: &'a Chain,
+        head: &'a Head,
+    }
+
+    let (meta, chain, head) = brain.parts();
+    encode(&Unlined { meta, chain, head }, Compression::Zstd).unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let brain = brain();
+    for compression in [Compression::None, Compression::Zstd, Compression::Snappy] {
+        let mut data = vec![];
+        save(&brain, &mut data, compression).unwrap();
+        assert_eq!(data[..4], MAGIC);
+
+        let (loaded, encoding) = load_with_encoding(&*data).unwrap();
+        assert_eq!(
+            encoding,
+            Encoding::Current {
+                version: FORMAT_VERSION,
+                compression
+            }
+        );
+        assert_eq!(loaded.metadata(), brain.metadata());
+        assert!(loaded.was_trained_on("the borrow checker is my friend"));
+        assert_same(&brain, &loaded);
+    }
+}
+
+#[test]
+fn metadata() {
+    let brain = brain();
+    let mut data = vec![];
+    save(&brain, &mut data, Compression::Zstd).unwrap();
+
+    let meta = read_metadata(&*data).unwrap();
+    assert_eq!(meta.name, "test");
+    assert_eq!(meta.depth, 3);
+    assert_eq!(meta.lines, 3);
+    assert!(meta.created_at > 0);
+    assert!(meta.trained_at >= meta.created_at);
+}
+
+#[test]
+fn legacy_zstd() {
+    let brain = brain();
+    let data = zstd::encode_all(&*legacy(&brain), 0).unwrap();
+
+    let (loaded, encoding) = load_with_encoding(&*data).unwrap();
+    assert_eq!(encoding, Encoding::LegacyZstd);
+    assert_eq!(loaded.name(), "test");
+    assert_eq!(loaded.metadata().lines, 0);
+    assert_same(&brain, &loaded);
+}
+
+#[test]
+fn legacy_snappy() {
+    let brain = brain();
+    let mut enc = snap::write::FrameEncoder::new(vec![]);
+    enc.write_all(&legacy(&brain)).unwrap();
+    let data = enc.into_inner().unwrap();
+
+    let (loaded, encoding) = load_with_encoding(&*data).unwrap();
+    assert_eq!(encoding, Encoding::LegacySnappy);
+    assert_same(&brain, &loaded);
+}
+
+#[test]
+fn invalid() {
+    assert!(matches!(
+        load(&b"not a brain"[..]),
+        Err(FormatError::UnknownFormat)
+    ));
+
+    let mut data = vec![];
+    save(&brain(), &mut data, Compression::None).unwrap();
+    data[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        load(&*data),
+        Err(FormatError::UnsupportedVersion(..))
+    ));
+
+    data[4..6].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data[6] = 42;
+    assert!(matches!(
+        load(&*data),
+        Err(FormatError::UnknownCompression(42))
+    ));
+
+    // a length that claims more metadata than there is
+    data[6] = Compression::None.as_byte();
+    data[7..11].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(load(&*data), Err(FormatError::Io(..))));
+}
+
+#[test]
+fn checksum() {
+    let mut data = vec![];
+    save(&brain(), &mut data, Compression::None).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xFF;
+    assert!(matches!(
+        load(&*data),
+        Err(FormatError::ChecksumMismatch { .. })
+    ));
+
+    data.truncate(last);
+    assert!(matches!(load(&*data), Err(FormatError::Io(..))));
+}
+
+#[test]
+fn version_1() {
+    let brain = brain();
+    let meta = bincode::serialize(brain.metadata()).unwrap();
+
+    let mut data = vec![];
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&1_u16.to_le_bytes());
+    data.push(Compression::Zstd.as_byte());
+    data.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    data.extend_from_slice(&meta);
+    data.extend(unlined(&brain));
+
+    let (loaded, encoding) = load_with_encoding(&*data).unwrap();
+    assert_eq!(
+        encoding,
+        Encoding::Current {
+            version: 1,
+            compression: Compression::Zstd
+        }
+    );
+    assert!(!loaded.was_trained_on("the borrow checker is my friend"));
+    assert_same(&brain, &loaded);
+}
+
+#[test]
+fn version_2() {
+    let brain = brain();
+    let meta = bincode::serialize(brain.metadata()).unwrap();
+    let body = unlined(&brain);
+
+    let mut data = vec![];
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&2_u16.to_le_bytes());
+    data.push(Compression::Zstd.as_byte());
+    data.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    data.extend_from_slice(&meta);
+    data.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    data.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    data.extend(body);
+
+    let (loaded, encoding) = load_with_encoding(&*data).unwrap();
+    assert_eq!(
+        encoding,
+        Encoding::Current {
+            version: 2,
+            compression: Compression::Zstd
+        }
+    );
+    assert_eq!(loaded.metadata(), brain.metadata());
+    assert!(!loaded.was_trained_on("the borrow checker is my friend"));
+    assert_same(&brain, &loaded);
+}
+
+#[test]
+fn backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.sdb");
+    let options = SaveOptions {
+        backups: 2,
+        ..SaveOptions::default()
+    };
+
+    let mut brain = brain();
+    for line in ["one more line", "and another line", "and the last line"] {
+        save_file(&brain, &path, &options).unwrap();
+        brain.train(line);
+        // backups are named by the millisecond
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    save_file(&brain, &path, &options).unwrap();
+
+    assert!(!dir.path().join("test.sdb.tmp").exists());
+    let found = super::backups(&path);
+    assert_eq!(found.len(), 2);
+    assert_eq!(load_file(&found[0]).unwrap().metadata().lines, 5);
+    assert_eq!(load_file(&found[1]).unwrap().metadata().lines, 4);
+    assert_same(&brain, &load_file(&path).unwrap());
+
+    // a corrupt primary falls back to the newest backup
+    std::fs::write(&path, b"SDB\0garbage").unwrap();
+    assert_eq!(load_file(&path).unwrap().metadata().lines, 5);
+
+    // without any valid backup the primary's error is returned
+    for backup in found {
+        std::fs::remove_file(backup).unwrap();
+    }
+    assert!(load_file(&path).is_err());
+}
+
+#[test]
+fn journal() {
+    use journal::{Entry, Journal};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = Journal::path_for(dir.path().join("test.sdb"));
+    assert_eq!(path, dir.path().join("test.sdb.journal"));
+    assert_ne!(path, Journal::path_for(dir.path().join("test.sdbm")));
+
+    let mut brain = brain();
+    let meta = brain.metadata().clone();
+
+    let mut journal = Journal::open(&path, &meta, |_| panic!("nothing to replay")).unwrap();
+    journal
+        .append([
+            Entry::Train("one more line"),
+            Entry::Train("and another line"),
+        ])
+        .unwrap();
+    journal.append([Entry::Forget("one more line")]).unwrap();
+    assert_eq!(journal.len(), 3);
+    drop(journal);
+
+    // a crash in the middle of an append
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&[0, 42, 0]).unwrap();
+    drop(file);
+
+    let mut replayed = vec![];
+    let mut journal = Journal::open(&path, &meta, |entry| match entry {
+        Entry::Train(line) => {
+            brain.train(line);
+            replayed.push(line.to_string())
+        }
+        Entry::Forget(line) => {
+            brain.forget(line);
+        }
+    })
+    .unwrap();
+    assert_eq!(replayed, ["one more line", "and another line"]);
+    assert_eq!(journal.len(), 3);
+    assert_eq!(brain.metadata().lines, 4);
+
+    // the partial entry was dropped, so appending still works
+    journal.append([Entry::Train("the last line")]).unwrap();
+    drop(journal);
+    let mut count = 0;
+    Journal::open(&path, &meta, |_| count += 1).unwrap();
+    assert_eq!(count, 4);
+
+    // after the brain is saved, the old journal no longer applies
+    let mut journal = Journal::open(&path, brain.metadata(), |_| panic!("wrong brain")).unwrap();
+    assert!(journal.is_empty());
+
+    journal.append([Entry::Train("one more line")]).unwrap();
+    journal.reset(brain.metadata()).unwrap();
+    assert!(journal.is_empty());
+    Journal::open(&path, brain.metadata(), |_| panic!("journal was reset")).unwrap();
+}
+
+#[test]
+fn migrate_journal() {
+    use journal::{Entry, Journal};
+
+    let dir = tempfile::tempdir().unwrap();
+    let (brain, other) = (dir.path().join("test.sdb"), dir.path().join("test.sdbm"));
+    let legacy = dir.path().join("test.journal");
+    let meta = self::brain().metadata().clone();
+
+    let mut journal = Journal::create(&legacy, &meta).unwrap();
+    journal.append([Entry::Train("one more line")]).unwrap();
+    drop(journal);
+
+    // a journal for another brain is left alone
+    let mut different = meta.clone();
+    different.name = "another brain".into();
+    Journal::migrate(&other, &different).unwrap();
+    assert!(legacy.exists());
+    assert!(!Journal::path_for(&other).exists());
+
+    Journal::migrate(&brain, &meta).unwrap();
+    assert!(!legacy.exists());
+
+    let mut count = 0;
+    Journal::open(Journal::path_for(&brain), &meta, |_| count += 1).unwrap();
+    assert_eq!(count, 1);
+}
@@ -0,0 +1,370 @@
+use std::time::{Duration, Instant};
+
+use crate::{brain::fingerprint, sampling::Candidates, token::TokenRef, SamplingConfig, Word};
+
+/// Everything needed to generate a sentence from a [`Brain`](crate::Brain)
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateRequest {
+    /// The minimum number of words
+    pub min: usize,
+    /// The maximum number of words
+    pub max: usize,
+    /// Words to weave into the output
+    pub query: Option<String>,
+    /// Seed for the rng. The same seed on the same brain produces the same output
+    pub seed: Option<u64>,
+    /// How long to try before giving up
+    pub timeout: Duration,
+    /// How the next word is picked
+    pub sampling: SamplingConfig,
+    /// Words the output must contain, woven in like the query
+    pub required: Vec<String>,
+    /// Words the output must not contain
+    pub forbidden: Vec<String>,
+    /// Words that are never picked while generating
+    pub blocklist: Vec<String>,
+    /// The maximum number of characters, words that don't fit are dropped from the end
+    pub max_chars: Option<usize>,
+    /// How many more times to try when a sentence doesn't meet these options,
+    /// or repeats a line the brain was trained on
+    ///
+    /// Repeating a line is only avoided when this is more than `0`, if every try
+    /// repeats one the first is used.
+    pub retries: usize,
+    /// Whether the output must end where a trained line ended
+    pub sentence_end: bool,
+}
+
+impl Default for GenerateRequest {
+    fn default() -> Self {
+        Self {
+            min: 3,
+            max: 5,
+            query: None,
+            seed: None,
+            timeout: Duration::from_secs(5),
+            sampling: SamplingConfig::default(),
+            required: Vec::new(),
+            forbidden: Vec::new(),
+            blocklist: Vec::new(),
+            max_chars: None,
+            retries: 0,
+            sentence_end: false,
+        }
+    }
+}
+
+impl GenerateRequest {
+    pub(crate) fn rng(&self) -> fastrand::Rng {
+        self.seed.map(fastrand::Rng::with_seed).unwrap_or_default()
+    }
+}
+
+/// The result of [`Brain::generate`](crate::Brain::generate)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerateOutcome {
+    /// A sentence was generated
+    Generated(String),
+    /// The brain hasn't been trained on anything
+    EmptyBrain,
+    /// The timeout was reached before enough words were generated
+    TimedOut,
+    /// None of the query words have been seen by the brain
+    UnknownQuery,
+    /// No sentence met the request's options, even after retrying
+    Unsatisfied,
+}
+
+impl GenerateOutcome {
+    pub const fn is_generated(&self) -> bool {
+        matches!(self, Self::Generated(..))
+    }
+
+    pub fn into_option(self) -> Option<String> {
+        match self {
+            Self::Generated(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for GenerateOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Generated(data) => f.write_str(data),
+            Self::EmptyBrain => f.write_str("the brain is empty"),
+            Self::TimedOut => f.write_str("generation timed out"),
+            Self::UnknownQuery => f.write_str("the query contains no known words"),
+            Self::Unsatisfied => f.write_str("no sentence met the options"),
+        }
+    }
+}
+
+/// Something a sentence can be generated from
+pub(crate) trait Source {
+    fn depth(&self) -> usize;
+
+    /// How many words can start a sentence
+    fn heads(&self) -> usize;
+
+    /// The starting word at `index`, this must be stable for seeded generation
+    fn head(&self, index: usize) -> &[u8];
+
+    /// Whether the word has been seen at all
+    fn knows(&self, word: &[u8]) -> bool;
+
+    /// Whether a line with this [`fingerprint`] was trained
+    fn trained_on(&self, fingerprint: u64) -> bool;
+
+    /// Merges the links that follow `context` into `candidates`
+    fn candidates<'a>(&'a self, context: &[Word], weight: f64, candidates: &mut Candidates<'a>);
+}
+
+pub(crate) fn generate(source: &impl Source, req: &GenerateRequest) -> GenerateOutcome {
+    if source.heads() == 0 {
+        return GenerateOutcome::EmptyBrain;
+    }
+
+    let query = base_words(req.query.as_deref());
+    if !query.is_empty() && !query.iter().any(|word| source.knows(word)) {
+        return GenerateOutcome::UnknownQuery;
+    }
+
+    let rng = &req.rng();
+    let now = Instant::now();
+
+    // the first sentence that only repeated a trained line
+    let mut repeated = None;
+    for _ in 0..=req.retries {
+        let sentence = match attempt(source, req, rng, now) {
+            Some(sentence) => sentence,
+            None => return repeated.map_or(GenerateOutcome::TimedOut, GenerateOutcome::Generated),
+        };
+
+        if !sentence.satisfies(req) {
+            continue;
+        }
+
+        if req.retries > 0 && source.trained_on(sentence.fingerprint()) {
+            repeated.get_or_insert(sentence.data);
+            continue;
+        }
+
+        return GenerateOutcome::Generated(sentence.data);
+    }
+
+    repeated.map_or(GenerateOutcome::Unsatisfied, GenerateOutcome::Generated)
+}
+
+struct Sentence {
+    data: String,
+    words: usize,
+    // whether it stopped where a trained line did
+    ended: bool,
+}
+
+impl Sentence {
+    fn satisfies(&self, req: &GenerateRequest) -> bool {
+        let contains = |word: &str| self.data.split(' ').any(|w| matches(w.as_bytes(), word));
+
+        self.words >= req.min
+            && (self.ended || !req.sentence_end)
+            && req.required.iter().all(|word| contains(word))
+            && !req.forbidden.iter().any(|word| contains(word))
+    }
+
+    fn fingerprint(&self) -> u64 {
+        fingerprint(self.data.split(' ').map(str::as_bytes))
+    }
+}
+
+// a single try at a sentence, `None` if it timed out
+fn attempt(
+    source: &impl Source,
+    req: &GenerateRequest,
+    rng: &fastrand::Rng,
+    now: Instant,
+) -> Option<Sentence> {
+    let (min, max) = (req.min, req.max);
+
+    let mut base = base_words(req.query.as_deref());
+    base.extend(req.required.iter().map(|word| word.bytes().collect()));
+
+    let mut words = <Vec<Word>>::new();
+    let mut indices = Adjacent::new();
+    rng.shuffle(&mut base);
+
+    // somewhere to insert a base word that isn't next to another one, if there's any left
+    let mut pick = |max: usize| {
+        let slots = if max == 1 { 1..2 } else { 1..max };
+        if slots.clone().all(|t| indices.is_taken(t)) {
+            return None;
+        }
+
+        loop {
+            let t = if max == 1 {
+                max
+            } else {
+                rng.usize(slots.clone())
+            };
+            if indices.create_adjacency(t) {
+                break Some(t);
+            }
+        }
+    };
+
+    let mut choose = |words: &mut Vec<Word>| {
+        if !base.is_empty() && words.len() > 1 && rng.f64() > rng.f64() {
+            if let Some(n) = pick(words.len()) {
+                let next = base.pop().unwrap();
+                words.insert(n, next) // TODO this would be better as a linked list
+            }
+        }
+    };
+
+    let mut ended = false;
+    'outer: loop {
+        if now.elapsed() > req.timeout {
+            return None;
+        }
+
+        if words.len() >= min {
+            break;
+        }
+
+        choose(&mut words);
+        let head = source.head(rng.usize(0..source.heads()));
+        if blocked(req, head) {
+            continue;
+        }
+
+        words.push(head.into());
+        ended = false;
+        if words.len() >= max {
+            break;
+        }
+
+        while let Some(word) = select_word(source, rng, req, context(&words, source.depth())) {
+            choose(&mut words);
+            words.push(word);
+            if words.len() >= max {
+                break 'outer;
+            }
+        }
+        ended = true;
+    }
+
+    while let Some(word) = base.pop() {
+        if now.elapsed() > req.timeout {
+            return None;
+        }
+
+        // once every slot is taken, the rest go on the end
+        match pick(words.len()) {
+            Some(n) => words.insert(n, word),
+            None => words.push(word),
+        }
+    }
+
+    let capacity = words.iter().map(|s| s.len() + 1).sum();
+    let mut out = String::with_capacity(capacity);
+    let (mut count, mut chars) = (0, 0);
+    ended &= words.len() <= max;
+
+    for word in words.into_iter().take(max) {
+        if let Ok(word) = std::str::from_utf8(&word) {
+            let len = word.chars().count() + usize::from(!out.is_empty());
+            if req.max_chars.is_some_and(|max| chars + len > max) {
+                ended = false;
+                break;
+            }
+
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(word);
+            count += 1;
+            chars += len;
+        }
+    }
+
+    out.shrink_to_fit();
+    Some(Sentence {
+        data: out,
+        words: count,
+        ended,
+    })
+}
+
+// `None` is the end of the sentence
+fn select_word(
+    source: &impl Source,
+    rng: &fastrand::Rng,
+    req: &GenerateRequest,
+    context: &[Word],
+) -> Option<Word> {
+    let sampling = &req.sampling;
+    let mut candidates = Candidates::default();
+    for width in sampling.widths(source.depth(), context.len()) {
+        let weight = sampling.backoff.weight(width);
+        source.candidates(&context[context.len() - width..], weight, &mut candidates)
+    }
+
+    if !req.blocklist.is_empty() {
+        candidates.block(|word| blocked(req, word));
+    }
+
+    match candidates.select(rng, sampling)? {
+        TokenRef::Word(word) => Some(word.into()),
+        TokenRef::End => None,
+    }
+}
+
+fn blocked(req: &GenerateRequest, word: &[u8]) -> bool {
+    req.blocklist.iter().any(|blocked| matches(word, blocked))
+}
+
+// words are compared ignoring ascii case
+fn matches(word: &[u8], other: &str) -> bool {
+    word.eq_ignore_ascii_case(other.as_bytes())
+}
+
+fn base_words(input: Option<&str>) -> Vec<Word> {
+    input
+        .map(|data| {
+            data.split_ascii_whitespace()
+                .map(|s| s.bytes().collect())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[inline(always)]
+fn context(words: &[Word], depth: usize) -> &[Word] {
+    &words[words.len().saturating_sub(depth)..]
+}
+
+struct Adjacent(Vec<usize>);
+impl Adjacent {
+    const fn new() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl Adjacent {
+    fn is_taken(&self, index: usize) -> bool {
+        self.0.contains(&index)
+    }
+
+    fn create_adjacency(&mut self, index: usize) -> bool {
+        if self.is_taken(index) {
+            return false;
+        }
+
+        self.0.reserve(0);
+        for index in [index.saturating_sub(1), index, index + 1] {
+            self.0.push(index)
+        }
+        true
+    }
+}
@@ -7,7 +7,25 @@ pub use link::Link;
 mod set;
 pub use set::Set;
 
+mod sampling;
+pub use sampling::{Backoff, SamplingConfig};
+
+mod generate;
+pub use generate::{GenerateOutcome, GenerateRequest};
+
+mod stats;
+pub use stats::{BrainStats, ContextLinks};
+
+pub mod format;
+pub use format::Metadata;
+
 mod brain;
-pub use brain::Brain;
+pub use brain::{Brain, DecayError, MergeError};
+
+mod diff;
+pub use diff::BrainDiff;
+
+pub mod mapped;
+pub use mapped::{FrozenBrain, MappedBrain};
 
-pub(self) type Word = Box<[u8]>;
+type Word = Box<[u8]>;
@@ -30,7 +30,7 @@ impl Link {
 impl PartialOrd for Link {
     #[inline(always)]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.count.partial_cmp(&other.count)
+        Some(self.cmp(other))
     }
 }
 
@@ -0,0 +1,710 @@
+//! A read-only layout of a [`Brain`] that is memory-mapped and queried in place
+//!
+//! Nothing has to be decoded before generating, so opening a large brain is
+//! nearly instant. [`MappedBrain`] buffers training in an in-memory [`Brain`],
+//! which is periodically [compacted](MappedBrain::compact) into a new file.
+//!
+//! Every integer is little endian:
+//!
+//! | field    | size           |                                                           |
+//! | -------- | -------------- | --------------------------------------------------------- |
+//! | magic    | 4              | `SDBM`                                                    |
+//! | version  | 2              |                                                           |
+//! | length   | 4              | length of the metadata                                    |
+//! | metadata | `length`       | bincode encoded [`Metadata`]                              |
+//! | counts   | 40             | heads, contexts, links, lines and the length of the words |
+//! | checksum | 4              | crc32 of the tables and the words                         |
+//! | heads    | 12 per head    | word offset (8), word length (4)                          |
+//! | contexts | 24 per context | key offset (8), key length (4), first link (8), links (4) |
+//! | links    | 20 per link    | word offset (8), word length (4), count (8)               |
+//! | lines    | 8 per line     | the fingerprint of a line the brain was trained on        |
+//! | words    | words length   | every unique word and key                                 |
+//!
+//! Offsets are relative to the start of the words. Heads are sorted by word and
+//! contexts by key, where a key is the words of the context joined by a space
+//! (which can't appear in a word). The links of a context are sorted by token,
+//! the end of a sentence has a word length of `u32::MAX`. Lines are sorted.
+//!
+//! Version `1` had no lines, so its counts were only 32 bytes. Neither it nor
+//! version `2` had a checksum.
+use std::{cmp::Ordering, io::Write, path::Path};
+
+use hashbrown::HashMap;
+
+use crate::{
+    brain,
+    format::{self, FormatError},
+    generate::{self, Source},
+    sampling::Candidates,
+    token::TokenRef,
+    Brain, GenerateOutcome, GenerateRequest, Link, Metadata, Set, Token, Word,
+};
+
+pub const MAGIC: [u8; 4] = *b"SDBM";
+pub const FORMAT_VERSION: u16 = 3;
+
+const HEAD: usize = 12;
+const CONTEXT: usize = 24;
+const LINK: usize = 20;
+const LINE: usize = 8;
+const END: u32 = u32::MAX;
+
+/// A read-only brain, queried directly from its (usually memory-mapped) file
+pub struct FrozenBrain {
+    meta: Metadata,
+    data: Storage,
+    heads: Table,
+    contexts: Table,
+    links: Table,
+    lines: Table,
+    words: usize,
+}
+
+impl FrozenBrain {
+    /// Memory-maps the file at `path`
+    ///
+    /// The file must not be modified while it's mapped, only replaced.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: brain files are replaced by a rename, they're never modified in place
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Self::parse(Storage::Mapped(map))
+    }
+
+    /// Memory-maps the file at `path`, falling back to the newest backup that can be opened
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        format::load_with_backups(path.as_ref(), |path| Self::open(path))
+    }
+
+    /// Reads a brain that was [written](Self::write) into memory
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FormatError> {
+        Self::parse(Storage::Owned(data))
+    }
+
+    /// Writes `brain` in the mapped layout
+    pub fn write(brain: &Brain, writer: impl Write) -> Result<(), FormatError> {
+        let mut writer = writer;
+        write(None, brain, brain.metadata(), &mut writer)
+    }
+
+    /// Atomically writes `brain` to `path` in the mapped layout, keeping `backups` previous versions
+    pub fn save_file(
+        brain: &Brain,
+        path: impl AsRef<Path>,
+        backups: usize,
+    ) -> Result<(), FormatError> {
+        format::write_atomic(path.as_ref(), backups, |writer| Self::write(brain, writer))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.meta.name
+    }
+
+    pub const fn depth(&self) -> usize {
+        self.meta.depth
+    }
+
+    pub const fn metadata(&self) -> &Metadata {
+        &self.meta
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn generate(&self, req: &GenerateRequest) -> GenerateOutcome {
+        generate::generate(self, req)
+    }
+
+    /// Whether the brain was trained on exactly `text`, see [`Brain::was_trained_on`]
+    pub fn was_trained_on(&self, text: &str) -> bool {
+        self.has_line(brain::line(text))
+    }
+
+    /// Decodes the whole brain, so it can be trained, forgotten or pruned again
+    pub fn thaw(&self) -> Brain {
+        let chain = (0..self.contexts.len)
+            .map(|index| {
+                let context = self
+                    .key(index)
+                    .split(|&c| c == b' ')
+                    .map(Word::from)
+                    .collect::<Vec<_>>();
+
+                let links = self
+                    .context_links(index)
+                    .map(|(token, count)| {
+                        let token = match token {
+                            TokenRef::Word(word) => Token::Word(word.into()),
+                            TokenRef::End => Token::End,
+                        };
+                        Link { token, count }
+                    })
+                    .collect();
+
+                (context, Set(links))
+            })
+            .collect();
+
+        let head = self.head_words().map(Word::from).collect();
+        let lines = (0..self.lines.len).map(|index| self.line(index)).collect();
+        Brain::from_parts(self.meta.clone(), chain, head, lines)
+    }
+
+    fn parse(data: Storage) -> Result<Self, FormatError> {
+        if data.get(..4) != Some(&MAGIC[..]) {
+            return Err(FormatError::UnknownFormat);
+        }
+
+        let version = u16::from_le_bytes(read(&data, 4).ok_or_else(truncated)?);
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        let len = u32::from_le_bytes(read(&data, 6).ok_or_else(truncated)?) as usize;
+        let meta = data.get(10..10 + len).ok_or_else(truncated)?;
+        let meta = bincode::deserialize(meta)?;
+
+        let counts = 10 + len;
+        let count = |index: usize| {
+            read(&data, counts + index * 8)
+                .map(u64::from_le_bytes)
+                .and_then(|count| usize::try_from(count).ok())
+                .ok_or_else(truncated)
+        };
+
+        // the first version didn't have any lines
+        let fields = if version == 1 { 4 } else { 5 };
+        let mut start = counts + fields * 8;
+        let checksum = match version {
+            1 | 2 => None,
+            _ => {
+                let checksum = read(&data, start).map(u32::from_le_bytes);
+                start += 4;
+                Some(checksum.ok_or_else(truncated)?)
+            }
+        };
+        let tables = start;
+        let mut table = |index, width| -> Result<Table, FormatError> {
+            let len = count(index)?;
+            let table = Table { start, len };
+            start = len
+                .checked_mul(width)
+                .and_then(|size| size.checked_add(start))
+                .ok_or_else(truncated)?;
+            Ok(table)
+        };
+
+        let heads = table(0, HEAD)?;
+        let contexts = table(1, CONTEXT)?;
+        let links = table(2, LINK)?;
+        let lines = match version {
+            1 => Table { start, len: 0 },
+            _ => table(3, LINE)?,
+        };
+
+        // the tables have to fit, the words themselves are bounds checked as they're read
+        let words = start;
+        let end = match count(fields - 1)?.checked_add(words) {
+            Some(end) if end <= data.len() => end,
+            _ => return Err(truncated()),
+        };
+
+        // checking reads the whole file once, but nothing is decoded
+        if let Some(expected) = checksum {
+            let found = crc32fast::hash(&data[tables..end]);
+            if found != expected {
+                return Err(FormatError::ChecksumMismatch { expected, found });
+            }
+        }
+
+        Ok(Self {
+            meta,
+            data,
+            heads,
+            contexts,
+            links,
+            lines,
+            words,
+        })
+    }
+
+    fn line(&self, index: usize) -> u64 {
+        self.u64(self.lines.record(index, LINE))
+    }
+
+    fn has_line(&self, fingerprint: u64) -> bool {
+        let (mut low, mut high) = (0, self.lines.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.line(mid).cmp(&fingerprint) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return true,
+            }
+        }
+        false
+    }
+
+    fn word(&self, offset: u64, len: u32) -> &[u8] {
+        usize::try_from(offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(self.words))
+            .and_then(|start| self.data.get(start..start.checked_add(len as usize)?))
+            .unwrap_or_default()
+    }
+
+    fn head_words(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        (0..self.heads.len).map(|index| self.head(index))
+    }
+
+    fn key(&self, index: usize) -> &[u8] {
+        let at = self.contexts.record(index, CONTEXT);
+        self.word(self.u64(at), self.u32(at + 8))
+    }
+
+    fn context_links(&self, index: usize) -> impl Iterator<Item = (TokenRef<'_>, usize)> + '_ {
+        let at = self.contexts.record(index, CONTEXT);
+        let first = self.u64(at + 12) as usize;
+        let len = self.u32(at + 20) as usize;
+
+        (first..first.saturating_add(len).min(self.links.len)).map(move |index| {
+            let at = self.links.record(index, LINK);
+            let token = match self.u32(at + 8) {
+                END => TokenRef::End,
+                len => TokenRef::Word(self.word(self.u64(at), len)),
+            };
+            (token, self.u64(at + 12) as usize)
+        })
+    }
+
+    fn find_context(&self, key: &[u8]) -> Option<usize> {
+        search(self.contexts.len, key, |index| self.key(index))
+    }
+
+    fn is_head(&self, word: &[u8]) -> bool {
+        search(self.heads.len, word, |index| self.head(index)).is_some()
+    }
+
+    fn u32(&self, at: usize) -> u32 {
+        read(&self.data, at)
+            .map(u32::from_le_bytes)
+            .unwrap_or_default()
+    }
+
+    fn u64(&self, at: usize) -> u64 {
+        read(&self.data, at)
+            .map(u64::from_le_bytes)
+            .unwrap_or_default()
+    }
+}
+
+impl Source for FrozenBrain {
+    fn depth(&self) -> usize {
+        self.meta.depth
+    }
+
+    fn heads(&self) -> usize {
+        self.heads.len
+    }
+
+    fn head(&self, index: usize) -> &[u8] {
+        let at = self.heads.record(index, HEAD);
+        self.word(self.u64(at), self.u32(at + 8))
+    }
+
+    fn knows(&self, word: &[u8]) -> bool {
+        self.is_head(word) || self.find_context(word).is_some()
+    }
+
+    fn trained_on(&self, fingerprint: u64) -> bool {
+        self.has_line(fingerprint)
+    }
+
+    fn candidates<'a>(&'a self, context: &[Word], weight: f64, candidates: &mut Candidates<'a>) {
+        if let Some(index) = self.find_context(&key(context)) {
+            candidates.merge(self.context_links(index), weight)
+        }
+    }
+}
+
+/// A [`FrozenBrain`] that can still be trained
+///
+/// Newly trained lines are kept in memory until the brain is [compacted](Self::compact).
+pub struct MappedBrain {
+    frozen: FrozenBrain,
+    delta: Brain,
+}
+
+impl MappedBrain {
+    /// Memory-maps the file at `path`, see [`FrozenBrain::open`]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        FrozenBrain::open(path).map(Self::new)
+    }
+
+    /// Memory-maps the file at `path`, see [`FrozenBrain::load_file`]
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        FrozenBrain::load_file(path).map(Self::new)
+    }
+
+    pub fn new(frozen: FrozenBrain) -> Self {
+        let delta = Brain::new(frozen.name(), frozen.depth());
+        Self { frozen, delta }
+    }
+
+    pub fn name(&self) -> &str {
+        self.frozen.name()
+    }
+
+    pub const fn depth(&self) -> usize {
+        self.frozen.depth()
+    }
+
+    /// The metadata of the frozen brain, including everything that has been trained since
+    pub fn metadata(&self) -> Metadata {
+        let mut meta = self.frozen.metadata().clone();
+        if self.pending() > 0 {
+            meta.lines += self.pending();
+            meta.trained_at = meta.trained_at.max(self.delta.metadata().trained_at);
+        }
+        meta
+    }
+
+    /// How many lines have been trained since the brain was last compacted
+    pub const fn pending(&self) -> u64 {
+        self.delta.metadata().lines
+    }
+
+    pub fn train(&mut self, text: &str) {
+        self.delta.train(text)
+    }
+
+    /// Whether the brain was trained on exactly `text`, including since it was last compacted
+    pub fn was_trained_on(&self, text: &str) -> bool {
+        self.frozen.was_trained_on(text) || self.delta.was_trained_on(text)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn generate(&self, req: &GenerateRequest) -> GenerateOutcome {
+        generate::generate(self, req)
+    }
+
+    /// Atomically writes the frozen brain and everything trained since to `path`, then maps the new file
+    #[tracing::instrument(skip(self, path))]
+    pub fn compact(&mut self, path: impl AsRef<Path>, backups: usize) -> Result<(), FormatError> {
+        self.save_file(path.as_ref(), backups)?;
+        *self = Self::open(path)?;
+        Ok(())
+    }
+
+    /// Atomically writes the frozen brain and everything trained since to `path`, keeping `backups` previous versions
+    ///
+    /// Unlike [`compact`](Self::compact) this leaves the brain as it is, so it can
+    /// still be read while it's written. Opening `path` afterwards gives the compacted brain.
+    pub fn save_file(&self, path: impl AsRef<Path>, backups: usize) -> Result<(), FormatError> {
+        let meta = self.metadata();
+        format::write_atomic(path.as_ref(), backups, |writer| {
+            write(Some(&self.frozen), &self.delta, &meta, writer)
+        })
+    }
+
+    /// Decodes the whole brain, including everything trained since it was last compacted
+    pub fn thaw(&self) -> Brain {
+        let mut brain = self.frozen.thaw();
+        brain.merge(&self.delta).expect("delta has the same depth");
+        brain
+    }
+
+    // starting words that only the delta has
+    fn new_heads(&self) -> impl Iterator<Item = &Word> + '_ {
+        let (_, _, head) = self.delta.parts();
+        head.iter().filter(|word| !self.frozen.is_head(word))
+    }
+}
+
+impl Source for MappedBrain {
+    fn depth(&self) -> usize {
+        self.frozen.depth()
+    }
+
+    fn heads(&self) -> usize {
+        self.frozen.heads.len + self.new_heads().count()
+    }
+
+    fn head(&self, index: usize) -> &[u8] {
+        match index.checked_sub(self.frozen.heads.len) {
+            Some(index) => self
+                .new_heads()
+                .nth(index)
+                .expect("head should be in range"),
+            None => self.frozen.head(index),
+        }
+    }
+
+    fn knows(&self, word: &[u8]) -> bool {
+        self.frozen.knows(word) || self.delta.knows(word)
+    }
+
+    fn trained_on(&self, fingerprint: u64) -> bool {
+        self.frozen.trained_on(fingerprint) || self.delta.trained_on(fingerprint)
+    }
+
+    fn candidates<'a>(&'a self, context: &[Word], weight: f64, candidates: &mut Candidates<'a>) {
+        self.frozen.candidates(context, weight, candidates);
+        self.delta.candidates(context, weight, candidates);
+    }
+}
+
+enum Storage {
+    Mapped(memmap2::Mmap),
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for Storage {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Owned(data) => data,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Table {
+    start: usize,
+    len: usize,
+}
+
+impl Table {
+    const fn record(&self, index: usize, width: usize) -> usize {
+        self.start + index * width
+    }
+}
+
+/// Writes `frozen` and `delta` merged together
+fn write(
+    frozen: Option<&FrozenBrain>,
+    delta: &Brain,
+    meta: &Metadata,
+    writer: &mut dyn Write,
+) -> Result<(), FormatError> {
+    let (_, chain, head) = delta.parts();
+
+    let mut delta_contexts = chain
+        .iter()
+        .map(|(context, set)| (key(context), set))
+        .collect::<Vec<_>>();
+    delta_contexts.sort_unstable_by(|(l, _), (r, _)| l.cmp(r));
+
+    let mut builder = Builder::default();
+
+    let frozen_heads = frozen.into_iter().flat_map(FrozenBrain::head_words);
+    let delta_heads = head.iter().map(|word| &**word);
+    for word in merge_sorted(frozen_heads, delta_heads, |l, r| l.cmp(r)) {
+        builder.head(word.either())
+    }
+
+    let frozen_contexts = frozen
+        .into_iter()
+        .flat_map(|frozen| (0..frozen.contexts.len).map(move |index| (frozen, index)));
+
+    let contexts = merge_sorted(
+        frozen_contexts,
+        &delta_contexts,
+        |(frozen, index), (key, _)| frozen.key(*index).cmp(key),
+    );
+
+    for context in contexts {
+        match context {
+            Either::Left((frozen, index)) => {
+                builder.context(frozen.key(index), frozen.context_links(index))
+            }
+            Either::Right((key, set)) => builder.context(key, set_links(set)),
+            Either::Both((frozen, index), (_, set)) => {
+                let links = merge_sorted(
+                    frozen.context_links(index),
+                    set_links(set),
+                    |(l, _), (r, _)| l.cmp(r),
+                )
+                .map(|link| match link {
+                    Either::Left(link) | Either::Right(link) => link,
+                    Either::Both((token, l), (_, r)) => (token, l + r),
+                });
+                builder.context(frozen.key(index), links)
+            }
+        }
+    }
+
+    let frozen_lines = frozen
+        .into_iter()
+        .flat_map(|frozen| (0..frozen.lines.len).map(move |index| frozen.line(index)));
+    builder.lines(frozen_lines.chain(delta.lines().iter().copied()));
+
+    builder.finish(meta, writer)
+}
+
+fn set_links(set: &Set) -> impl Iterator<Item = (TokenRef<'_>, usize)> {
+    set.links()
+        .iter()
+        .map(|link| (link.token.as_ref(), link.count))
+}
+
+#[derive(Default)]
+struct Builder<'a> {
+    heads: Vec<u8>,
+    contexts: Vec<u8>,
+    links: Vec<u8>,
+    lines: Vec<u64>,
+    words: Vec<u8>,
+    interned: HashMap<&'a [u8], u64>,
+    counts: [u64; 3],
+}
+
+impl<'a> Builder<'a> {
+    fn head(&mut self, word: &'a [u8]) {
+        let offset = self.intern(word);
+        self.heads.extend_from_slice(&offset.to_le_bytes());
+        self.heads
+            .extend_from_slice(&(word.len() as u32).to_le_bytes());
+        self.counts[0] += 1;
+    }
+
+    fn context(&mut self, key: &'a [u8], links: impl Iterator<Item = (TokenRef<'a>, usize)>) {
+        let offset = self.intern(key);
+        let first = self.counts[2];
+
+        for (token, count) in links {
+            let (offset, len) = match token {
+                TokenRef::Word(word) => (self.intern(word), word.len() as u32),
+                TokenRef::End => (0, END),
+            };
+            self.links.extend_from_slice(&offset.to_le_bytes());
+            self.links.extend_from_slice(&len.to_le_bytes());
+            self.links.extend_from_slice(&(count as u64).to_le_bytes());
+            self.counts[2] += 1;
+        }
+
+        self.contexts.extend_from_slice(&offset.to_le_bytes());
+        self.contexts
+            .extend_from_slice(&(key.len() as u32).to_le_bytes());
+        self.contexts.extend_from_slice(&first.to_le_bytes());
+        self.contexts
+            .extend_from_slice(&((self.counts[2] - first) as u32).to_le_bytes());
+        self.counts[1] += 1;
+    }
+
+    fn lines(&mut self, lines: impl Iterator<Item = u64>) {
+        self.lines.extend(lines);
+        self.lines.sort_unstable();
+        self.lines.dedup();
+    }
+
+    fn intern(&mut self, word: &'a [u8]) -> u64 {
+        let words = &mut self.words;
+        *self.interned.entry(word).or_insert_with(|| {
+            let offset = words.len() as u64;
+            words.extend_from_slice(word);
+            offset
+        })
+    }
+
+    fn finish(self, meta: &Metadata, writer: &mut dyn Write) -> Result<(), FormatError> {
+        let meta = bincode::serialize(meta)?;
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(meta.len() as u32).to_le_bytes())?;
+        writer.write_all(&meta)?;
+        for count in self.counts {
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        writer.write_all(&(self.lines.len() as u64).to_le_bytes())?;
+        writer.write_all(&(self.words.len() as u64).to_le_bytes())?;
+
+        let lines = self
+            .lines
+            .iter()
+            .flat_map(|line| line.to_le_bytes())
+            .collect::<Vec<_>>();
+        let tables = [
+            &*self.heads,
+            &*self.contexts,
+            &*self.links,
+            &*lines,
+            &*self.words,
+        ];
+
+        let mut checksum = crc32fast::Hasher::new();
+        for table in tables {
+            checksum.update(table);
+        }
+        writer.write_all(&checksum.finalize().to_le_bytes())?;
+        for table in tables {
+            writer.write_all(table)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+enum Either<L, R> {
+    Left(L),
+    Right(R),
+    Both(L, R),
+}
+
+impl<'a> Either<&'a [u8], &'a [u8]> {
+    const fn either(self) -> &'a [u8] {
+        match self {
+            Self::Left(item) | Self::Right(item) | Self::Both(item, _) => item,
+        }
+    }
+}
+
+/// Merges two sorted iterators, pairing up equal items
+fn merge_sorted<L, R>(
+    left: impl IntoIterator<Item = L>,
+    right: impl IntoIterator<Item = R>,
+    mut cmp: impl FnMut(&L, &R) -> Ordering,
+) -> impl Iterator<Item = Either<L, R>> {
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    std::iter::from_fn(move || {
+        let order = match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) => cmp(l, r),
+            (Some(..), None) => Ordering::Less,
+            (None, Some(..)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+        Some(match order {
+            Ordering::Less => Either::Left(left.next()?),
+            Ordering::Greater => Either::Right(right.next()?),
+            Ordering::Equal => Either::Both(left.next()?, right.next()?),
+        })
+    })
+}
+
+fn search<'a>(len: usize, key: &[u8], at: impl Fn(usize) -> &'a [u8]) -> Option<usize> {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match at(mid).cmp(key) {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Some(mid),
+        }
+    }
+    None
+}
+
+fn key(context: &[Word]) -> Vec<u8> {
+    context.join(&b' ')
+}
+
+fn read<const N: usize>(data: &[u8], at: usize) -> Option<[u8; N]> {
+    data.get(at..at.checked_add(N)?)?.try_into().ok()
+}
+
+fn truncated() -> FormatError {
+    std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,253 @@
+use super::*;
+
+const CORPUS: &[&str] = &[
+    "the borrow checker is my friend",
+    "rust makes the compiler my friend",
+    "the stream is live and the chat is happy",
+    "today the stream is live",
+    "is the chat happy today",
+];
+
+fn trained(lines: &[&str]) -> Brain {
+    let mut brain = Brain::new("test", 3);
+    for line in lines {
+        brain.train(line)
+    }
+    brain
+}
+
+fn frozen(brain: &Brain) -> FrozenBrain {
+    let mut data = vec![];
+    FrozenBrain::write(brain, &mut data).unwrap();
+    FrozenBrain::from_bytes(data).unwrap()
+}
+
+fn outputs(generate: impl Fn(&GenerateRequest) -> GenerateOutcome) -> Vec<GenerateOutcome> {
+    (0..16)
+        .map(|seed| {
+            generate(&GenerateRequest {
+                seed: Some(seed),
+                max: 12,
+                ..GenerateRequest::default()
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn same_as_brain() {
+    let brain = trained(CORPUS);
+    let frozen = frozen(&brain);
+
+    assert_eq!(frozen.metadata(), brain.metadata());
+    assert_eq!(
+        outputs(|req| frozen.generate(req)),
+        outputs(|req| brain.generate(req))
+    );
+
+    let query = GenerateRequest {
+        query: Some("compiler".into()),
+        seed: Some(7),
+        ..GenerateRequest::default()
+    };
+    assert_eq!(frozen.generate(&query), brain.generate(&query));
+
+    let unknown = GenerateRequest {
+        query: Some("nope".into()),
+        ..GenerateRequest::default()
+    };
+    assert_eq!(frozen.generate(&unknown), GenerateOutcome::UnknownQuery);
+}
+
+#[test]
+fn was_trained_on() {
+    let (old, new) = CORPUS.split_at(3);
+    let mut mapped = MappedBrain::new(frozen(&trained(old)));
+    for line in new {
+        mapped.train(line)
+    }
+
+    for text in CORPUS {
+        assert!(mapped.was_trained_on(text), "{text}");
+    }
+    assert!(!mapped.frozen.was_trained_on(new[0]));
+    assert!(!mapped.was_trained_on("the borrow checker is my"));
+    assert!(mapped.thaw().was_trained_on(CORPUS[0]));
+}
+
+#[test]
+fn version_1() {
+    let brain = trained(CORPUS);
+    let (meta, chain, head) = brain.parts();
+    let unlined = Brain::from_parts(
+        meta.clone(),
+        chain.clone(),
+        head.clone(),
+        Default::default(),
+    );
+
+    // without the checksum, the count of the lines, or any lines
+    let mut data = vec![];
+    FrozenBrain::write(&unlined, &mut data).unwrap();
+    let counts = 10 + u32::from_le_bytes(data[6..10].try_into().unwrap()) as usize;
+    data.drain(counts + 40..counts + 44);
+    data.drain(counts + 24..counts + 32);
+    data[4..6].copy_from_slice(&1_u16.to_le_bytes());
+
+    let frozen = FrozenBrain::from_bytes(data).unwrap();
+    assert!(!frozen.was_trained_on(CORPUS[0]));
+    assert_eq!(
+        outputs(|req| frozen.generate(req)),
+        outputs(|req| brain.generate(req))
+    );
+}
+
+#[test]
+fn version_2() {
+    let brain = trained(CORPUS);
+
+    // without the checksum
+    let mut data = vec![];
+    FrozenBrain::write(&brain, &mut data).unwrap();
+    let counts = 10 + u32::from_le_bytes(data[6..10].try_into().unwrap()) as usize;
+    data.drain(counts + 40..counts + 44);
+    data[4..6].copy_from_slice(&2_u16.to_le_bytes());
+
+    let frozen = FrozenBrain::from_bytes(data).unwrap();
+    assert!(frozen.was_trained_on(CORPUS[0]));
+    assert_eq!(
+        outputs(|req| frozen.generate(req)),
+        outputs(|req| brain.generate(req))
+    );
+}
+
+#[test]
+fn thaw() {
+    let brain = trained(CORPUS);
+    let thawed = frozen(&brain).thaw();
+    assert_eq!(thawed.stats(10), brain.stats(10));
+    assert_eq!(
+        outputs(|req| thawed.generate(req)),
+        outputs(|req| brain.generate(req))
+    );
+}
+
+#[test]
+fn empty() {
+    let frozen = frozen(&Brain::new("empty", 3));
+    assert_eq!(
+        frozen.generate(&GenerateRequest::default()),
+        GenerateOutcome::EmptyBrain
+    );
+}
+
+#[test]
+fn delta() {
+    let (old, new) = CORPUS.split_at(3);
+    let mut mapped = MappedBrain::new(frozen(&trained(old)));
+
+    let query = GenerateRequest {
+        query: Some("today".into()),
+        ..GenerateRequest::default()
+    };
+    assert_eq!(mapped.generate(&query), GenerateOutcome::UnknownQuery);
+
+    for line in new {
+        mapped.train(line)
+    }
+    assert_eq!(mapped.pending(), 2);
+    assert_eq!(mapped.metadata().lines, 5);
+    assert!(mapped.generate(&query).is_generated());
+
+    let all = trained(CORPUS);
+    assert_eq!(mapped.thaw().stats(10), all.stats(10));
+}
+
+#[test]
+fn compact() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.sdbm");
+
+    let (old, new) = CORPUS.split_at(3);
+    FrozenBrain::save_file(&trained(old), &path, 0).unwrap();
+
+    let mut mapped = MappedBrain::open(&path).unwrap();
+    for line in new {
+        mapped.train(line)
+    }
+    mapped.compact(&path, 1).unwrap();
+    assert_eq!(mapped.pending(), 0);
+    assert_eq!(format::backups(&path).len(), 1);
+
+    // once compacted it's exactly the same as training everything up front
+    let all = trained(CORPUS);
+    let reopened = FrozenBrain::open(&path).unwrap();
+    assert_eq!(reopened.metadata().lines, 5);
+    assert_eq!(
+        outputs(|req| reopened.generate(req)),
+        outputs(|req| all.generate(req))
+    );
+    assert_eq!(
+        outputs(|req| mapped.generate(req)),
+        outputs(|req| all.generate(req))
+    );
+}
+
+#[test]
+fn invalid() {
+    assert!(matches!(
+        FrozenBrain::from_bytes(b"not a brain".to_vec()),
+        Err(FormatError::UnknownFormat)
+    ));
+
+    let mut data = vec![];
+    FrozenBrain::write(&trained(CORPUS), &mut data).unwrap();
+
+    let mut newer = data.clone();
+    newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        FrozenBrain::from_bytes(newer),
+        Err(FormatError::UnsupportedVersion(..))
+    ));
+
+    let mut corrupt = data.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        FrozenBrain::from_bytes(corrupt),
+        Err(FormatError::ChecksumMismatch { .. })
+    ));
+
+    data.truncate(data.len() - 1);
+    assert!(matches!(
+        FrozenBrain::from_bytes(data),
+        Err(FormatError::Io(..))
+    ));
+}
+
+#[test]
+fn load_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.sdbm");
+
+    FrozenBrain::save_file(&trained(&CORPUS[..1]), &path, 1).unwrap();
+    FrozenBrain::save_file(&trained(CORPUS), &path, 1).unwrap();
+    let mapped = MappedBrain::load_file(&path).unwrap();
+    assert!(mapped.was_trained_on(CORPUS[1]));
+    drop(mapped);
+
+    // a corrupt file falls back to the previous save
+    let mut data = std::fs::read(&path).unwrap();
+    *data.last_mut().unwrap() ^= 1;
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, data).unwrap();
+    assert!(MappedBrain::open(&path).is_err());
+
+    let mapped = MappedBrain::load_file(&path).unwrap();
+    assert!(mapped.was_trained_on(CORPUS[0]));
+    assert!(!mapped.was_trained_on(CORPUS[1]));
+
+    for backup in format::backups(&path) {
+        std::fs::remove_file(backup).unwrap();
+    }
+    assert!(MappedBrain::load_file(&path).is_err());
+}
@@ -0,0 +1,212 @@
+use crate::token::TokenRef;
+
+/// Controls how the next token is picked when generating
+///
+/// The default matches the original behavior: every context width is
+/// consulted, and a link is weighted by its count multiplied by its width.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SamplingConfig {
+    /// Below `1.0` favors common continuations, above `1.0` flattens the distribution.
+    ///
+    /// `0.0` (or less) always picks the most likely continuation
+    pub temperature: f64,
+    /// Only sample from the `k` most likely continuations
+    pub top_k: Option<usize>,
+    /// Only sample from the most likely continuations whose probabilities add up to `p`
+    pub top_p: Option<f64>,
+    /// How much each context width contributes
+    pub backoff: Backoff,
+    /// The shortest context width that is consulted.
+    ///
+    /// Larger values trade creativity for coherence. At the start of a
+    /// sentence (where there isn't enough context) the longest available
+    /// context is used instead
+    pub min_context: usize,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 1.0,
+            top_k: None,
+            top_p: None,
+            backoff: Backoff::default(),
+            min_context: 1,
+        }
+    }
+}
+
+impl SamplingConfig {
+    pub(crate) fn widths(&self, depth: usize, available: usize) -> std::ops::RangeInclusive<usize> {
+        let upper = std::cmp::min(depth, available);
+        let lower = self.min_context.clamp(1, upper.max(1));
+        lower..=upper
+    }
+}
+
+/// How each context width is weighted before the links are merged
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backoff {
+    /// A width is weighted by itself
+    #[default]
+    Linear,
+    /// Every width is weighted the same
+    Uniform,
+    /// A width is weighted by `base ^ (width - 1)`
+    Exponential(f64),
+    /// Explicit weights, starting at a width of `1`.
+    ///
+    /// Widths past the end of the list use the last weight
+    Custom(Vec<f64>),
+}
+
+impl Backoff {
+    pub fn weight(&self, width: usize) -> f64 {
+        match self {
+            Self::Linear => width as f64,
+            Self::Uniform => 1.0,
+            Self::Exponential(base) => base.powi(width.saturating_sub(1) as i32),
+            Self::Custom(weights) => weights
+                .get(width.saturating_sub(1))
+                .or_else(|| weights.last())
+                .copied()
+                .unwrap_or(1.0),
+        }
+    }
+}
+
+/// Weighted tokens merged from every context width, sorted by token
+#[derive(Default)]
+pub(crate) struct Candidates<'a> {
+    links: Vec<(TokenRef<'a>, f64)>,
+}
+
+impl<'a> Candidates<'a> {
+    /// Merges `links` (which must be sorted by token) with their counts scaled by `weight`
+    pub(crate) fn merge(
+        &mut self,
+        links: impl IntoIterator<Item = (TokenRef<'a>, usize)>,
+        weight: f64,
+    ) {
+        use std::cmp::Ordering::*;
+
+        let mut right = links
+            .into_iter()
+            .map(|(token, count)| (token, count as f64 * weight))
+            .peekable();
+
+        if self.links.is_empty() {
+            self.links.extend(right);
+            return;
+        }
+
+        let mut left = std::mem::take(&mut self.links).into_iter().peekable();
+        let mut out = Vec::with_capacity(left.len() + right.size_hint().0);
+
+        while let (Some((l, _)), Some((r, _))) = (left.peek(), right.peek()) {
+            match l.cmp(r) {
+                Less => out.extend(left.next()),
+                Greater => out.extend(right.next()),
+                Equal => {
+                    let ((token, l), (_, r)) = (left.next().unwrap(), right.next().unwrap());
+                    out.push((token, l + r))
+                }
+            }
+        }
+
+        out.extend(left.chain(right));
+        self.links = out;
+    }
+
+    /// Drops every word that `blocked` matches, the end of a sentence is kept
+    pub(crate) fn block(&mut self, blocked: impl Fn(&[u8]) -> bool) {
+        self.links
+            .retain(|(token, _)| !matches!(token, TokenRef::Word(word) if blocked(word)))
+    }
+
+    pub(crate) fn select(
+        mut self,
+        rng: &fastrand::Rng,
+        config: &SamplingConfig,
+    ) -> Option<TokenRef<'a>> {
+        self.links.retain(|&(_, weight)| weight > 0.0);
+        if self.links.is_empty() {
+            return None;
+        }
+
+        if config.temperature <= 0.0 {
+            return self.most_likely();
+        }
+
+        if config.top_k.is_some() || config.top_p.is_some() {
+            self.truncate(config.top_k, config.top_p);
+        }
+
+        if config.temperature != 1.0 {
+            let exponent = config.temperature.recip();
+            for (_, weight) in &mut self.links {
+                *weight = weight.powf(exponent)
+            }
+        }
+
+        // the candidates are merged from every width (with its weight) for each pick, so
+        // this is linear in them either way, keeping running totals wouldn't make it faster
+        let sum = self.links.iter().map(|(_, weight)| weight).sum::<f64>();
+        if !sum.is_normal() {
+            return self.most_likely();
+        }
+
+        let n = rng.f64() * sum;
+        let mut total = 0.0;
+        self.links
+            .iter()
+            .find(|(_, weight)| {
+                total += weight;
+                total > n
+            })
+            .or_else(|| self.links.last())
+            .map(|&(token, _)| token)
+    }
+
+    fn most_likely(&self) -> Option<TokenRef<'a>> {
+        self.links
+            .iter()
+            .fold(None, |max: Option<&(TokenRef, f64)>, link| match max {
+                Some(max) if max.1 >= link.1 => Some(max),
+                _ => Some(link),
+            })
+            .map(|&(token, _)| token)
+    }
+
+    // the token order is only needed while merging, so this sorts by weight
+    fn truncate(&mut self, top_k: Option<usize>, top_p: Option<f64>) {
+        self.links.sort_by(|(_, l), (_, r)| r.total_cmp(l));
+
+        if let Some(k) = top_k {
+            self.links.truncate(k.max(1));
+        }
+
+        if let Some(p) = top_p {
+            let total = self.links.iter().map(|(_, weight)| weight).sum::<f64>();
+            let threshold = p.clamp(0.0, 1.0) * total;
+
+            let mut sum = 0.0;
+            let keep = self
+                .links
+                .iter()
+                .take_while(|(_, weight)| {
+                    let below = sum < threshold;
+                    sum += weight;
+                    below
+                })
+                .count();
+
+            self.links.truncate(keep.max(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
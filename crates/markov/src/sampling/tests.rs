@@ -0,0 +1,173 @@
+use super::*;
+
+// a, b and c seen 1, 5 and 2 times, then the end of the sentence seen `end` times
+fn candidates(end: usize) -> Candidates<'static> {
+    let mut candidates = Candidates::default();
+    candidates.merge(
+        [
+            (TokenRef::Word(b"a"), 1),
+            (TokenRef::Word(b"b"), 5),
+            (TokenRef::Word(b"c"), 2),
+            (TokenRef::End, end),
+        ],
+        1.0,
+    );
+    candidates
+}
+
+// how often each word was selected from `candidates(0)` over `n` seeds
+fn selected(config: &SamplingConfig, n: u64) -> [usize; 3] {
+    let mut counts = [0; 3];
+    for seed in 0..n {
+        let rng = fastrand::Rng::with_seed(seed);
+        match candidates(0).select(&rng, config) {
+            Some(TokenRef::Word(b"a")) => counts[0] += 1,
+            Some(TokenRef::Word(b"b")) => counts[1] += 1,
+            Some(TokenRef::Word(b"c")) => counts[2] += 1,
+            token => panic!("unexpected token: {token:?}"),
+        }
+    }
+    counts
+}
+
+#[test]
+fn merge_scales_and_sums() {
+    let mut candidates = candidates(1);
+    candidates.merge([(TokenRef::Word(b"b"), 1), (TokenRef::End, 2)], 2.0);
+    assert_eq!(
+        candidates.links,
+        [
+            (TokenRef::Word(b"a"), 1.0),
+            (TokenRef::Word(b"b"), 7.0),
+            (TokenRef::Word(b"c"), 2.0),
+            (TokenRef::End, 5.0),
+        ]
+    );
+}
+
+#[test]
+fn nothing_to_select() {
+    let rng = fastrand::Rng::with_seed(1);
+    let config = SamplingConfig::default();
+    assert_eq!(Candidates::default().select(&rng, &config), None);
+
+    let mut zero = Candidates::default();
+    zero.merge([(TokenRef::Word(b"a"), 3)], 0.0);
+    assert_eq!(zero.select(&rng, &config), None);
+}
+
+#[test]
+fn proportional_to_weight() {
+    let [a, b, c] = selected(&SamplingConfig::default(), 800);
+    assert!(a > 0 && c > 0);
+    assert!(b > c && c > a, "{a} {b} {c}");
+}
+
+#[test]
+fn zero_temperature_is_most_likely() {
+    for temperature in [0.0, -1.0] {
+        let config = SamplingConfig {
+            temperature,
+            ..SamplingConfig::default()
+        };
+        assert_eq!(selected(&config, 32), [0, 32, 0]);
+    }
+}
+
+#[test]
+fn temperature() {
+    let cold = SamplingConfig {
+        temperature: 0.25,
+        ..SamplingConfig::default()
+    };
+    let hot = SamplingConfig {
+        temperature: 10.0,
+        ..SamplingConfig::default()
+    };
+
+    let [a, b, _] = selected(&SamplingConfig::default(), 800);
+    let [cold_a, cold_b, _] = selected(&cold, 800);
+    let [hot_a, hot_b, _] = selected(&hot, 800);
+    assert!(cold_b > b && b > hot_b, "{cold_b} {b} {hot_b}");
+    assert!(cold_a < a && a < hot_a, "{cold_a} {a} {hot_a}");
+}
+
+#[test]
+fn top_k() {
+    let config = |top_k| SamplingConfig {
+        top_k: Some(top_k),
+        ..SamplingConfig::default()
+    };
+    assert_eq!(selected(&config(1), 32), [0, 32, 0]);
+    // `0` still keeps the most likely
+    assert_eq!(selected(&config(0), 32), [0, 32, 0]);
+
+    let [a, b, c] = selected(&config(2), 200);
+    assert_eq!(a, 0);
+    assert!(b > 0 && c > 0);
+}
+
+#[test]
+fn top_p() {
+    let config = |top_p| SamplingConfig {
+        top_p: Some(top_p),
+        ..SamplingConfig::default()
+    };
+    // b alone is 5/8 of the weight
+    assert_eq!(selected(&config(0.5), 32), [0, 32, 0]);
+
+    let [a, b, c] = selected(&config(0.7), 200);
+    assert_eq!(a, 0);
+    assert!(b > 0 && c > 0);
+
+    let [a, b, c] = selected(&config(1.0), 200);
+    assert!(a > 0 && b > 0 && c > 0);
+}
+
+#[test]
+fn truncate_sorts_by_weight() {
+    let mut top = candidates(3);
+    top.truncate(Some(3), None);
+    assert_eq!(
+        top.links,
+        [
+            (TokenRef::Word(b"b"), 5.0),
+            (TokenRef::End, 3.0),
+            (TokenRef::Word(b"c"), 2.0),
+        ]
+    );
+
+    let mut nucleus = candidates(3);
+    nucleus.truncate(None, Some(0.6));
+    assert_eq!(
+        nucleus.links,
+        [(TokenRef::Word(b"b"), 5.0), (TokenRef::End, 3.0)]
+    );
+}
+
+#[test]
+fn backoff() {
+    assert_eq!(Backoff::Linear.weight(3), 3.0);
+    assert_eq!(Backoff::Uniform.weight(3), 1.0);
+    assert_eq!(Backoff::Exponential(2.0).weight(1), 1.0);
+    assert_eq!(Backoff::Exponential(2.0).weight(3), 4.0);
+
+    let custom = Backoff::Custom(vec![1.0, 5.0]);
+    assert_eq!(custom.weight(1), 1.0);
+    assert_eq!(custom.weight(2), 5.0);
+    assert_eq!(custom.weight(4), 5.0);
+    assert_eq!(Backoff::Custom(vec![]).weight(2), 1.0);
+}
+
+#[test]
+fn min_context() {
+    let config = |min_context| SamplingConfig {
+        min_context,
+        ..SamplingConfig::default()
+    };
+    assert_eq!(config(1).widths(3, 5), 1..=3);
+    assert_eq!(config(2).widths(3, 5), 2..=3);
+    // without enough context the longest available is used
+    assert_eq!(config(3).widths(3, 1), 1..=1);
+    assert_eq!(config(0).widths(3, 2), 1..=2);
+}
@@ -1,11 +1,15 @@
+use smallvec::SmallVec;
+
 use super::{Link, Token};
 
+/// The links that can follow a context, kept sorted by their token
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
-pub struct Set(pub(crate) Vec<Link>); // TODO a small vec
+#[serde(from = "SmallVec<[Link; 1]>")]
+pub struct Set(pub(crate) SmallVec<[Link; 1]>);
 
 impl Set {
     pub fn new(token: Token) -> Self {
-        Self(vec![Link::new(token)])
+        Self(smallvec::smallvec![Link::new(token)])
     }
 
     #[inline(always)]
@@ -13,20 +17,72 @@ impl Set {
         self.0.len()
     }
 
+    #[inline(always)]
+    pub fn links(&self) -> &[Link] {
+        &self.0
+    }
+
+    pub fn total(&self) -> usize {
+        self.0.iter().map(|link| link.count).sum()
+    }
+
     #[inline]
     pub fn insert(&mut self, token: Token) {
-        if let Some(existing) = self.find_mut(&token) {
-            existing.expand(1);
-            // TODO this doesn't have to be done at this stage
-            // self.0.sort_unstable_by(|a, b| a.cmp(b).reverse());
-            // self.0.dedup();
-            return;
+        match self.search(&token) {
+            Ok(index) => self.0[index].expand(1),
+            Err(index) => self.0.insert(index, Link::new(token)),
+        }
+    }
+
+    /// Adds every link from `other` to this set
+    pub fn merge(&mut self, other: &Self) {
+        for link in other.links() {
+            match self.search(&link.token) {
+                Ok(index) => self.0[index].merge(link),
+                Err(index) => self.0.insert(index, link.clone()),
+            }
         }
+    }
 
-        self.0.push(Link::new(token))
+    /// Removes `count` occurrences of `token`, returning whether the set is now empty
+    pub fn remove(&mut self, token: &Token, count: usize) -> bool {
+        if let Ok(index) = self.search(token) {
+            let link = &mut self.0[index];
+            link.count = link.count.saturating_sub(count);
+            if link.count == 0 {
+                self.0.remove(index);
+            }
+        }
+        self.0.is_empty()
     }
 
-    fn find_mut(&mut self, token: &Token) -> Option<&mut Link> {
-        self.0.iter_mut().find(|left| &left.token == token)
+    /// Keeps the links that `keep` returns true for, returning how many were removed
+    pub fn retain(&mut self, mut keep: impl FnMut(&mut Link) -> bool) -> usize {
+        let len = self.0.len();
+        self.0.retain(|link| keep(link));
+        len - self.0.len()
+    }
+
+    #[inline]
+    fn search(&self, token: &Token) -> Result<usize, usize> {
+        self.0.binary_search_by(|link| link.token.cmp(token))
     }
 }
+
+// older brains were stored with unsorted (and possibly unmerged) links
+impl From<SmallVec<[Link; 1]>> for Set {
+    fn from(mut links: SmallVec<[Link; 1]>) -> Self {
+        links.sort_unstable_by(|left, right| left.token.cmp(&right.token));
+        links.dedup_by(|right, left| {
+            let same = left.token == right.token;
+            if same {
+                left.merge(right)
+            }
+            same
+        });
+        Self(links)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,86 @@
+use super::*;
+
+fn word(word: &str) -> Token {
+    Token::Word(word.as_bytes().into())
+}
+
+fn links(set: &Set) -> Vec<(Token, usize)> {
+    set.links()
+        .iter()
+        .map(|link| (link.token.clone(), link.count))
+        .collect()
+}
+
+#[test]
+fn insert_keeps_links_sorted() {
+    let mut set = Set::new(word("c"));
+    for token in [Token::End, word("a"), word("c"), word("b"), word("a")] {
+        set.insert(token)
+    }
+
+    assert_eq!(
+        links(&set),
+        [
+            (word("a"), 2),
+            (word("b"), 1),
+            (word("c"), 2),
+            (Token::End, 1)
+        ]
+    );
+    assert_eq!(set.total(), 6);
+}
+
+#[test]
+fn merge() {
+    let mut left = Set::new(word("b"));
+    left.insert(word("d"));
+
+    let mut right = Set::new(word("a"));
+    right.insert(word("b"));
+    right.insert(Token::End);
+
+    left.merge(&right);
+    assert_eq!(
+        links(&left),
+        [
+            (word("a"), 1),
+            (word("b"), 2),
+            (word("d"), 1),
+            (Token::End, 1)
+        ]
+    );
+}
+
+#[test]
+fn legacy_links_are_sorted_and_merged() {
+    let legacy = smallvec::smallvec![
+        Link {
+            token: word("b"),
+            count: 2
+        },
+        Link {
+            token: Token::End,
+            count: 1
+        },
+        Link {
+            token: word("a"),
+            count: 1
+        },
+        Link {
+            token: word("b"),
+            count: 3
+        },
+    ];
+
+    // this is how sets are deserialized
+    let data = bincode::serialize::<SmallVec<[Link; 1]>>(&legacy).unwrap();
+    let set: Set = bincode::deserialize(&data).unwrap();
+    assert_eq!(
+        links(&set),
+        [(word("a"), 1), (word("b"), 5), (Token::End, 1)]
+    );
+
+    let mut set = set;
+    set.insert(word("c"));
+    assert_eq!(set.links()[2].token, word("c"));
+}
@@ -0,0 +1,62 @@
+/// A summary of what a [`Brain`](crate::Brain) contains
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BrainStats {
+    pub name: String,
+    pub depth: usize,
+    /// Number of contexts, across every width
+    pub contexts: usize,
+    /// Number of distinct words
+    pub unique_words: usize,
+    /// Number of links, across every context
+    pub total_links: usize,
+    /// Number of words that can start a sentence
+    pub heads: usize,
+    /// Number of distinct lines the brain remembers being trained on
+    pub lines: usize,
+    /// The most frequently seen words, with how often they were seen
+    pub top_words: Vec<(String, usize)>,
+    /// A rough estimate of how many bytes the brain uses in memory
+    pub memory: usize,
+}
+
+impl std::fmt::Display for BrainStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "name:         {}", self.name)?;
+        writeln!(f, "depth:        {}", self.depth)?;
+        writeln!(f, "contexts:     {}", self.contexts)?;
+        writeln!(f, "unique words: {}", self.unique_words)?;
+        writeln!(f, "total links:  {}", self.total_links)?;
+        writeln!(f, "heads:        {}", self.heads)?;
+        writeln!(f, "lines:        {}", self.lines)?;
+        writeln!(f, "memory:       ~{} KiB", self.memory / 1024)?;
+        if !self.top_words.is_empty() {
+            writeln!(f, "top words:")?;
+        }
+        for (word, count) in &self.top_words {
+            writeln!(f, "  {count:>8} {word}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A context and what can follow it, from [`Brain::contexts`](crate::Brain::contexts)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ContextLinks {
+    pub context: Vec<String>,
+    /// The words that follow the context and how often, most frequent first. `None` ends the sentence
+    pub links: Vec<(Option<String>, usize)>,
+}
+
+impl std::fmt::Display for ContextLinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ->", self.context.join(" "))?;
+        for (i, (word, count)) in self.links.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            match word {
+                Some(word) => write!(f, "{sep} {word} ({count})")?,
+                None => write!(f, "{sep} <end> ({count})")?,
+            }
+        }
+        Ok(())
+    }
+}
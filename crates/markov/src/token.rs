@@ -1,6 +1,6 @@
 use crate::Word;
 
-#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub enum Token {
     Word(Word), // TODO a thin box
     End,
@@ -16,3 +16,19 @@ impl std::fmt::Debug for Token {
         .finish()
     }
 }
+
+impl Token {
+    pub(crate) fn as_ref(&self) -> TokenRef<'_> {
+        match self {
+            Self::Word(word) => TokenRef::Word(word),
+            Self::End => TokenRef::End,
+        }
+    }
+}
+
+/// A borrowed [`Token`], ordered the same way
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum TokenRef<'a> {
+    Word(&'a [u8]),
+    End,
+}
@@ -0,0 +1,120 @@
+//! Requests against a single brain, with more and more of them training it
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use brain::state::{Limits, Shutdown, State};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use markov::{
+    format::{journal::Journal, SaveOptions},
+    Brain,
+};
+use tower::ServiceExt;
+
+const REQUESTS: usize = 256;
+
+const WORDS: &[&str] = &[
+    "the", "a", "chat", "is", "stream", "kappa", "pog", "rust", "bot", "hello", "what", "why",
+    "this", "that", "compiler", "borrow", "checker", "love", "hate", "yes", "no", "maybe", "lol",
+    "game", "music", "song", "today", "tomorrow", "never", "always", "good", "bad",
+];
+
+fn line(rng: &fastrand::Rng) -> String {
+    (0..rng.usize(3..20))
+        .map(|_| WORDS[rng.usize(0..WORDS.len())])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn app(dir: &std::path::Path) -> Router {
+    let rng = fastrand::Rng::with_seed(0xDEAD_BEEF);
+    let mut brain = Brain::new("bench", 5);
+    (0..10_000).for_each(|_| brain.train(&line(&rng)));
+
+    let path = dir.join("bench.sdb");
+    let journal = Journal::create(Journal::path_for(&path), brain.metadata()).unwrap();
+    let limits = Limits {
+        queue: REQUESTS,
+        readers: REQUESTS,
+        ..Limits::default()
+    };
+    let brain = brain::spawn_brain(brain, path, journal, SaveOptions::default(), limits);
+
+    brain::router(State {
+        directory: dir.to_path_buf(),
+        brains: Arc::new(tokio::sync::Mutex::new(
+            [("bench".to_string(), brain)].into_iter().collect(),
+        )),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits,
+        shutdown: Shutdown::default(),
+    })
+}
+
+// every `train`th request trains the brain, the rest generate from it
+async fn traffic(app: &Router, train: Option<usize>, rng: &fastrand::Rng) {
+    let tasks = (0..REQUESTS)
+        .map(|i| {
+            let (method, uri, body) = match train {
+                Some(n) if i % n == 0 => (
+                    Method::POST,
+                    "/bench/train",
+                    serde_json::json!({ "data": line(rng) }),
+                ),
+                _ => (
+                    Method::GET,
+                    "/bench/generate",
+                    serde_json::json!({ "min": 3, "max": 15, "seed": rng.u64(..) }),
+                ),
+            };
+            let req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            tokio::spawn(app.clone().oneshot(req))
+        })
+        .collect::<Vec<_>>();
+
+    for task in tasks {
+        let status = task.await.unwrap().unwrap().status();
+        // a seed can lead to a dead end, which isn't what's being measured
+        assert!(
+            matches!(status, StatusCode::OK | StatusCode::SERVICE_UNAVAILABLE),
+            "{status}"
+        );
+    }
+}
+
+fn mixed(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let app = rt.block_on(async { app(dir.path()) });
+    let rng = fastrand::Rng::with_seed(42);
+
+    let mut group = c.benchmark_group("mixed");
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Elements(REQUESTS as _));
+    for (name, train) in [
+        ("generate only", None),
+        ("1 in 10 train", Some(10)),
+        ("1 in 2 train", Some(2)),
+    ] {
+        group.bench_function(name, |b| b.iter(|| rt.block_on(traffic(&app, train, &rng))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mixed);
+criterion_main!(benches);
@@ -0,0 +1,225 @@
+//! API keys, and what each of them is allowed to do
+//!
+//! Keys are given as `token:permission[:brain,brain..]`, e.g. `s3cret:train:museun`.
+//! A key without any brains can use every brain. Requests send their key as
+//! either `Authorization: Bearer <token>` or `X-Api-Key: <token>`.
+//!
+//! If no keys are configured, every request is allowed.
+use std::{collections::HashMap, marker::PhantomData};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, Path, RequestParts},
+    http::{header, HeaderMap},
+    response::Response,
+};
+
+use crate::{handlers::make_error, state::State};
+
+/// What a key can do, each permission includes the ones before it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// Generate from brains, and look at them
+    Generate,
+    /// Train (and forget) lines
+    Train,
+    /// Create, remove and manage brains
+    Admin,
+}
+
+impl std::str::FromStr for Permission {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "generate" => Self::Generate,
+            "train" => Self::Train,
+            "admin" => Self::Admin,
+            s => {
+                return Err(format!(
+                    "unknown permission: {s} (expected generate, train or admin)"
+                ))
+            }
+        })
+    }
+}
+
+/// A key, without its token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    pub permission: Permission,
+    /// The brains the key can use, or every brain if this is empty
+    pub brains: Vec<String>,
+}
+
+impl Key {
+    pub fn allows(&self, brain: &str) -> bool {
+        self.brains.is_empty() || self.brains.iter().any(|name| name == brain)
+    }
+}
+
+/// A key with its token, as given on the command line
+#[derive(Debug, Clone)]
+pub struct KeySpec {
+    pub token: String,
+    pub key: Key,
+}
+
+impl std::str::FromStr for KeySpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (token, permission) = match (parts.next(), parts.next()) {
+            (Some(token), Some(permission)) if !token.is_empty() => (token, permission),
+            _ => return Err("a key must look like token:permission[:brain,brain]".into()),
+        };
+
+        let brains = parts
+            .next()
+            .into_iter()
+            .flat_map(|brains| brains.split(','))
+            .filter(|name| !name.is_empty())
+            .map(ToString::to_string)
+            .collect();
+
+        Ok(Self {
+            token: token.to_string(),
+            key: Key {
+                permission: permission.parse()?,
+                brains,
+            },
+        })
+    }
+}
+
+/// Every configured key
+#[derive(Debug, Default)]
+pub struct Keys {
+    keys: Vec<KeySpec>,
+}
+
+impl Keys {
+    pub fn new(keys: impl IntoIterator<Item = KeySpec>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Finds the key for `token`, comparing against every key in constant time
+    pub fn find(&self, token: &str) -> Option<&Key> {
+        self.keys
+            .iter()
+            .fold(None, |found, spec| match constant_eq(&spec.token, token) {
+                true => Some(&spec.key),
+                false => found,
+            })
+    }
+}
+
+fn constant_eq(left: &str, right: &str) -> bool {
+    left.len() == right.len()
+        && left
+            .bytes()
+            .zip(right.bytes())
+            .fold(0, |diff, (l, r)| diff | (l ^ r))
+            == 0
+}
+
+/// Which permission a handler needs
+pub trait Required {
+    const PERMISSION: Permission;
+}
+
+pub enum Generate {}
+impl Required for Generate {
+    const PERMISSION: Permission = Permission::Generate;
+}
+
+pub enum Train {}
+impl Required for Train {
+    const PERMISSION: Permission = Permission::Train;
+}
+
+pub enum Admin {}
+impl Required for Admin {
+    const PERMISSION: Permission = Permission::Admin;
+}
+
+/// Rejects the request unless its key has permission `P` for the brain in the path
+///
+/// This must come before any extractor that reads the body.
+pub struct Auth<P> {
+    /// The key that was used, or nothing if there are no keys
+    pub key: Option<Key>,
+    _required: PhantomData<fn() -> P>,
+}
+
+impl<P> Auth<P> {
+    /// Whether the brain called `name` can be used with this key
+    pub fn allows(&self, name: &str) -> bool {
+        match &self.key {
+            Some(key) => key.allows(name),
+            None => true,
+        }
+    }
+}
+
+#[async_trait]
+impl<B, P> FromRequest<B> for Auth<P>
+where
+    B: Send,
+    P: Required,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let state = req
+            .extensions()
+            .get::<State>()
+            .cloned()
+            .expect("state should be available");
+
+        if state.keys.is_empty() {
+            return Ok(Self {
+                key: None,
+                _required: PhantomData,
+            });
+        }
+
+        let key = match token(req.headers()).and_then(|token| state.keys.find(token)) {
+            Some(key) => key.clone(),
+            None => return Err(make_error(401, "a valid api key is required")),
+        };
+
+        if key.permission < P::PERMISSION {
+            return Err(make_error(403, "this key isn't allowed to do that"));
+        }
+
+        let params = req
+            .extract::<Path<HashMap<String, String>>>()
+            .await
+            .map(|Path(params)| params)
+            .unwrap_or_default();
+        if let Some(name) = params.get("name").filter(|name| !key.allows(name)) {
+            return Err(make_error(403, format!("this key can't use {name}")));
+        }
+
+        Ok(Self {
+            key: Some(key),
+            _required: PhantomData,
+        })
+    }
+}
+
+fn token(headers: &HeaderMap) -> Option<&str> {
+    if let Some(auth) = headers.get(header::AUTHORIZATION) {
+        return auth.to_str().ok()?.strip_prefix("Bearer ").map(str::trim);
+    }
+    headers.get("x-api-key")?.to_str().ok().map(str::trim)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use markov::format::SaveOptions;
+use tower::ServiceExt;
+
+use super::*;
+
+fn app(dir: &std::path::Path, keys: &[&str]) -> Router {
+    crate::router(State {
+        directory: dir.to_path_buf(),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::new(Keys::new(keys.iter().map(|key| key.parse().unwrap()))),
+        limits: Default::default(),
+        shutdown: Default::default(),
+    })
+}
+
+async fn call(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    header: Option<(&str, &str)>,
+    body: &str,
+) -> (StatusCode, String) {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some((key, val)) = header {
+        req = req.header(key, val);
+    }
+
+    let resp = app
+        .clone()
+        .oneshot(req.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+
+    let status = resp.status();
+    let mut body = resp.into_body();
+    let mut data = vec![];
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk.unwrap());
+    }
+    (status, String::from_utf8(data).unwrap())
+}
+
+const fn bearer(token: &str) -> Option<(&str, &str)> {
+    Some(("authorization", token))
+}
+
+#[test]
+fn keys() {
+    let spec: KeySpec = "s3cret:train:museun,other".parse().unwrap();
+    assert_eq!(spec.token, "s3cret");
+    assert_eq!(spec.key.permission, Permission::Train);
+    assert_eq!(spec.key.brains, ["museun", "other"]);
+    assert!(spec.key.allows("other") && !spec.key.allows("another"));
+
+    let spec: KeySpec = "s3cret:admin".parse().unwrap();
+    assert!(spec.key.brains.is_empty() && spec.key.allows("anything"));
+
+    for bad in ["", "s3cret", ":admin", "s3cret:root"] {
+        assert!(bad.parse::<KeySpec>().is_err(), "{bad}");
+    }
+
+    let keys = Keys::new(["a:generate".parse().unwrap(), "ab:admin".parse().unwrap()]);
+    assert_eq!(keys.find("ab").unwrap().permission, Permission::Admin);
+    assert_eq!(keys.find("a").unwrap().permission, Permission::Generate);
+    assert!(keys.find("abc").is_none());
+}
+
+#[tokio::test]
+async fn without_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(dir.path(), &[]);
+
+    let (status, _) = call(&app, Method::POST, "/museun/create", None, "{}").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(
+        &app,
+        Method::POST,
+        "/museun/train",
+        None,
+        r#"{"data":"hello"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn permissions() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = app(
+        dir.path(),
+        &[
+            "root:admin",
+            "scoped:admin:museun",
+            "trainer:train:museun",
+            "reader:generate",
+        ],
+    );
+    let train = r#"{"data":"the stream is live"}"#;
+
+    let create = |header| call(&app, Method::POST, "/museun/create", header, "{}");
+    assert_eq!(create(None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        create(bearer("Bearer nope")).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(create(bearer("trainer")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        create(bearer("Bearer trainer")).await.0,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(create(bearer("Bearer root")).await.0, StatusCode::OK);
+
+    let (status, _) = call(
+        &app,
+        Method::POST,
+        "/other/create",
+        bearer("Bearer root"),
+        "{}",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let train_as = |uri, header| call(&app, Method::POST, uri, header, train);
+    assert_eq!(
+        train_as("/museun/train", bearer("Bearer reader")).await.0,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        train_as("/museun/train", bearer("Bearer trainer")).await.0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        train_as("/museun/train", Some(("x-api-key", "trainer")))
+            .await
+            .0,
+        StatusCode::OK
+    );
+    // the trainer is scoped to museun
+    assert_eq!(
+        train_as("/other/train", bearer("Bearer trainer")).await.0,
+        StatusCode::FORBIDDEN
+    );
+
+    let (status, body) = call(&app, Method::GET, "/brains", bearer("Bearer trainer"), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, r#"{"brains":["museun"]}"#);
+
+    let (_, body) = call(&app, Method::GET, "/brains", bearer("Bearer reader"), "").await;
+    assert_eq!(body, r#"{"brains":["museun","other"]}"#);
+
+    let (status, _) = call(&app, Method::GET, "/museun", bearer("Bearer reader"), "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // renaming needs the new name to be in scope too
+    let rename = r#"{"name":"elsewhere"}"#;
+    let (status, _) = call(
+        &app,
+        Method::POST,
+        "/museun/rename",
+        bearer("Bearer scoped"),
+        rename,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,91 @@
+//! Reads a JSON [`request::Batch`](crate::request::Batch) as it's streamed in
+//!
+//! Only the line being parsed is kept in memory, every line is sent on as soon as
+//! it's parsed. Parsing blocks, so it runs on the blocking pool.
+use std::{fmt, io::Read};
+
+use axum::body::Bytes;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// Parses the batch in `chunks`, sending each line to `lines`
+///
+/// Stops with an error if `lines` is closed.
+pub(crate) fn read_json(chunks: Receiver<Bytes>, lines: &Sender<String>) -> serde_json::Result<()> {
+    let mut de = serde_json::Deserializer::from_reader(ChunkReader {
+        chunks,
+        chunk: Bytes::new(),
+    });
+    de.deserialize_any(Lines(lines))?;
+    de.end()
+}
+
+// the body as it's received, this blocks until the next chunk arrives
+struct ChunkReader {
+    chunks: Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
+    }
+}
+
+// either a list of lines, or an object with a list of lines as its `data`
+struct Lines<'a>(&'a Sender<String>);
+
+impl<'de> Visitor<'de> for Lines<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list of lines, or an object with a list of lines as its data")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(line) = seq.next_element::<String>()? {
+            if self.0.blocking_send(line).is_err() {
+                return Err(de::Error::custom("the batch was abandoned"));
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut data = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match &*key {
+                "data" if data => return Err(de::Error::duplicate_field("data")),
+                "data" => {
+                    map.next_value_seed(Lines(self.0))?;
+                    data = true;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        match data {
+            true => Ok(()),
+            false => Err(de::Error::missing_field("data")),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Lines<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
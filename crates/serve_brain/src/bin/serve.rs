@@ -1,9 +1,17 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
-use brain::{spawn_brain, start_server, state::State, Messaging};
+use anyhow::Context;
+use brain::{
+    auth::{KeySpec, Keys},
+    decay_brains, save_brains, spawn_brain, start_server,
+    state::{Limits, Shutdown, State},
+    Messaging,
+};
+use filters::Filters;
 use gumdrop::Options;
-use markov::Brain;
+use markov::format::{Compression, SaveOptions};
 use tokio::sync::Mutex;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Debug, Options)]
 struct Config {
@@ -19,51 +27,232 @@ struct Config {
         meta = "addr"
     )]
     address: String,
+
+    #[options(
+        help = "scale every brain's counts by this factor (from 0 to 1) each decay interval",
+        meta = "factor"
+    )]
+    decay_factor: Option<f64>,
+
+    #[options(help = "seconds between each decay", default = "86400", meta = "secs")]
+    decay_interval: u64,
+
+    #[options(
+        help = "number of previous saves to keep for each brain",
+        default = "3",
+        meta = "n"
+    )]
+    backups: usize,
+
+    #[options(
+        help = "compression for saved brains: none, zstd or snappy",
+        default = "zstd",
+        meta = "kind"
+    )]
+    compression: Compression,
+
+    #[options(
+        help = "comma separated filters for trained lines, e.g. standard,name=shaken,min-words=2",
+        meta = "filters"
+    )]
+    filters: Option<Filters>,
+
+    #[options(
+        help = "an api key as token:permission[:brain,brain], permission is generate, train or admin. \
+                keys can also be given as whitespace separated BRAIN_KEYS. without any keys, anyone can do anything",
+        meta = "key"
+    )]
+    key: Vec<KeySpec>,
+
+    #[options(
+        help = "the largest request body, in bytes",
+        no_short,
+        default = "8388608",
+        meta = "bytes"
+    )]
+    max_body: usize,
+
+    #[options(
+        help = "changes that can wait for each brain, more than this are answered with 429",
+        no_short,
+        default = "16",
+        meta = "n"
+    )]
+    queue: usize,
+
+    #[options(
+        help = "requests that can generate from each brain at once, more than this are answered with 429",
+        no_short,
+        default = "8",
+        meta = "n"
+    )]
+    readers: usize,
+
+    #[options(
+        help = "seconds a request waits for its brain before giving up, a change it gives up on is answered with 202",
+        no_short,
+        default = "30",
+        meta = "secs"
+    )]
+    timeout: u64,
+
+    #[options(
+        help = "seconds a brain tries to generate a single sentence",
+        no_short,
+        default = "5",
+        meta = "secs"
+    )]
+    generate_timeout: u64,
+
+    #[options(
+        help = "seconds every brain has to save when the server shuts down",
+        no_short,
+        default = "30",
+        meta = "secs"
+    )]
+    save_timeout: u64,
+
+    #[options(
+        help = "which events are logged, e.g. info or warn,markov=debug. defaults to BRAIN_LOG, or info. \
+                markov only logs warnings unless it's given a level",
+        no_short,
+        meta = "filter"
+    )]
+    log: Option<String>,
 }
 
-async fn load_brains(paths: &[PathBuf]) -> State {
+async fn load_brains(
+    directory: PathBuf,
+    paths: &[PathBuf],
+    save_options: SaveOptions,
+    filters: Filters,
+    keys: Keys,
+    limits: Limits,
+) -> anyhow::Result<State> {
     let mut map = HashMap::<String, Messaging>::default();
-    for name in paths.into_iter() {
+    for name in paths.iter() {
         let stem = name.file_stem().expect("valid path");
 
         let (tx, mut rx) = tokio::sync::mpsc::channel(paths.len());
         tokio::task::spawn({
             let tx = tx.clone();
             let name = name.to_owned();
-            let stem = stem.to_owned();
             async move {
-                let brain = brain::load(&name)
-                    .await
-                    .unwrap_or_else(|_| Brain::new(stem.to_string_lossy(), 5));
+                // a brain that can't be loaded (nor any of its backups) is never replaced
+                let brain = match brain::load_model(&name).await {
+                    // anything trained since the brain was last saved
+                    Ok(brain) => brain::replay(brain, &name).await,
+                    Err(err) => Err(err),
+                };
                 let _ = tx.send((name, brain)).await;
             }
         });
         drop(tx);
 
         while let Some((name, brain)) = rx.recv().await {
-            let out = spawn_brain(brain, name);
-            map.insert(stem.to_string_lossy().to_string(), Messaging::new(out));
+            let (brain, journal) =
+                brain.with_context(|| format!("cannot load {}", name.display()))?;
+            tracing::info!(path = %name.display(), lines = brain.metadata().lines, "loaded a brain");
+            let brain = spawn_brain(brain, name, journal, save_options, limits);
+            map.insert(stem.to_string_lossy().to_string(), brain);
         }
     }
 
-    State {
+    Ok(State {
+        directory,
         brains: Arc::new(Mutex::new(map)),
-    }
+        reserved: Arc::default(),
+        save_options,
+        filters: Arc::new(filters),
+        keys: Arc::new(keys),
+        limits,
+        shutdown: Shutdown::default(),
+    })
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
     let config = Config::parse_args_default_or_exit();
 
+    // every generation in markov is instrumented, so it's quiet unless it's asked for
+    let log = match config.log {
+        Some(log) => log,
+        None => std::env::var("BRAIN_LOG").unwrap_or_else(|_| "info".to_string()),
+    };
+    let filter = EnvFilter::try_new(format!("markov=warn,{log}"))
+        .with_context(|| format!("invalid log filter: {log}"))?;
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
     let mut paths = vec![];
-    let mut stream = tokio::fs::read_dir(config.directory).await?;
+    let mut stream = tokio::fs::read_dir(&config.directory).await?;
     while let Some(entry) = stream.next_entry().await.ok().flatten() {
         let path = entry.path();
-        if let Some("sdb") = path.extension().and_then(|s| s.to_str()) {
+        if let Some("sdb" | "sdbm") = path.extension().and_then(|s| s.to_str()) {
             paths.push(path)
         }
     }
 
-    let brains = load_brains(&paths).await;
-    start_server(config.address, brains).await
+    let mut keys = config.key;
+    if let Ok(env) = std::env::var("BRAIN_KEYS") {
+        for key in env.split_whitespace() {
+            keys.push(
+                key.parse()
+                    .map_err(|err| anyhow::anyhow!("BRAIN_KEYS: {err}"))?,
+            );
+        }
+    }
+    let keys = Keys::new(keys);
+
+    let save_options = SaveOptions {
+        compression: config.compression,
+        backups: config.backups,
+    };
+    anyhow::ensure!(
+        config.decay_interval > 0,
+        "the decay interval must be at least a second"
+    );
+    if let Some(factor) = config.decay_factor {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&factor),
+            "the decay factor must be from 0 to 1"
+        );
+    }
+    anyhow::ensure!(config.queue > 0, "the queue must have room for a request");
+    anyhow::ensure!(
+        config.readers > 0,
+        "at least one request must be able to generate"
+    );
+    anyhow::ensure!(
+        config.timeout > 0 && config.generate_timeout > 0 && config.save_timeout > 0,
+        "every timeout must be at least a second"
+    );
+    let limits = Limits {
+        body: config.max_body,
+        queue: config.queue,
+        readers: config.readers,
+        timeout: Duration::from_secs(config.timeout),
+        generate: Duration::from_secs(config.generate_timeout),
+    };
+
+    let brains = load_brains(
+        config.directory,
+        &paths,
+        save_options,
+        config.filters.unwrap_or_default(),
+        keys,
+        limits,
+    )
+    .await?;
+    tokio::spawn(save_brains(brains.clone()));
+
+    if let Some(factor) = config.decay_factor {
+        let interval = Duration::from_secs(config.decay_interval);
+        tokio::spawn(decay_brains(brains.clone(), interval, factor));
+    }
+
+    let save_timeout = Duration::from_secs(config.save_timeout);
+    start_server(config.address, brains, save_timeout).await
 }
@@ -1,16 +1,31 @@
-use std::path::PathBuf;
-
 use axum::{
-    extract::Path,
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    Extension, Json,
+    body::{Body, HttpBody},
+    extract::{Path, Query, RawBody},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    BoxError, Extension, Json,
 };
-use markov::Brain;
+use filters::Filters;
+use futures_util::StreamExt;
+use markov::{format::journal::Journal, Brain};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
 
-use crate::{messaging, request, response, spawn_brain, state::State, Messaging};
+use crate::{
+    auth::{self, Auth},
+    batch,
+    messaging::{self, Rejected},
+    metrics, openapi, request, response, spawn_brain,
+    state::State,
+    IsMapped, Messaging, NotGenerated, DEFAULT_DEPTH, MAX_CANDIDATES, MAX_DEPTH, MAX_LINE,
+};
 
 pub async fn generate(
+    _: Auth<auth::Generate>,
     Path(name): Path<String>,
     req: Option<Json<request::Generate>>,
     state: Extension<State>,
@@ -21,15 +36,52 @@ pub async fn generate(
     };
 
     let opts = req.map(|Json(data)| data).unwrap_or_default();
-    let resp = brain.send(messaging::Request::Generate { opts }).await;
+    if let Err(err) = opts.validate() {
+        return make_error(400, err);
+    }
+
+    let resp = brain.generate(opts).await;
+    match resp {
+        Ok(messaging::Response::Generated { data }) => json(response::Generate { data }),
+        Ok(messaging::Response::Error { error }) => generate_error(error),
+        Err(err) => rejected(err),
+        _ => StatusCode::OK.into_response(),
+    }
+}
+
+pub async fn candidates(
+    _: Auth<auth::Generate>,
+    Path(name): Path<String>,
+    req: Option<Json<request::Candidates>>,
+    state: Extension<State>,
+) -> Response {
+    let brain = match state.try_get(&name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    let request::Candidates { count, opts } = req.map(|Json(data)| data).unwrap_or_default();
+    let count = count.unwrap_or(5);
+    if !(1..=MAX_CANDIDATES).contains(&count) {
+        return make_error(400, format!("count must be between 1 and {MAX_CANDIDATES}"));
+    }
+    if let Err(err) = opts.validate() {
+        return make_error(400, err);
+    }
+
+    let resp = brain.candidates(opts, count).await;
     match resp {
-        messaging::Response::Generated { data } => json(response::Generate { data }),
-        messaging::Response::Error { error } => make_error(503, error),
+        Ok(messaging::Response::Candidates { candidates }) => {
+            json(response::Candidates { candidates })
+        }
+        Ok(messaging::Response::Error { error }) => generate_error(error),
+        Err(err) => rejected(err),
         _ => StatusCode::OK.into_response(),
     }
 }
 
 pub async fn train(
+    _: Auth<auth::Train>,
     Path(name): Path<String>,
     Json(request::Train { data }): Json<request::Train>,
     state: Extension<State>,
@@ -41,55 +93,609 @@ pub async fn train(
         None => return make_error(404, format!("cannot find {name}")),
     };
 
-    if let Error { error } = brain.send(Train { data }).await {
-        return make_error(401, error);
+    // lines that are filtered out entirely aren't an error, there's just nothing to train
+    let data = match state.filters.apply(&data) {
+        Some(data) => data,
+        None => return StatusCode::OK.into_response(),
+    };
+
+    match brain.request(Train { data }).await {
+        Ok(Error { error }) => make_error(503, error),
+        Err(err) => rejected(err),
+        _ => StatusCode::OK.into_response(),
+    }
+}
+
+/// Trains many lines at once
+///
+/// A JSON body is a list of lines (see [`request::Batch`]), anything else is
+/// read as newline-delimited text. Either is trained as it's streamed in, and
+/// no line can be longer than [`MAX_LINE`] bytes.
+pub async fn train_batch(
+    _: Auth<auth::Train>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    RawBody(body): RawBody,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    let brain = match state.try_get(&name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|s| s.to_str().ok())
+        .filter(|s| s.starts_with("application/json"))
+        .is_some();
+
+    let mut batch = Batch::new(&brain, &state.filters);
+    let done = match is_json {
+        true => train_json(&mut batch, body, state.limits.body).await,
+        false => train_text(&mut batch, body, state.limits.body).await,
+    };
+
+    if let Err(resp) = done.and(batch.flush().await) {
+        return resp;
+    }
+
+    json(response::Trained {
+        trained: batch.trained,
+        skipped: batch.skipped,
+    })
+}
+
+async fn train_text(batch: &mut Batch<'_>, mut body: Body, limit: usize) -> Result<(), Response> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        buf.extend_from_slice(&chunk.map_err(|err| body_error(&err, limit))?);
+
+        // everything up to the last newline is complete
+        if let Some(end) = buf.iter().rposition(|&c| c == b'\n') {
+            let rest = buf.split_off(end + 1);
+            let lines = std::mem::replace(&mut buf, rest);
+            batch.push_text(&lines).await?;
+        }
+
+        if buf.len() > MAX_LINE {
+            return Err(line_too_long());
+        }
     }
+    batch.push_text(&buf).await
+}
 
-    if let Error { error } = brain.send(Save).await {
-        return make_error(503, error);
+async fn train_json(batch: &mut Batch<'_>, mut body: Body, limit: usize) -> Result<(), Response> {
+    let (chunks, rx) = tokio::sync::mpsc::channel(4);
+    let (tx, mut lines) = tokio::sync::mpsc::channel(Batch::SIZE);
+    let parse = tokio::task::spawn_blocking(move || batch::read_json(rx, &tx));
+
+    // the body is fed to the parser while the lines it parsed are trained
+    let feed = tokio::spawn(async move {
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|err| body_error(&err, limit))?;
+            // the parser stopped early, it has the reason why
+            if chunks.send(chunk).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    while let Some(line) = lines.recv().await {
+        batch.push_lines([line]).await?;
     }
 
-    StatusCode::OK.into_response()
+    // a body that couldn't be read is also a batch that couldn't be parsed
+    feed.await
+        .map_err(|err| make_error(500, format!("cannot read the body: {err}")))??;
+    match parse.await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(make_error(400, format!("invalid batch: {err}"))),
+        Err(err) => Err(make_error(500, format!("cannot read the batch: {err}"))),
+    }
+}
+
+fn body_error(err: &(dyn std::error::Error + 'static), limit: usize) -> Response {
+    match too_large(err) {
+        true => make_error(413, BodyTooLarge { limit }),
+        false => make_error(400, format!("cannot read the body: {err}")),
+    }
+}
+
+fn line_too_long() -> Response {
+    make_error(413, format!("a line is longer than {MAX_LINE} bytes"))
+}
+
+// filters lines, sending them to the brain in batches
+struct Batch<'a> {
+    brain: &'a Messaging,
+    filters: &'a Filters,
+    pending: Vec<String>,
+    trained: usize,
+    skipped: usize,
+}
+
+impl<'a> Batch<'a> {
+    const SIZE: usize = 256;
+
+    fn new(brain: &'a Messaging, filters: &'a Filters) -> Self {
+        Self {
+            brain,
+            filters,
+            pending: Vec::with_capacity(Self::SIZE),
+            trained: 0,
+            skipped: 0,
+        }
+    }
+
+    async fn push_text(&mut self, text: &[u8]) -> Result<(), Response> {
+        let text = std::str::from_utf8(text)
+            .map_err(|err| make_error(400, format!("the body isn't utf-8: {err}")))?;
+        self.push_lines(text.lines().map(ToString::to_string)).await
+    }
+
+    async fn push_lines(
+        &mut self,
+        lines: impl IntoIterator<Item = String>,
+    ) -> Result<(), Response> {
+        for line in lines {
+            if line.len() > MAX_LINE {
+                return Err(line_too_long());
+            }
+            match self.filters.apply(&line) {
+                Some(line) => self.pending.push(line),
+                None => self.skipped += 1,
+            }
+            if self.pending.len() == Self::SIZE {
+                self.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Response> {
+        use messaging::{Request::*, Response::*};
+
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let data = std::mem::replace(&mut self.pending, Vec::with_capacity(Self::SIZE));
+        let len = data.len();
+        if let Error { error } = self.brain.send(TrainBatch { data }).await {
+            let trained = self.trained;
+            return Err(make_error(
+                503,
+                format!("{error} (after training {trained} lines)"),
+            ));
+        }
+        self.trained += len;
+        Ok(())
+    }
+}
+
+pub async fn forget(
+    _: Auth<auth::Train>,
+    Path(name): Path<String>,
+    Json(request::Forget { data }): Json<request::Forget>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    use messaging::{Request::*, Response::*};
+
+    let brain = match state.try_get(&name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    // the line was trained after it was filtered, so it's forgotten the same way
+    let data = match state.filters.apply(&data) {
+        Some(data) => data,
+        None => return StatusCode::OK.into_response(),
+    };
+
+    match brain.request(Forget { data }).await {
+        Ok(Error { error }) => brain_error(error),
+        Err(err) => rejected(err),
+        _ => StatusCode::OK.into_response(),
+    }
+}
+
+pub async fn prune(
+    _: Auth<auth::Admin>,
+    Path(name): Path<String>,
+    Json(request::Prune { min_count }): Json<request::Prune>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    remove_links(&name, messaging::Request::Prune { min_count }, &state).await
+}
+
+pub async fn decay(
+    _: Auth<auth::Admin>,
+    Path(name): Path<String>,
+    Json(request::Decay { factor }): Json<request::Decay>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    if !(0.0..=1.0).contains(&factor) {
+        return make_error(400, "factor must be between 0.0 and 1.0");
+    }
+    remove_links(&name, messaging::Request::Decay { factor }, &state).await
+}
+
+async fn remove_links(name: &str, req: messaging::Request, state: &State) -> Response {
+    use messaging::Response::*;
+
+    let brain = match state.try_get(name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    let links = match brain.request(req).await {
+        Ok(Removed { links }) => links,
+        Ok(Error { error }) => return brain_error(error),
+        Err(err) => return rejected(err),
+        _ => 0,
+    };
+
+    json(response::Removed { links })
 }
 
-// TODO 'scope' the path to the configured directory
 pub async fn create(
+    _: Auth<auth::Admin>,
     Path(name): Path<String>,
-    Json(request::Create { path, depth }): Json<request::Create>,
+    Json(request::Create { depth }): Json<request::Create>,
     state: Extension<State>,
 ) -> impl IntoResponse {
     use messaging::{Request::*, Response::*};
 
-    if let Some(..) = state.try_get(&name).await {
-        return make_error(401, format!("{name} already exists"));
+    let path = match state.path_for(&name) {
+        Some(path) => path,
+        None => return make_error(400, format!("invalid name: {name}")),
     };
 
-    let brain = Brain::new(name.clone(), depth.unwrap());
-    let out = spawn_brain(brain, path.clone());
-    state.brains.lock().await.insert(
-        PathBuf::from(path)
-            .file_stem()
-            .expect("valid path") // TODO not this
-            .to_string_lossy()
-            .to_string(),
-        Messaging::new(out),
-    );
+    let depth = depth.unwrap_or(DEFAULT_DEPTH);
+    if !(1..=MAX_DEPTH).contains(&depth) {
+        return make_error(400, format!("depth must be between 1 and {MAX_DEPTH}"));
+    }
+
+    // a brain that failed to load (or was removed from the server) still has its file
+    if tokio::fs::metadata(&path).await.is_ok() {
+        return make_error(409, format!("{name} already exists"));
+    }
+
+    let brain = {
+        let mut brains = state.brains.lock().await;
+        if brains.contains_key(&name) || state.is_reserved(&name) {
+            return make_error(409, format!("{name} already exists"));
+        }
+
+        let brain = Brain::new(name.clone(), depth);
+        let journal = match Journal::create(Journal::path_for(&path), brain.metadata()) {
+            Ok(journal) => journal,
+            Err(err) => {
+                return make_error(503, format!("cannot create a journal for {name}: {err}"))
+            }
+        };
+
+        let brain = spawn_brain(brain, path, journal, state.save_options, state.limits);
+        brains.insert(name.clone(), brain.clone());
+        brain
+    };
 
-    let brain = state.try_get(&name).await.unwrap();
     match brain.send(ForceSave).await {
         Error { error } => make_error(503, format!("cannot save {name}: {error}")),
         _ => StatusCode::OK.into_response(),
     }
 }
 
-fn json<T: serde::Serialize + 'static + Send>(data: T) -> Response {
+pub async fn list(auth: Auth<auth::Generate>, state: Extension<State>) -> impl IntoResponse {
+    let mut brains = state
+        .brains
+        .lock()
+        .await
+        .keys()
+        .filter(|name| auth.allows(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    brains.sort();
+    json(response::Brains { brains })
+}
+
+/// Sends every line the brain is trained on, and everything generated from it, as server-sent events
+///
+/// Each event is named for its kind, with a JSON [`response::Event`] as its data. A client
+/// that falls too far behind is sent a `lagged` event with how many events it missed.
+/// The stream ends when the brain is removed, or the server shuts down.
+pub async fn stream(
+    _: Auth<auth::Generate>,
+    Path(name): Path<String>,
+    state: Extension<State>,
+) -> Response {
+    let brain = match state.try_get(&name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    let shutdown = state.shutdown.clone();
+    let events = futures_util::stream::unfold(brain.subscribe(), |mut rx| async move {
+        let event = match rx.recv().await {
+            Ok(event) => sse::Event::default()
+                .event(event.kind())
+                .json_data(&event)
+                .unwrap_or_default(),
+            Err(RecvError::Lagged(missed)) => sse::Event::default()
+                .event("lagged")
+                .data(missed.to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok::<_, Infallible>(event), rx))
+    })
+    // otherwise a connected client would keep the server from shutting down
+    .take_until(async move { shutdown.wait().await });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Every brain's metrics, in the Prometheus text format
+pub async fn metrics(auth: Auth<auth::Generate>, state: Extension<State>) -> impl IntoResponse {
+    let brains = state.brains.lock().await.clone();
+    let body = metrics::render(
+        brains
+            .iter()
+            .filter(|(name, _)| auth.allows(name))
+            .map(|(name, brain)| (&**name, brain.metrics())),
+    );
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+/// Describes every endpoint, this doesn't need a key
+pub async fn openapi() -> impl IntoResponse {
+    json(openapi::document())
+}
+
+pub async fn info(
+    _: Auth<auth::Generate>,
+    Path(name): Path<String>,
+    Query(request::Info { top }): Query<request::Info>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    use messaging::{Request::*, Response::*};
+
+    let brain = match state.try_get(&name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    match brain
+        .request(Describe {
+            top: top.unwrap_or(10),
+        })
+        .await
+    {
+        Ok(Described { info }) => json(info),
+        Ok(Error { error }) => make_error(503, error),
+        Err(err) => rejected(err),
+        _ => StatusCode::OK.into_response(),
+    }
+}
+
+/// Stops the brain and removes its file and journal, any backups are kept
+pub async fn delete(
+    _: Auth<auth::Admin>,
+    Path(name): Path<String>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    use messaging::{Request::*, Response::*};
+
+    // the name is held until the brain is gone, so it can't be renamed meanwhile
+    let _reserved = match state.reserve(&name) {
+        Some(reserved) => reserved,
+        None => return make_error(409, format!("{name} is being renamed")),
+    };
+    let brain = match state.try_get(&name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    // a brain that couldn't be deleted keeps running, and keeps its name
+    if let Error { error } = brain.send(Delete).await {
+        return make_error(503, format!("cannot delete {name}: {error}"));
+    }
+    state.brains.lock().await.remove(&name);
+    StatusCode::OK.into_response()
+}
+
+pub async fn rename(
+    auth: Auth<auth::Admin>,
+    Path(name): Path<String>,
+    Json(request::Rename { name: new }): Json<request::Rename>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    use messaging::{Request::*, Response::*};
+
+    let path = match state.path_for(&new) {
+        Some(path) => path,
+        None => return make_error(400, format!("invalid name: {new}")),
+    };
+
+    if !auth.allows(&new) {
+        return make_error(403, format!("this key can't use {new}"));
+    }
+
+    // both names are held while the brain is moved, without holding up every other brain
+    let _reserved = match (state.reserve(&name), state.reserve(&new)) {
+        (Some(old), Some(new)) => (old, new),
+        (None, ..) => return make_error(409, format!("{name} is already being renamed")),
+        (.., None) => return make_error(409, format!("{new} already exists")),
+    };
+
+    let brain = {
+        let brains = state.brains.lock().await;
+        if brains.contains_key(&new) {
+            return make_error(409, format!("{new} already exists"));
+        }
+        match brains.get(&name) {
+            Some(brain) => brain.clone(),
+            None => return make_error(404, format!("cannot find {name}")),
+        }
+    };
+    if tokio::fs::metadata(&path).await.is_ok() {
+        return make_error(409, format!("{new} already exists"));
+    }
+
+    // this waits for the brain, so the map can't go out of sync with what's on disk
+    if let Error { error } = brain
+        .send(Rename {
+            name: new.clone(),
+            path: path.clone(),
+        })
+        .await
+    {
+        return make_error(503, format!("cannot rename {name}: {error}"));
+    }
+
+    brain.moved(path).await;
+    let mut brains = state.brains.lock().await;
+    // a brain that was deleted meanwhile stays deleted
+    if brains.remove(&name).is_some() {
+        brains.insert(new, brain);
+    }
+    StatusCode::OK.into_response()
+}
+
+/// Loads the brain from disk again, replaying its journal
+///
+/// If the file was replaced, the journal (which belongs to the old file) is discarded.
+pub async fn reload(
+    _: Auth<auth::Admin>,
+    Path(name): Path<String>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    send_to(&name, messaging::Request::Reload, "reload", &state).await
+}
+
+pub async fn save(
+    _: Auth<auth::Admin>,
+    Path(name): Path<String>,
+    state: Extension<State>,
+) -> impl IntoResponse {
+    send_to(&name, messaging::Request::ForceSave, "save", &state).await
+}
+
+async fn send_to(name: &str, req: messaging::Request, action: &str, state: &State) -> Response {
+    use messaging::Response::*;
+
+    let brain = match state.try_get(name).await {
+        Some(brain) => brain,
+        None => return make_error(404, format!("cannot find {name}")),
+    };
+
+    match brain.request(req).await {
+        Ok(Error { error }) => make_error(503, format!("cannot {action} {name}: {error}")),
+        Err(err) => rejected(err),
+        _ => StatusCode::OK.into_response(),
+    }
+}
+
+pub(crate) fn json<T: serde::Serialize + 'static + Send>(data: T) -> Response {
     Json(data).into_response()
 }
 
-fn make_error(code: u16, err: impl ToString + Send) -> Response {
+/// Writes an event for every request, once it has been handled
+pub(crate) async fn trace_request(req: Request<Body>, next: Next<Body>) -> Response {
+    let (method, uri) = (req.method().clone(), req.uri().clone());
+    let start = std::time::Instant::now();
+    let resp = next.run(req).await;
+    tracing::debug!(
+        status = resp.status().as_u16(),
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "{method} {uri}"
+    );
+    resp
+}
+
+/// Turns away any body larger than the configured limit
+///
+/// A body with a `Content-Length` is checked before it's read, anything else
+/// fails once more than the limit has been read.
+pub(crate) async fn limit_body(req: Request<Body>, next: Next<Body>) -> Response {
+    let limit = req
+        .extensions()
+        .get::<State>()
+        .expect("state should be available")
+        .limits
+        .body;
+
+    let length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|s| s.to_str().ok())
+        .and_then(|s| s.parse::<usize>().ok());
+    if length > Some(limit) {
+        return make_error(413, BodyTooLarge { limit });
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = futures_util::stream::unfold(Some((body, limit)), move |state| async move {
+        let (mut body, left) = state?;
+        match body.data().await? {
+            Ok(chunk) if chunk.len() <= left => {
+                let left = left - chunk.len();
+                Some((Ok(chunk), Some((body, left))))
+            }
+            Ok(..) => Some((Err(BoxError::from(BodyTooLarge { limit })), None)),
+            Err(err) => Some((Err(err.into()), None)),
+        }
+    });
+    next.run(Request::from_parts(parts, Body::wrap_stream(body)))
+        .await
+}
+
+#[derive(Debug)]
+struct BodyTooLarge {
+    limit: usize,
+}
+
+impl std::fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the body is larger than {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+fn too_large(err: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |err| err.source()).any(|err| err.is::<BodyTooLarge>())
+}
+
+// a memory-mapped brain can't do what was asked, anything else is the brain failing
+fn brain_error(error: anyhow::Error) -> Response {
+    match error.is::<IsMapped>() {
+        true => make_error(409, error),
+        false => make_error(503, error),
+    }
+}
+
+// a brain that's fine can still have nothing to generate from, or not meet the options
+fn generate_error(error: anyhow::Error) -> Response {
+    match error.downcast_ref::<NotGenerated>() {
+        Some(err) => make_error(err.status(), error),
+        None => make_error(503, error),
+    }
+}
+
+fn rejected(err: Rejected) -> Response {
+    make_error(err.status(), err)
+}
+
+pub(crate) fn make_error(code: u16, err: impl ToString + Send) -> Response {
     let status_code = StatusCode::from_u16(code).expect("valid status code");
     let json = json(response::Error {
         msg: err.to_string(),
     });
     (status_code, json).into_response()
 }
+
+#[cfg(test)]
+mod tests;
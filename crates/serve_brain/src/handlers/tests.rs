@@ -0,0 +1,455 @@
+use std::{path::Path, sync::Arc};
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use markov::format::{self, SaveOptions};
+use serde_json::Value;
+use tower::ServiceExt;
+
+use super::*;
+
+fn app(dir: &Path) -> (Router, State) {
+    app_with(dir, Filters::default())
+}
+
+fn app_with(dir: &Path, filters: Filters) -> (Router, State) {
+    let state = State {
+        directory: dir.to_path_buf(),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::new(filters),
+        keys: Arc::default(),
+        limits: Default::default(),
+        shutdown: Default::default(),
+    };
+    (crate::router(state.clone()), state)
+}
+
+async fn call(app: &Router, method: Method, uri: &str, body: &str) -> (StatusCode, String) {
+    send(app, method, uri, "application/json", body.to_string()).await
+}
+
+async fn send(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    content_type: &str,
+    body: impl Into<Body>,
+) -> (StatusCode, String) {
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", content_type)
+        .body(body.into())
+        .unwrap();
+
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let mut body = resp.into_body();
+    let mut data = vec![];
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk.unwrap());
+    }
+    (status, String::from_utf8(data).unwrap())
+}
+
+async fn info(app: &Router, name: &str) -> Value {
+    let (status, body) = call(app, Method::GET, &format!("/{name}"), "").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    serde_json::from_str(&body).unwrap()
+}
+
+// creates `name` and trains it on `lines`, without saving them
+async fn trained(app: &Router, name: &str, lines: &[&str]) {
+    let (status, _) = call(app, Method::POST, &format!("/{name}/create"), "{}").await;
+    assert_eq!(status, StatusCode::OK);
+    for line in lines {
+        let body = serde_json::json!({ "data": line }).to_string();
+        let (status, _) = call(app, Method::POST, &format!("/{name}/train"), &body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn save() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = app(dir.path());
+    trained(&app, "museun", &["hello world"]).await;
+    assert_eq!(info(&app, "museun").await["journaled"], 1);
+
+    let (status, _) = call(&app, Method::POST, "/museun/save", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info(&app, "museun").await["journaled"], 0);
+
+    let saved = format::load_file(dir.path().join("museun.sdb")).unwrap();
+    assert_eq!(saved.metadata().lines, 1);
+
+    let (status, _) = call(&app, Method::POST, "/missing/save", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reload() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = app(dir.path());
+    trained(&app, "museun", &["hello world"]).await;
+
+    // the file is replaced behind the server's back
+    let path = dir.path().join("museun.sdb");
+    let mut brain = format::load_file(&path).unwrap();
+    brain.train("the stream is live");
+    brain.train("the chat is happy");
+    format::save_file(&brain, &path, &SaveOptions::default()).unwrap();
+
+    let (status, _) = call(&app, Method::POST, "/museun/reload", "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // the journal belonged to the old file, so it was discarded
+    let info = info(&app, "museun").await;
+    assert_eq!(info["metadata"]["lines"], 2);
+    assert_eq!(info["journaled"], 0);
+
+    let (status, _) = call(&app, Method::POST, "/missing/reload", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn delete() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = app(dir.path());
+    trained(&app, "museun", &["hello world"]).await;
+
+    let path = dir.path().join("museun.sdb");
+    assert!(path.exists() && Journal::path_for(&path).exists());
+
+    // a brain that can't be deleted is kept
+    std::fs::rename(&path, dir.path().join("moved")).unwrap();
+    std::fs::create_dir(&path).unwrap();
+    let (status, _) = call(&app, Method::DELETE, "/museun", "").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let (_, body) = call(&app, Method::GET, "/brains", "").await;
+    assert_eq!(body, r#"{"brains":["museun"]}"#);
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 1);
+    std::fs::remove_dir(&path).unwrap();
+    std::fs::rename(dir.path().join("moved"), &path).unwrap();
+
+    let (status, _) = call(&app, Method::DELETE, "/museun", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!path.exists() && !Journal::path_for(&path).exists());
+
+    let (status, _) = call(&app, Method::GET, "/museun", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&app, Method::DELETE, "/museun", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // the name can be used again
+    trained(&app, "museun", &[]).await;
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 0);
+}
+
+#[tokio::test]
+async fn rename() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, state) = app(dir.path());
+    trained(&app, "museun", &["hello world"]).await;
+    trained(&app, "taken", &[]).await;
+
+    let rename = |name: &str, new: &str| {
+        let (app, uri) = (app.clone(), format!("/{name}/rename"));
+        let body = serde_json::json!({ "name": new }).to_string();
+        async move { call(&app, Method::POST, &uri, &body).await.0 }
+    };
+
+    assert_eq!(rename("museun", "taken").await, StatusCode::CONFLICT);
+    assert_eq!(rename("museun", "metrics").await, StatusCode::BAD_REQUEST);
+    assert_eq!(rename("missing", "other").await, StatusCode::NOT_FOUND);
+
+    // a name that's being taken can't be taken again
+    let reserved = state.reserve("other").unwrap();
+    assert_eq!(rename("museun", "other").await, StatusCode::CONFLICT);
+    let (status, _) = call(&app, Method::POST, "/other/create", "{}").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    drop(reserved);
+
+    assert_eq!(rename("museun", "other").await, StatusCode::OK);
+    assert!(!state.is_reserved("museun") && !state.is_reserved("other"));
+
+    let (status, _) = call(&app, Method::GET, "/museun", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let info = info(&app, "other").await;
+    assert_eq!(info["metadata"]["name"], "other");
+    assert_eq!(info["metadata"]["lines"], 1);
+
+    let (old, new) = (dir.path().join("museun.sdb"), dir.path().join("other.sdb"));
+    assert!(!old.exists() && !Journal::path_for(&old).exists());
+    assert_eq!(format::load_file(&new).unwrap().name(), "other");
+
+    let (_, body) = call(&app, Method::GET, "/brains", "").await;
+    assert_eq!(body, r#"{"brains":["other","taken"]}"#);
+}
+
+#[tokio::test]
+async fn train_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    let filters = Filters {
+        min_words: 2,
+        ..Filters::default()
+    };
+    let (app, _) = app_with(dir.path(), filters);
+    trained(&app, "museun", &[]).await;
+
+    let batch = |content_type, body: &'static str| {
+        send(
+            &app,
+            Method::POST,
+            "/museun/train/batch",
+            content_type,
+            body,
+        )
+    };
+    let trained = |body: &str| serde_json::from_str::<Value>(body).unwrap();
+
+    // lines with a single word are filtered out
+    let (status, body) = batch("application/json", r#"["the stream is live", "hi"]"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        trained(&body),
+        serde_json::json!({ "trained": 1, "skipped": 1 })
+    );
+
+    let (status, body) = batch(
+        "application/json",
+        r#"{"data": ["the chat is happy", "today the stream is live"], "extra": true}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        trained(&body),
+        serde_json::json!({ "trained": 2, "skipped": 0 })
+    );
+
+    let (status, body) = batch("text/plain", "hello world\nhi\n\nbye for now").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        trained(&body),
+        serde_json::json!({ "trained": 2, "skipped": 2 })
+    );
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 5);
+
+    for invalid in [
+        r#"["unfinished"#,
+        r#"{"lines": []}"#,
+        r#"[1, 2]"#,
+        r#"[] []"#,
+    ] {
+        let (status, _) = batch("application/json", invalid).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{invalid}");
+    }
+
+    // text has to be utf-8
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/museun/train/batch",
+        "text/plain",
+        vec![0xff, 0xfe],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn train_batch_streamed() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = app(dir.path());
+    trained(&app, "museun", &[]).await;
+
+    // both are read chunk by chunk, lines can be split across them
+    let chunks = |chunks: Vec<&'static str>| {
+        Body::wrap_stream(futures_util::stream::iter(
+            chunks.into_iter().map(Ok::<_, Infallible>),
+        ))
+    };
+    let batch = |content_type, body| {
+        send(
+            &app,
+            Method::POST,
+            "/museun/train/batch",
+            content_type,
+            body,
+        )
+    };
+
+    let body = chunks(vec![
+        r#"["the stream "#,
+        r#"is live", "the chat"#,
+        r#" is happy"]"#,
+    ]);
+    let (status, body) = batch("application/json", body).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, r#"{"trained":2,"skipped":0}"#);
+
+    let body = chunks(vec!["hello ", "world\nbye", " for now\n"]);
+    let (status, body) = batch("text/plain", body).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, r#"{"trained":2,"skipped":0}"#);
+
+    // a line that never ends isn't buffered forever
+    let long = "a".repeat(MAX_LINE + 1);
+    let (status, _) = batch("text/plain", Body::from(long.clone())).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let long = serde_json::json!([long]).to_string();
+    let (status, _) = batch("application/json", Body::from(long)).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 4);
+}
+
+#[tokio::test]
+async fn candidates() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = app(dir.path());
+    trained(
+        &app,
+        "museun",
+        &[
+            "the stream is live",
+            "the chat is happy",
+            "today the stream is happy",
+        ],
+    )
+    .await;
+
+    let candidates = |mut body: Value| {
+        let app = app.clone();
+        body["min"] = 1.into();
+        body["max"] = 8.into();
+        async move {
+            let body = body.to_string();
+            call(&app, Method::GET, "/museun/generate/candidates", &body).await
+        }
+    };
+
+    let (status, body) = candidates(serde_json::json!({ "count": 3, "seed": 7 })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let body: Value = serde_json::from_str(&body).unwrap();
+    let mut seeds = body["candidates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|candidate| candidate["seed"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    seeds.sort_unstable();
+    assert_eq!(seeds, [7, 8, 9]);
+
+    // the same seeds give the same sentences
+    let (_, again) = candidates(serde_json::json!({ "count": 3, "seed": 7 })).await;
+    assert_eq!(serde_json::from_str::<Value>(&again).unwrap(), body);
+
+    for count in [0, MAX_CANDIDATES + 1] {
+        let (status, _) = candidates(serde_json::json!({ "count": count })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    // every sentence has a `the` in it
+    let (status, _) = candidates(serde_json::json!({ "forbidden": ["the"] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn not_generated() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, _) = app(dir.path());
+    trained(&app, "museun", &[]).await;
+
+    let generate = |mut body: Value| {
+        body["min"] = 1.into();
+        body["max"] = 8.into();
+        let (app, body) = (&app, body.to_string());
+        async move { call(app, Method::GET, "/museun/generate", &body).await }
+    };
+
+    // there's nothing to generate from, but the brain is fine
+    let (status, _) = generate(serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let body = serde_json::json!({ "data": "the stream is live" }).to_string();
+    let (status, _) = call(&app, Method::POST, "/museun/train", &body).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = generate(serde_json::json!({ "query": "kappa" })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = generate(serde_json::json!({ "forbidden": ["the"] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = generate(serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn mapped() {
+    let dir = tempfile::tempdir().unwrap();
+    let (app, state) = app(dir.path());
+
+    let path = dir.path().join("museun.sdbm");
+    markov::FrozenBrain::save_file(&Brain::new("museun", 2), &path, 0).unwrap();
+    let brain = crate::open_brain(&path, "museun", SaveOptions::default(), state.limits)
+        .await
+        .unwrap();
+    state.brains.lock().await.insert("museun".into(), brain);
+
+    let line = r#"{"data":"hello world"}"#;
+    let (status, _) = call(&app, Method::POST, "/museun/train", line).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // only a decoded brain can forget or remove links
+    let (status, body) = call(&app, Method::POST, "/museun/forget", line).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{body}");
+    let (status, _) = call(&app, Method::POST, "/museun/prune", r#"{"min_count":2}"#).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 1);
+
+    // saving compacts the brain into a new file
+    let (status, _) = call(&app, Method::POST, "/museun/save", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info(&app, "museun").await["journaled"], 0);
+    assert!(markov::MappedBrain::open(&path)
+        .unwrap()
+        .was_trained_on("hello world"));
+}
+
+#[tokio::test]
+async fn forget_is_filtered() {
+    let dir = tempfile::tempdir().unwrap();
+    let filters = Filters {
+        mentions: true,
+        min_words: 2,
+        ..Filters::default()
+    };
+    let (app, _) = app_with(dir.path(), filters);
+    trained(
+        &app,
+        "museun",
+        &["@museun the stream is live", "the chat is happy"],
+    )
+    .await;
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 2);
+
+    // a line the filters drop was never trained, so there's nothing to forget
+    let forget = |data: &str| {
+        let (app, body) = (&app, serde_json::json!({ "data": data }).to_string());
+        async move { call(app, Method::POST, "/museun/forget", &body).await }
+    };
+    let (status, _) = forget("@museun hi").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 2);
+
+    let (status, _) = forget("@museun the stream is live").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info(&app, "museun").await["metadata"]["lines"], 1);
+}
@@ -1,79 +1,326 @@
 use anyhow::Context;
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{get, post, MethodRouter},
     Extension, Router, Server,
 };
-use futures::{SinkExt, StreamExt};
-use markov::Brain;
-use std::{path::Path, time::Duration};
+use markov::{
+    format::{self, journal::Journal, SaveOptions},
+    Brain, FrozenBrain, MappedBrain,
+};
+use std::{future::Future, path::Path, sync::Arc, time::Duration};
 
+pub mod auth;
+pub mod metrics;
+pub mod openapi;
 pub mod state;
 
+mod batch;
 mod handlers;
 
 mod messaging;
-pub use messaging::{spawn_brain, Messaging};
+pub use messaging::{spawn_brain, Messaging, NotGenerated, Rejected, Request, Response};
+
+mod model;
+pub use model::{IsMapped, Model};
 
-mod request;
-mod response;
+pub use brain_types::{request, response, MAX_RETRIES, MAX_WORDS};
 
 pub const SAVE_DURATION: Duration = Duration::from_secs(5 * 60);
+/// How long generating a single sentence can take, unless it's configured
 pub const GENERATE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The depth of a new brain when none is given
+pub const DEFAULT_DEPTH: usize = 5;
+pub const MAX_DEPTH: usize = 16;
+/// The most sentences a single generate request can ask for
+pub const MAX_CANDIDATES: usize = 32;
+/// The longest line `/:name/train/batch` trains, in bytes
+pub const MAX_LINE: usize = 64 * 1024;
+/// How many events a `/:name/stream` client can fall behind before it misses some
+pub const STREAM_CAPACITY: usize = 256;
 
+/// Serves every brain until SIGINT or SIGTERM, then saves them
+///
+/// Requests that were already being handled are finished first, and streams are
+/// ended. Every brain then has `save_timeout` to save, any that couldn't are listed
+/// in the error.
 pub async fn start_server(
     addr: impl tokio::net::ToSocketAddrs + Send + 'static,
     state: state::State,
+    save_timeout: Duration,
 ) -> anyhow::Result<()> {
     let addr = tokio::net::lookup_host(addr)
         .await?
         .next()
         .with_context(|| "could not resolve an addr")?;
 
-    let app = Router::new()
-        .route("/:name/generate", get(handlers::generate))
-        .route("/:name/train", post(handlers::train))
-        .route("/:name/create", post(handlers::create))
-        .layer(Extension(state));
+    let listener =
+        std::net::TcpListener::bind(addr).with_context(|| format!("cannot listen on {addr}"))?;
+    serve(listener, state, save_timeout, shutdown_signal()).await
+}
 
-    Server::bind(&addr).serve(app.into_make_service()).await?;
-    Ok(())
+/// Serves every brain on `listener` until `signal` completes, see [`start_server`]
+async fn serve(
+    listener: std::net::TcpListener,
+    state: state::State,
+    save_timeout: Duration,
+    signal: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    tracing::info!("listening on {}", listener.local_addr()?);
+    let shutdown = state.shutdown.clone();
+    Server::from_tcp(listener)?
+        .serve(router(state.clone()).into_make_service())
+        .with_graceful_shutdown(async move {
+            signal.await;
+            shutdown.trigger();
+        })
+        .await?;
+
+    tracing::info!("shutting down, saving every brain");
+    let failed = save_all(&state, save_timeout).await;
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    let mut msg = format!("cannot save {} brain(s):", failed.len());
+    for (name, err) in failed {
+        msg.push_str(&format!("\n  {name}: {err}"));
+    }
+    Err(anyhow::anyhow!(msg))
+}
+
+/// Waits for SIGINT, or SIGTERM on unix
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(..) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Saves every brain at once, giving each of them up to `timeout`
+///
+/// Returns the name of each brain that couldn't be saved, and why.
+pub async fn save_all(state: &state::State, timeout: Duration) -> Vec<(String, anyhow::Error)> {
+    let brains = state.brains.lock().await.clone();
+    let saves = brains
+        .into_iter()
+        .map(|(name, brain)| {
+            let save = tokio::spawn(async move {
+                let resp = tokio::time::timeout(timeout, brain.send(messaging::Request::ForceSave));
+                match resp.await {
+                    Ok(messaging::Response::Error { error }) => Err(error),
+                    Ok(..) => Ok(()),
+                    Err(..) => Err(anyhow::anyhow!("timed out after {timeout:?}")),
+                }
+            });
+            (name, save)
+        })
+        .collect::<Vec<_>>();
+
+    let mut failed = vec![];
+    for (name, save) in saves {
+        match save.await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => failed.push((name, err)),
+            Err(err) => failed.push((name, err.into())),
+        }
+    }
+    failed.sort_by(|(left, _), (right, _)| left.cmp(right));
+    failed
+}
+
+/// Every endpoint, without a server
+pub fn router(state: state::State) -> Router {
+    routes()
+        .into_iter()
+        .fold(Router::new(), |router, (path, route)| {
+            router.route(path, route)
+        })
+        .layer(middleware::from_fn(handlers::limit_body))
+        .layer(middleware::from_fn(handlers::trace_request))
+        .layer(Extension(state))
+}
+
+// every path that's served, and how. each of them is documented in `openapi`
+fn routes() -> Vec<(&'static str, MethodRouter)> {
+    vec![
+        ("/:name/generate", get(handlers::generate)),
+        ("/:name/generate/candidates", get(handlers::candidates)),
+        ("/:name/train", post(handlers::train)),
+        ("/:name/train/batch", post(handlers::train_batch)),
+        ("/:name/create", post(handlers::create)),
+        ("/:name/forget", post(handlers::forget)),
+        ("/:name/prune", post(handlers::prune)),
+        ("/:name/decay", post(handlers::decay)),
+        ("/brains", get(handlers::list)),
+        ("/metrics", get(handlers::metrics)),
+        ("/openapi.json", get(handlers::openapi)),
+        ("/:name", get(handlers::info).delete(handlers::delete)),
+        ("/:name/rename", post(handlers::rename)),
+        ("/:name/reload", post(handlers::reload)),
+        ("/:name/save", post(handlers::save)),
+        ("/:name/stream", get(handlers::stream)),
+    ]
+}
+
+/// Periodically decays every brain so older data fades
+pub async fn decay_brains(state: state::State, interval: Duration, factor: f64) {
+    let mut interval = tokio::time::interval(interval);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let brains = state.brains.lock().await.clone();
+        for (name, brain) in &brains {
+            if let messaging::Response::Error { error } =
+                brain.send(messaging::Request::Decay { factor }).await
+            {
+                tracing::warn!(brain = %name, "cannot decay: {error}");
+            }
+        }
+    }
+}
+
+/// Periodically saves every brain that was trained since it was last saved, emptying its journal
+pub async fn save_brains(state: state::State) {
+    let mut interval = tokio::time::interval(SAVE_DURATION);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let brains = state.brains.lock().await.clone();
+        for (name, brain) in &brains {
+            if let messaging::Response::Error { error } = brain.send(messaging::Request::Save).await
+            {
+                tracing::warn!(brain = %name, "cannot save: {error}");
+            }
+        }
+    }
+}
+
+/// Starts a thread for the brain saved at `path`, replaying its journal
+///
+/// If there's no brain there yet, a new one called `name` is started instead. It's
+/// saved to `path` straight away, like a created brain, so the journal matches it
+/// the next time it's opened.
+pub async fn open_brain(
+    path: impl AsRef<Path> + Send,
+    name: &str,
+    options: SaveOptions,
+    limits: state::Limits,
+) -> anyhow::Result<Messaging> {
+    let path = path.as_ref().to_owned();
+    let (brain, created) = match tokio::fs::metadata(&path).await {
+        Ok(..) => (load_model(&path).await?, false),
+        Err(..) => (Brain::new(name, DEFAULT_DEPTH).into(), true),
+    };
+    let (brain, journal) = replay(brain, &path).await?;
+    let brain = spawn_brain(brain, path, journal, options, limits);
+
+    if created {
+        if let messaging::Response::Error { error } =
+            brain.send(messaging::Request::ForceSave).await
+        {
+            return Err(error.context(format!("cannot save {name}")));
+        }
+    }
+    Ok(brain)
 }
 
+/// Loads a brain, falling back to its newest valid backup if the file is corrupt
 pub async fn load(path: impl AsRef<Path> + Send) -> anyhow::Result<Brain> {
-    let reader = tokio::io::BufReader::new(tokio::fs::File::open(path).await?);
+    // legacy brains are migrated to the current format on their next save
+    let path = path.as_ref().to_owned();
+    let brain = tokio::task::spawn_blocking(move || format::load_file(path)).await??;
+    Ok(brain)
+}
 
-    let dec = async_compression::tokio::bufread::ZstdDecoder::new(reader);
-    let mut reader = async_bincode::tokio::AsyncBincodeReader::from(dec);
+/// Loads a brain, memory-mapping it if it's in the mapped layout (a `.sdbm` file)
+///
+/// Either layout falls back to its newest valid backup if the file is corrupt.
+pub async fn load_model(path: impl AsRef<Path> + Send) -> anyhow::Result<Model> {
+    let path = path.as_ref().to_owned();
+    tokio::task::spawn_blocking(move || load_model_sync(&path)).await?
+}
 
-    let element: Brain = reader
-        .next()
-        .await
-        .with_context(|| "cannot deserialize brain")??;
+pub fn load_model_sync(path: &Path) -> anyhow::Result<Model> {
+    // legacy brains are migrated to the current format on their next save
+    Ok(match is_mapped(path) {
+        true => Model::Mapped(MappedBrain::load_file(path)?),
+        false => Model::Owned(format::load_file(path)?),
+    })
+}
 
-    Ok(element)
+/// Replays the journal of the brain saved at `path`, returning the journal to continue with
+pub async fn replay(
+    brain: Model,
+    path: impl AsRef<Path> + Send,
+) -> anyhow::Result<(Model, Journal)> {
+    let path = path.as_ref().to_owned();
+    tokio::task::spawn_blocking(move || replay_sync(brain, &path)).await?
 }
 
-pub async fn save(brain: &Brain, path: impl AsRef<Path> + Send) -> anyhow::Result<()> {
-    let writer = tokio::io::BufWriter::new(tokio::fs::File::create(path).await?);
-    let enc = async_compression::tokio::write::ZstdEncoder::new(writer);
-    let mut writer = async_bincode::tokio::AsyncBincodeWriter::from(enc);
-    writer.send(brain).await?;
-    Ok(())
+pub fn replay_sync(mut brain: Model, path: &Path) -> anyhow::Result<(Model, Journal)> {
+    let meta = brain.metadata();
+    Journal::migrate(path, &meta)?;
+    let journal = Journal::open(Journal::path_for(path), &meta, |entry| brain.apply(entry))?;
+    Ok((brain, journal))
+}
+
+/// Atomically saves a brain, keeping the previous file as a backup
+pub async fn save(
+    brain: Arc<Brain>,
+    path: impl AsRef<Path> + Send,
+    options: SaveOptions,
+) -> anyhow::Result<()> {
+    let path = path.as_ref().to_owned();
+    tokio::task::spawn_blocking(move || save_sync(&brain, path, &options)).await?
 }
 
-pub fn save_sync(brain: &Brain, path: impl AsRef<Path>) -> anyhow::Result<()> {
-    let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
-    let enc = zstd::Encoder::new(writer, 0)?;
-    bincode::serialize_into(enc, brain)?;
+pub fn save_sync(
+    brain: &Brain,
+    path: impl AsRef<Path>,
+    options: &SaveOptions,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if is_mapped(path) {
+        FrozenBrain::save_file(brain, path, options.backups)?;
+    } else {
+        format::save_file(brain, path, options)?;
+    }
     Ok(())
 }
 
+fn is_mapped(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some("sdbm")
+}
+
 pub trait BrainExt {
-    fn save(&self, path: &Path) -> anyhow::Result<()>;
+    fn save(&self, path: &Path, options: &SaveOptions) -> anyhow::Result<()>;
 }
 
 impl BrainExt for Brain {
-    fn save(&self, path: &Path) -> anyhow::Result<()> {
-        save_sync(self, path)
+    fn save(&self, path: &Path, options: &SaveOptions) -> anyhow::Result<()> {
+        save_sync(self, path, options)
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -1,76 +1,530 @@
 use std::{
     path::{Path, PathBuf},
-    time::Instant,
+    sync::{atomic::Ordering, Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, Instant},
 };
 
-use anyhow::Context;
-use markov::Brain;
-use tokio::sync::{mpsc::Sender, oneshot};
+use markov::{
+    format::{
+        journal::{Entry, Journal},
+        SaveOptions,
+    },
+    GenerateOutcome, GenerateRequest,
+};
+use tokio::sync::{
+    broadcast,
+    mpsc::{error::TrySendError, Sender},
+    oneshot, Mutex, Semaphore,
+};
+
+use crate::{
+    metrics::Metrics, model::Model, request, response, response::Event, state::Limits,
+    STREAM_CAPACITY,
+};
 
-use crate::{request, BrainExt, GENERATE_TIMEOUT, SAVE_DURATION};
+type Channel = Sender<(Request, oneshot::Sender<Response>)>;
 
+/// A handle to a brain thread, which is started again if it dies
+///
+/// Only the brain thread changes the brain, everything that just reads it
+/// (like generating) runs alongside it, and alongside each other.
 #[derive(Clone)]
 pub struct Messaging {
-    tx: Sender<(Request, oneshot::Sender<Response>)>,
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    thread: Mutex<Thread>,
+    path: Mutex<PathBuf>,
+    readers: Arc<Semaphore>,
+    metrics: Arc<Metrics>,
+    events: broadcast::Sender<Event>,
+    options: SaveOptions,
+    limits: Limits,
+}
+
+// the running brain thread, and the brain it writes to
+#[derive(Clone)]
+struct Thread {
+    tx: Channel,
+    brain: Arc<RwLock<Model>>,
+}
+
+/// Why a brain didn't respond to a request
+#[derive(Debug)]
+pub enum Rejected {
+    /// Too many requests are already waiting for the brain
+    Busy,
+    /// The brain didn't respond in time
+    TimedOut,
+    /// The brain didn't get to a change in time, it's still queued and will be applied
+    Queued,
+    /// The brain thread died, and was started again from what's on disk
+    Restarted,
+    /// The brain thread died, and couldn't be started again
+    Stopped(anyhow::Error),
+}
+
+impl Rejected {
+    pub const fn status(&self) -> u16 {
+        match self {
+            Self::Busy => 429,
+            Self::TimedOut => 504,
+            Self::Queued => 202,
+            Self::Restarted | Self::Stopped(..) => 503,
+        }
+    }
 }
 
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Busy => f.write_str("the brain is busy, try again later"),
+            Self::TimedOut => f.write_str("the brain took too long to respond"),
+            Self::Queued => f.write_str(
+                "the brain is still busy with the change, it will be applied so don't send it again",
+            ),
+            Self::Restarted => f.write_str("the brain stopped, it was loaded from disk again"),
+            Self::Stopped(err) => write!(f, "the brain stopped, and cannot be loaded: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Why the brain didn't generate anything
+///
+/// This is down to what the brain was trained on and what was asked of it, the
+/// brain itself is fine.
+#[derive(Debug)]
+pub struct NotGenerated(pub GenerateOutcome);
+
+impl NotGenerated {
+    pub fn status(&self) -> u16 {
+        match self.0 {
+            GenerateOutcome::EmptyBrain | GenerateOutcome::UnknownQuery => 404,
+            GenerateOutcome::Unsatisfied => 422,
+            // it ran out of time
+            _ => 504,
+        }
+    }
+}
+
+impl std::fmt::Display for NotGenerated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot generate data: {}", self.0)
+    }
+}
+
+impl std::error::Error for NotGenerated {}
+
 impl Messaging {
-    pub const fn new(tx: Sender<(Request, oneshot::Sender<Response>)>) -> Self {
-        Self { tx }
+    fn new(
+        thread: Thread,
+        path: PathBuf,
+        metrics: Arc<Metrics>,
+        events: broadcast::Sender<Event>,
+        options: SaveOptions,
+        limits: Limits,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                thread: Mutex::new(thread),
+                path: Mutex::new(path),
+                readers: Arc::new(Semaphore::new(limits.readers)),
+                metrics,
+                events,
+                options,
+                limits,
+            }),
+        }
     }
 
+    /// Sends a request, waiting for room in the queue and then for the response
     pub async fn send(&self, req: Request) -> Response {
         let (tx, rx) = oneshot::channel();
-        let _ = self.tx.send((req, tx)).await;
-        rx.await.unwrap()
+        let sender = self.inner.thread.lock().await.tx.clone();
+        let resp = match sender.send((req, tx)).await {
+            Ok(()) => rx.await.ok(),
+            Err(..) => None,
+        };
+        let resp = match resp {
+            Some(resp) => Ok(resp),
+            None => Err(self.restart().await),
+        };
+        self.inner.metrics.observe(&resp);
+        resp.unwrap_or_else(|err| Response::Error { error: err.into() })
+    }
+
+    /// Sends a request, unless the queue is full, waiting up to the request timeout for the response
+    pub async fn request(&self, req: Request) -> Result<Response, Rejected> {
+        let resp = self.dispatch(req).await;
+        self.inner.metrics.observe(&resp);
+        resp
+    }
+
+    /// What happened to the brain since the server started
+    pub fn metrics(&self) -> &Metrics {
+        &self.inner.metrics
+    }
+
+    /// Listens for every line the brain is trained on, and everything generated from it
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.inner.events.subscribe()
+    }
+
+    async fn dispatch(&self, req: Request) -> Result<Response, Rejected> {
+        let changes = req.changes();
+        let (tx, rx) = oneshot::channel();
+        let sender = self.inner.thread.lock().await.tx.clone();
+        match sender.try_send((req, tx)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(..)) => return Err(Rejected::Busy),
+            Err(TrySendError::Closed(..)) => return Err(self.restart().await),
+        }
+
+        match tokio::time::timeout(self.inner.limits.timeout, rx).await {
+            Ok(Ok(resp)) => Ok(resp),
+            Ok(Err(..)) => Err(self.restart().await),
+            // whoever sent it shouldn't send it again
+            Err(..) if changes => Err(Rejected::Queued),
+            Err(..) => Err(Rejected::TimedOut),
+        }
+    }
+
+    /// Generates a sentence, without waiting for the brain thread
+    pub async fn generate(&self, opts: request::Generate) -> Result<Response, Rejected> {
+        let resp = self
+            .read(
+                move |brain, timeout, metrics| match generate(brain, opts, timeout, metrics) {
+                    GenerateOutcome::Generated(data) => Response::Generated { data },
+                    outcome => Response::Error {
+                        error: NotGenerated(outcome).into(),
+                    },
+                },
+            )
+            .await;
+
+        if let Ok(Response::Generated { data }) = &resp {
+            publish(&self.inner.events, || Event::Generated {
+                data: data.clone(),
+            });
+        }
+        resp
+    }
+
+    /// Generates up to `count` sentences, without waiting for the brain thread
+    ///
+    /// They're all generated in the time a single sentence has.
+    pub async fn candidates(
+        &self,
+        opts: request::Generate,
+        count: usize,
+    ) -> Result<Response, Rejected> {
+        let resp = self
+            .read(move |brain, timeout, metrics| {
+                match candidates(brain, opts, count, timeout, metrics) {
+                    Ok(candidates) => Response::Candidates { candidates },
+                    Err(error) => Response::Error { error },
+                }
+            })
+            .await;
+
+        if let Ok(Response::Candidates { candidates }) = &resp {
+            for candidate in candidates {
+                publish(&self.inner.events, || Event::Generated {
+                    data: candidate.data.clone(),
+                });
+            }
+        }
+        resp
+    }
+
+    // reads the brain on the blocking pool, turning the request away if too many are already reading
+    async fn read<F>(&self, func: F) -> Result<Response, Rejected>
+    where
+        F: FnOnce(&Model, Duration, &Metrics) -> Response + Send + 'static,
+    {
+        let resp = self.try_read(func).await;
+        self.inner.metrics.observe(&resp);
+        resp
+    }
+
+    async fn try_read<F>(&self, func: F) -> Result<Response, Rejected>
+    where
+        F: FnOnce(&Model, Duration, &Metrics) -> Response + Send + 'static,
+    {
+        let permit = match self.inner.readers.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(..) => return Err(Rejected::Busy),
+        };
+
+        let brain = self.inner.thread.lock().await.brain.clone();
+        let metrics = Arc::clone(&self.inner.metrics);
+        let timeout = self.inner.limits.generate;
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            // the brain thread is the only writer, so this is only poisoned if it died
+            let brain = brain.read().ok()?;
+            Some(func(&brain, timeout, &metrics))
+        });
+
+        match tokio::time::timeout(self.inner.limits.timeout, task).await {
+            Ok(Ok(Some(resp))) => Ok(resp),
+            Ok(Ok(None)) => Err(self.restart().await),
+            Ok(Err(err)) => Ok(Response::Error {
+                error: anyhow::anyhow!("cannot read the brain: {err}"),
+            }),
+            Err(..) => Err(Rejected::TimedOut),
+        }
+    }
+
+    /// Tells the handle where the brain is now stored, after it was renamed
+    pub async fn moved(&self, path: PathBuf) {
+        *self.inner.path.lock().await = path;
+    }
+
+    // a deleted brain has no file, so it stays stopped
+    async fn restart(&self) -> Rejected {
+        let mut thread = self.inner.thread.lock().await;
+        if !thread.tx.is_closed() {
+            // another request already restarted it
+            return Rejected::Restarted;
+        }
+
+        let path = self.inner.path.lock().await.clone();
+        tracing::warn!(path = %path.display(), "the brain thread stopped, loading it again");
+        let loaded = match crate::load_model(&path).await {
+            Ok(model) => crate::replay(model, &path).await,
+            Err(err) => Err(err),
+        };
+        match loaded {
+            Ok((brain, journal)) => {
+                *thread = spawn_thread(
+                    brain,
+                    path,
+                    journal,
+                    Arc::clone(&self.inner.metrics),
+                    self.inner.events.clone(),
+                    self.inner.options,
+                    self.inner.limits,
+                );
+                Rejected::Restarted
+            }
+            Err(err) => {
+                tracing::error!(path = %path.display(), "cannot load the brain again: {err}");
+                Rejected::Stopped(err)
+            }
+        }
     }
 }
 
 pub enum Response {
-    Generated { data: String },
-    Error { error: anyhow::Error },
+    Generated {
+        data: String,
+    },
+    Candidates {
+        candidates: Vec<response::Candidate>,
+    },
+    Removed {
+        links: usize,
+    },
+    Described {
+        info: response::Info,
+    },
+    Error {
+        error: anyhow::Error,
+    },
     Nothing,
 }
 
 pub enum Request {
-    Train { data: String },
-    Generate { opts: request::Generate },
+    Train {
+        data: String,
+    },
+    /// Trains every line, journaling them together
+    TrainBatch {
+        data: Vec<String>,
+    },
+    Forget {
+        data: String,
+    },
+    Prune {
+        min_count: usize,
+    },
+    Decay {
+        factor: f64,
+    },
+    Describe {
+        top: usize,
+    },
     Save,
     ForceSave,
+    /// Renames the brain, moving it to `path`
+    Rename {
+        name: String,
+        path: PathBuf,
+    },
+    /// Throws away the brain, loading it (and its journal) from disk again
+    Reload,
+    /// Removes the brain's file and journal, then stops the brain thread if they're gone
+    Delete,
+}
+
+impl Request {
+    /// Whether the request does anything, rather than only reading the brain
+    pub const fn changes(&self) -> bool {
+        !matches!(self, Self::Describe { .. })
+    }
 }
 
+/// Starts a thread for the brain, which handles changes to it one at a time
 pub fn spawn_brain(
-    mut brain: Brain,
+    brain: impl Into<Model>,
     path: impl Into<PathBuf>,
-) -> Sender<(Request, oneshot::Sender<Response>)> {
+    journal: Journal,
+    options: SaveOptions,
+    limits: Limits,
+) -> Messaging {
+    let path = path.into();
+    let metrics = Arc::<Metrics>::default();
+    let (events, _) = broadcast::channel(STREAM_CAPACITY);
+    let thread = spawn_thread(
+        brain.into(),
+        path.clone(),
+        journal,
+        Arc::clone(&metrics),
+        events.clone(),
+        options,
+        limits,
+    );
+    Messaging::new(thread, path, metrics, events, options, limits)
+}
+
+fn spawn_thread(
+    brain: Model,
+    path: PathBuf,
+    journal: Journal,
+    metrics: Arc<Metrics>,
+    events: broadcast::Sender<Event>,
+    options: SaveOptions,
+    limits: Limits,
+) -> Thread {
     use {Request as In, Response as Out};
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<(In, oneshot::Sender<Out>)>(16);
-    let mut last = Instant::now();
-    let path = path.into();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<(In, oneshot::Sender<Out>)>(limits.queue);
+    metrics.measure(&brain);
+    let brain = Arc::new(RwLock::new(brain));
+    let mut store = Store {
+        path,
+        journal,
+        options,
+        metrics,
+        events,
+    };
 
+    let shared = Arc::clone(&brain);
     let func = move || {
         while let Some((msg, out)) = rx.blocking_recv() {
+            let delete = matches!(msg, In::Delete);
             let mut out = Some(out);
-            let resp = match handle_message(msg, &mut brain, &mut out, &mut last, &path) {
+            let resp = match handle_message(msg, &shared, &mut out, &mut store) {
                 Ok(false) => Response::Nothing,
                 Err(error) => Response::Error { error },
                 Ok(true) => continue,
             };
+            // a brain that couldn't be deleted is still there
+            let stop = delete && matches!(resp, Response::Nothing);
             let _ = out.take().unwrap().send(resp);
+            if stop {
+                break;
+            }
         }
     };
 
     let _join = std::thread::spawn(func);
-    tx
+    Thread { tx, brain }
+}
+
+// where a brain is saved, and what happened to it since
+struct Store {
+    path: PathBuf,
+    journal: Journal,
+    options: SaveOptions,
+    metrics: Arc<Metrics>,
+    events: broadcast::Sender<Event>,
+}
+
+impl Store {
+    fn save(&mut self, brain: &RwLock<Model>) -> anyhow::Result<()> {
+        let start = Instant::now();
+        self.write(brain, &self.path)?;
+        let brain = read(brain);
+        self.journal.reset(&brain.metadata())?;
+        self.metrics.saved(start.elapsed(), &brain);
+        Ok(())
+    }
+
+    // the brain can still be generated from while it's written
+    fn write(&self, brain: &RwLock<Model>, path: &Path) -> anyhow::Result<()> {
+        let compacted = read(brain).save(path, &self.options)?;
+        if let Some(compacted) = compacted {
+            *write(brain) = compacted;
+        }
+        Ok(())
+    }
+
+    // the brain is saved at its new path before anything at the old path is removed,
+    // which is only tidied up, the brain has moved even if that fails
+    fn rename(&mut self, brain: &RwLock<Model>, name: String, path: PathBuf) -> anyhow::Result<()> {
+        let old = {
+            let mut brain = write(brain);
+            let owned = brain.owned()?;
+            let old = owned.name().to_string();
+            owned.rename(name);
+            old
+        };
+
+        if let Err(err) = self.write(brain, &path) {
+            write(brain).owned()?.rename(old);
+            return Err(err);
+        }
+
+        let journal = Journal::create(Journal::path_for(&path), &read(brain).metadata())?;
+        let old_journal = std::mem::replace(&mut self.journal, journal);
+        let old_path = std::mem::replace(&mut self.path, path);
+
+        for old in [&*old_path, old_journal.path()] {
+            if let Err(err) = remove_file(old) {
+                tracing::warn!(path = ?old, "cannot remove after renaming: {err}");
+            }
+        }
+        Ok(())
+    }
+}
+
+// nothing else writes, so this only waits for generation
+fn read(brain: &RwLock<Model>) -> RwLockReadGuard<'_, Model> {
+    brain.read().expect("only the brain thread writes")
+}
+
+// generation is only held up while the brain changes
+fn write(brain: &RwLock<Model>) -> RwLockWriteGuard<'_, Model> {
+    brain.write().expect("only the brain thread writes")
+}
+
+fn remove_file(path: &Path) -> anyhow::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
 }
 
 fn handle_message(
     msg: Request,
-    brain: &mut Brain,
+    brain: &RwLock<Model>,
     out: &mut Option<oneshot::Sender<Response>>,
-    last: &mut Instant,
-    path: &Path,
+    store: &mut Store,
 ) -> anyhow::Result<bool> {
     use Request::*;
     use Response::*;
@@ -81,46 +535,177 @@ fn handle_message(
         sent = true
     };
 
+    // only changing the brain takes the write lock, saving it just reads it
     match msg {
-        Train { data } => train(brain, &data),
+        Train { data } => {
+            store.journal.append([Entry::Train(&data)])?;
+            write(brain).train(&data);
+            store.metrics.trained_lines.fetch_add(1, Ordering::Relaxed);
+            publish(&store.events, || Event::Trained { data });
+        }
 
-        Generate { opts } => {
-            let resp = generate(brain, opts)
-                .map(|data| Generated { data })
-                .with_context(|| "cannot generate data")?;
-            send(resp);
+        TrainBatch { data } => {
+            store
+                .journal
+                .append(data.iter().map(|data| Entry::Train(data)))?;
+            let mut brain = write(brain);
+            data.iter().for_each(|data| brain.train(data));
+            drop(brain);
+
+            let lines = data.len() as u64;
+            store
+                .metrics
+                .trained_lines
+                .fetch_add(lines, Ordering::Relaxed);
+            for data in data {
+                publish(&store.events, || Event::Trained { data });
+            }
         }
 
-        ForceSave => {
-            save(brain, path)?;
-            *last = Instant::now();
+        Forget { data } => {
+            let mut brain = write(brain);
+            let owned = brain.owned()?;
+            // there's nothing to replay for a line it was never trained on
+            if owned.was_trained_on(&data) {
+                store.journal.append([Entry::Forget(&data)])?;
+                owned.forget(&data);
+            }
         }
 
-        Save if last.elapsed() >= SAVE_DURATION => {
-            save(brain, path)?;
-            *last = Instant::now();
+        // these aren't journaled, so they're saved right away
+        Prune { min_count } => {
+            let links = write(brain).owned()?.prune(min_count);
+            store.save(brain)?;
+            send(Removed { links })
         }
 
+        Decay { factor } => {
+            let links = write(brain).owned()?.decay(factor)?;
+            store.save(brain)?;
+            send(Removed { links })
+        }
+
+        Describe { top } => send(describe(&read(brain), store, top)),
+
+        ForceSave => store.save(brain)?,
+
+        Rename { name, path } => store.rename(brain, name, path)?,
+
+        Reload => {
+            let (reloaded, journal) = crate::load_model_sync(&store.path)
+                .and_then(|model| crate::replay_sync(model, &store.path))?;
+            *write(brain) = reloaded;
+            store.journal = journal;
+        }
+
+        Delete => {
+            remove_file(&store.path)?;
+            remove_file(store.journal.path())?;
+        }
+
+        Save if !store.journal.is_empty() => store.save(brain)?,
+
         Save => {}
     }
 
     Ok(sent)
 }
 
-fn generate(brain: &Brain, opts: request::Generate) -> Option<String> {
-    brain.generate(
-        &fastrand::Rng::new(),
-        opts.min,
-        opts.max,
-        opts.query.as_deref(),
-        GENERATE_TIMEOUT,
-    )
+// events are only made if anything is listening for them
+fn publish(events: &broadcast::Sender<Event>, event: impl FnOnce() -> Event) {
+    if events.receiver_count() > 0 {
+        let _ = events.send(event());
+    }
 }
 
-fn train(brain: &mut Brain, data: &str) {
-    brain.train(data)
+fn describe(brain: &Model, store: &Store, top: usize) -> Response {
+    Response::Described {
+        info: response::Info {
+            metadata: brain.metadata(),
+            path: store.path.display().to_string(),
+            mapped: matches!(brain, Model::Mapped(..)),
+            journaled: store.journal.len(),
+            stats: brain.stats(top),
+        },
+    }
 }
 
-fn save(brain: &Brain, path: &Path) -> anyhow::Result<()> {
-    brain.save(path)
+fn generate(
+    brain: &Model,
+    opts: request::Generate,
+    timeout: Duration,
+    metrics: &Metrics,
+) -> GenerateOutcome {
+    let start = Instant::now();
+    let outcome = brain.generate(&GenerateRequest {
+        min: opts.min,
+        max: opts.max,
+        query: opts.query,
+        seed: opts.seed,
+        timeout,
+        sampling: opts.sampling,
+        required: opts.required,
+        forbidden: opts.forbidden,
+        blocklist: opts.blocklist,
+        max_chars: opts.max_chars,
+        retries: opts.retries,
+        sentence_end: opts.sentence_end,
+    });
+    metrics.generated(start.elapsed(), &outcome);
+    outcome
+}
+
+// seeds are consecutive so a client can reproduce any candidate. together they
+// have as long as a single sentence, any that don't fit are left out
+fn candidates(
+    brain: &Model,
+    opts: request::Generate,
+    count: usize,
+    timeout: Duration,
+    metrics: &Metrics,
+) -> anyhow::Result<Vec<response::Candidate>> {
+    let query = opts.query.clone().unwrap_or_default();
+    let mut seed = opts.seed.unwrap_or_else(|| fastrand::u64(..));
+
+    let mut candidates = Vec::with_capacity(count);
+    let mut skipped = GenerateOutcome::TimedOut;
+    let deadline = Instant::now() + timeout;
+    for _ in 0..count {
+        let start = Instant::now();
+        let left = deadline.saturating_duration_since(start);
+        if left.is_zero() {
+            break;
+        }
+
+        let opts = request::Generate {
+            seed: Some(seed),
+            ..opts.clone()
+        };
+        match generate(brain, opts, left, metrics) {
+            GenerateOutcome::Generated(data) => candidates.push(response::Candidate {
+                seed,
+                words: data.split_whitespace().count(),
+                matched: query
+                    .split_whitespace()
+                    .filter(|word| data.split_whitespace().any(|w| w == *word))
+                    .count(),
+                elapsed_ms: start.elapsed().as_millis() as u64,
+                data,
+            }),
+            // a different seed might not time out, or might meet the options
+            outcome @ (GenerateOutcome::TimedOut | GenerateOutcome::Unsatisfied) => {
+                skipped = outcome
+            }
+            outcome => return Err(NotGenerated(outcome).into()),
+        }
+        seed = seed.wrapping_add(1);
+    }
+
+    if candidates.is_empty() {
+        return Err(NotGenerated(skipped).into());
+    }
+    Ok(candidates)
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,273 @@
+use markov::Brain;
+
+use super::*;
+
+fn limits() -> Limits {
+    Limits {
+        timeout: Duration::from_millis(20),
+        ..Limits::default()
+    }
+}
+
+fn thread(tx: Channel) -> Thread {
+    Thread {
+        tx,
+        brain: Arc::new(RwLock::new(Brain::new("test", 2).into())),
+    }
+}
+
+fn messaging(thread: Thread, path: impl Into<PathBuf>) -> Messaging {
+    let (events, _) = broadcast::channel(STREAM_CAPACITY);
+    let metrics = Arc::default();
+    Messaging::new(
+        thread,
+        path.into(),
+        metrics,
+        events,
+        SaveOptions::default(),
+        limits(),
+    )
+}
+
+// a handle whose brain thread has already stopped
+fn stopped(path: PathBuf) -> Messaging {
+    let (tx, _) = tokio::sync::mpsc::channel(1);
+    messaging(thread(tx), path)
+}
+
+#[tokio::test]
+async fn busy_and_timed_out() {
+    let (tx, _rx) = tokio::sync::mpsc::channel(1);
+    let brain = messaging(thread(tx), "test.sdb");
+
+    // nothing reads the queue, so the first request waits and the second has no room
+    assert!(matches!(
+        brain.request(Request::Describe { top: 1 }).await,
+        Err(Rejected::TimedOut)
+    ));
+    assert!(matches!(
+        brain.request(Request::Save).await,
+        Err(Rejected::Busy)
+    ));
+
+    let metrics = brain.metrics();
+    assert_eq!(metrics.request_timeouts.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.rejected.load(Ordering::Relaxed), 1);
+    assert_eq!(metrics.failures.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn changes_that_time_out_are_still_queued() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let brain = messaging(thread(tx), "test.sdb");
+
+    let data = "hello world".to_string();
+    match brain.request(Request::Train { data }).await {
+        Err(err @ Rejected::Queued) => assert_eq!(err.status(), 202),
+        _ => panic!("the change should still be queued"),
+    }
+    assert_eq!(brain.metrics().request_timeouts.load(Ordering::Relaxed), 1);
+
+    // the brain thread gets to it eventually
+    assert!(matches!(
+        rx.recv().await,
+        Some((Request::Train { data }, _)) if data == "hello world"
+    ));
+}
+
+#[tokio::test]
+async fn generates_while_the_thread_is_busy() {
+    let (tx, _rx) = tokio::sync::mpsc::channel(1);
+    let thread = thread(tx);
+    thread.brain.write().unwrap().train("hello there world");
+    let brain = messaging(thread, "test.sdb");
+
+    // the thread never reads its queue, but generating doesn't go through it
+    assert!(matches!(
+        brain.request(Request::Save).await,
+        Err(Rejected::Queued)
+    ));
+    let opts = request::Generate {
+        min: 1,
+        max: 3,
+        ..request::Generate::default()
+    };
+    assert!(matches!(
+        brain.generate(opts.clone()).await,
+        Ok(Response::Generated { .. })
+    ));
+    assert!(matches!(
+        brain.candidates(opts, 2).await,
+        Ok(Response::Candidates { candidates }) if candidates.len() == 2
+    ));
+    assert_eq!(brain.metrics().generated.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn restarts_from_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.sdb");
+
+    let mut saved = Brain::new("test", 2);
+    saved.train("hello world");
+    crate::save_sync(&saved, &path, &SaveOptions::default()).unwrap();
+
+    let brain = stopped(path);
+    assert!(matches!(
+        brain.request(Request::Describe { top: 1 }).await,
+        Err(Rejected::Restarted)
+    ));
+
+    match brain.request(Request::Describe { top: 1 }).await {
+        Ok(Response::Described { info }) => {
+            assert_eq!(info.metadata.name, "test");
+            assert_eq!(info.metadata.lines, 1);
+        }
+        _ => panic!("the brain should have been restarted"),
+    }
+}
+
+#[tokio::test]
+async fn stays_stopped_without_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let brain = stopped(dir.path().join("test.sdb"));
+    assert!(matches!(
+        brain.request(Request::Save).await,
+        Err(Rejected::Stopped(..))
+    ));
+    assert!(matches!(
+        brain.send(Request::Save).await,
+        Response::Error { .. }
+    ));
+}
+
+#[tokio::test]
+async fn events() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.sdb");
+    let brain = Brain::new("test", 2);
+    let journal = Journal::create(Journal::path_for(&path), brain.metadata()).unwrap();
+    let brain = spawn_brain(brain, path, journal, SaveOptions::default(), limits());
+
+    let mut events = brain.subscribe();
+    let data = "hello there world".to_string();
+    brain.send(Request::Train { data }).await;
+    let data = vec!["one two".to_string(), "three four".to_string()];
+    brain.send(Request::TrainBatch { data }).await;
+
+    let opts = request::Generate {
+        min: 1,
+        max: 3,
+        ..request::Generate::default()
+    };
+    let generated = match brain.generate(opts).await {
+        Ok(Response::Generated { data }) => data,
+        _ => panic!("the brain should have generated something"),
+    };
+
+    let mut received = vec![];
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    assert!(matches!(
+        &*received,
+        [
+            Event::Trained { data: first },
+            Event::Trained { data: second },
+            Event::Trained { data: third },
+            Event::Generated { data: last },
+        ] if first == "hello there world"
+            && second == "one two"
+            && third == "three four"
+            && *last == generated
+    ));
+}
+
+#[tokio::test]
+async fn generation_options() {
+    let (tx, _rx) = tokio::sync::mpsc::channel(1);
+    let thread = thread(tx);
+    thread.brain.write().unwrap().train("hello there world");
+    let brain = messaging(thread, "test.sdb");
+
+    let opts = request::Generate {
+        min: 1,
+        max: 3,
+        blocklist: vec!["there".into()],
+        ..request::Generate::default()
+    };
+    match brain.generate(opts).await {
+        Ok(Response::Generated { data }) => assert_eq!(data, "hello"),
+        _ => panic!("the brain should have generated something"),
+    }
+
+    let opts = request::Generate {
+        min: 1,
+        max: 3,
+        max_chars: Some(3),
+        ..request::Generate::default()
+    };
+    assert!(matches!(
+        brain.generate(opts.clone()).await,
+        Ok(Response::Error { error }) if error.to_string().contains("no sentence met the options")
+    ));
+    assert!(matches!(
+        brain.candidates(opts, 2).await,
+        Ok(Response::Error { error }) if error.to_string().contains("no sentence met the options")
+    ));
+}
+
+#[test]
+fn candidates_share_a_single_timeout() {
+    let mut brain = Brain::new("test", 2);
+    brain.train("hello there world");
+    let (brain, metrics) = (Model::from(brain), Metrics::default());
+    let opts = request::Generate {
+        min: 1,
+        max: 3,
+        ..request::Generate::default()
+    };
+
+    let all = candidates(&brain, opts.clone(), 4, Duration::from_secs(5), &metrics).unwrap();
+    assert_eq!(all.len(), 4);
+
+    // nothing is left once the time is up, however many were asked for
+    let err = candidates(
+        &brain,
+        opts,
+        crate::MAX_CANDIDATES,
+        Duration::ZERO,
+        &metrics,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{err}");
+}
+
+#[tokio::test]
+async fn saves_while_generating() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.sdb");
+    let mut brain = Brain::new("test", 2);
+    brain.train("hello there world");
+    let journal = Journal::create(Journal::path_for(&path), brain.metadata()).unwrap();
+    let brain = spawn_brain(brain, &path, journal, SaveOptions::default(), limits());
+
+    // something is generating from the brain the whole time
+    let shared = brain.inner.thread.lock().await.brain.clone();
+    let (reading, read) = std::sync::mpsc::channel();
+    let (done, finish) = std::sync::mpsc::channel::<()>();
+    let reader = std::thread::spawn(move || {
+        let _brain = shared.read().unwrap();
+        reading.send(()).unwrap();
+        let _ = finish.recv();
+    });
+    read.recv().unwrap();
+
+    let save = tokio::time::timeout(Duration::from_secs(5), brain.send(Request::ForceSave));
+    assert!(matches!(save.await, Ok(Response::Nothing)));
+    drop(done);
+    reader.join().unwrap();
+
+    let saved = markov::format::load_file(&path).unwrap();
+    assert_eq!(saved.metadata().lines, 1);
+}
@@ -0,0 +1,263 @@
+//! Counters for each brain, served at `/metrics` in the Prometheus text format
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use markov::GenerateOutcome;
+
+use crate::{
+    messaging::{Rejected, Response},
+    model::Model,
+};
+
+// the upper bound of each bucket, in seconds
+const GENERATE_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+const SAVE_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0];
+
+/// What happened to a brain since the server started
+///
+/// These follow the brain, so they're kept if it's renamed or restarted.
+pub struct Metrics {
+    pub trained_lines: AtomicU64,
+    /// Every sentence that was generated, or tried to be
+    pub generated: AtomicU64,
+    /// Sentences that ran out of time
+    pub generate_timeouts: AtomicU64,
+    /// Requests that gave up waiting for the brain
+    pub request_timeouts: AtomicU64,
+    /// Requests turned away because the brain was busy
+    pub rejected: AtomicU64,
+    /// Requests that failed, or that the brain stopped during
+    pub failures: AtomicU64,
+    pub generate_seconds: Histogram,
+    pub save_seconds: Histogram,
+    /// How big the brain was when it was last saved
+    pub size: Size,
+}
+
+#[derive(Default)]
+pub struct Size {
+    pub lines: AtomicU64,
+    /// These are only known for brains that aren't memory-mapped
+    pub contexts: AtomicU64,
+    pub links: AtomicU64,
+    pub memory: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            trained_lines: AtomicU64::default(),
+            generated: AtomicU64::default(),
+            generate_timeouts: AtomicU64::default(),
+            request_timeouts: AtomicU64::default(),
+            rejected: AtomicU64::default(),
+            failures: AtomicU64::default(),
+            generate_seconds: Histogram::new(GENERATE_BUCKETS),
+            save_seconds: Histogram::new(SAVE_BUCKETS),
+            size: Size::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn generated(&self, elapsed: Duration, outcome: &GenerateOutcome) {
+        self.generated.fetch_add(1, Ordering::Relaxed);
+        self.generate_seconds.observe(elapsed);
+        if let GenerateOutcome::TimedOut = outcome {
+            self.generate_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a request that failed, or was turned away
+    pub fn observe(&self, resp: &Result<Response, Rejected>) {
+        let counter = match resp {
+            Ok(Response::Error { .. }) | Err(Rejected::Restarted | Rejected::Stopped(..)) => {
+                &self.failures
+            }
+            Err(Rejected::Busy) => &self.rejected,
+            Err(Rejected::TimedOut | Rejected::Queued) => &self.request_timeouts,
+            Ok(..) => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn saved(&self, elapsed: Duration, brain: &Model) {
+        self.save_seconds.observe(elapsed);
+        self.measure(brain);
+    }
+
+    pub fn measure(&self, brain: &Model) {
+        let size = &self.size;
+        size.lines.store(brain.metadata().lines, Ordering::Relaxed);
+        if let Some(stats) = brain.stats(0) {
+            size.contexts.store(stats.contexts as _, Ordering::Relaxed);
+            size.links.store(stats.total_links as _, Ordering::Relaxed);
+            size.memory.store(stats.memory as _, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Counts durations into buckets, each bucket includes every shorter one
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    /// `bounds` are the upper bound of each bucket, in seconds
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::default()).collect(),
+            count: AtomicU64::default(),
+            sum_micros: AtomicU64::default(),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            if secs <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as _, Ordering::Relaxed);
+    }
+}
+
+// a metric's name, its help, and where its value is
+type Metric<T> = (&'static str, &'static str, fn(&T) -> &AtomicU64);
+
+/// Writes the metrics of every brain, sorted by name
+pub fn render<'a>(brains: impl IntoIterator<Item = (&'a str, &'a Metrics)>) -> String {
+    let mut brains = brains.into_iter().collect::<Vec<_>>();
+    brains.sort_by_key(|(name, _)| *name);
+
+    let mut out = String::new();
+    let counters: [Metric<Metrics>; 6] = [
+        ("brain_trained_lines_total", "Lines trained", |m| {
+            &m.trained_lines
+        }),
+        (
+            "brain_generated_total",
+            "Sentences generated, or tried to be",
+            |m| &m.generated,
+        ),
+        (
+            "brain_generate_timeouts_total",
+            "Sentences that ran out of time",
+            |m| &m.generate_timeouts,
+        ),
+        (
+            "brain_request_timeouts_total",
+            "Requests that gave up waiting for the brain",
+            |m| &m.request_timeouts,
+        ),
+        (
+            "brain_rejected_total",
+            "Requests turned away because the brain was busy",
+            |m| &m.rejected,
+        ),
+        ("brain_failures_total", "Requests that failed", |m| {
+            &m.failures
+        }),
+    ];
+    for (metric, help, get) in counters {
+        header(&mut out, metric, help, "counter");
+        for (name, metrics) in &brains {
+            let value = get(metrics).load(Ordering::Relaxed);
+            let _ = writeln!(out, "{metric}{{brain=\"{}\"}} {value}", escape(name));
+        }
+    }
+
+    let gauges: [Metric<Size>; 4] = [
+        (
+            "brain_lines",
+            "Lines the brain was trained on, as of its last save",
+            |s| &s.lines,
+        ),
+        (
+            "brain_contexts",
+            "Contexts in the brain, as of its last save",
+            |s| &s.contexts,
+        ),
+        (
+            "brain_links",
+            "Links in the brain, as of its last save",
+            |s| &s.links,
+        ),
+        (
+            "brain_memory_bytes",
+            "Roughly how much memory the brain uses, as of its last save",
+            |s| &s.memory,
+        ),
+    ];
+    for (metric, help, get) in gauges {
+        header(&mut out, metric, help, "gauge");
+        for (name, metrics) in &brains {
+            let value = get(&metrics.size).load(Ordering::Relaxed);
+            let _ = writeln!(out, "{metric}{{brain=\"{}\"}} {value}", escape(name));
+        }
+    }
+
+    let metric = "brain_generate_seconds";
+    header(
+        &mut out,
+        metric,
+        "How long each sentence took to generate",
+        "histogram",
+    );
+    for (name, metrics) in &brains {
+        histogram(&mut out, metric, name, &metrics.generate_seconds);
+    }
+
+    let metric = "brain_save_seconds";
+    header(&mut out, metric, "How long each save took", "histogram");
+    for (name, metrics) in &brains {
+        histogram(&mut out, metric, name, &metrics.save_seconds);
+    }
+
+    out
+}
+
+fn header(out: &mut String, metric: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {metric} {help}");
+    let _ = writeln!(out, "# TYPE {metric} {kind}");
+}
+
+fn histogram(out: &mut String, metric: &str, name: &str, histogram: &Histogram) {
+    let name = escape(name);
+    for (bound, bucket) in histogram.bounds.iter().zip(&histogram.buckets) {
+        let count = bucket.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "{metric}_bucket{{brain=\"{name}\",le=\"{bound}\"}} {count}"
+        );
+    }
+
+    let count = histogram.count.load(Ordering::Relaxed);
+    let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(
+        out,
+        "{metric}_bucket{{brain=\"{name}\",le=\"+Inf\"}} {count}"
+    );
+    let _ = writeln!(out, "{metric}_sum{{brain=\"{name}\"}} {sum}");
+    let _ = writeln!(out, "{metric}_count{{brain=\"{name}\"}} {count}");
+}
+
+// brains loaded from disk can have any name
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests;
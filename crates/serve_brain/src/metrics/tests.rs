@@ -0,0 +1,40 @@
+use super::*;
+
+#[test]
+fn histogram() {
+    let histogram = Histogram::new(&[0.1, 1.0]);
+    for millis in [50, 500, 5000] {
+        histogram.observe(Duration::from_millis(millis));
+    }
+
+    let mut out = String::new();
+    super::histogram(&mut out, "test_seconds", "museun", &histogram);
+    assert_eq!(
+        out,
+        "\
+test_seconds_bucket{brain=\"museun\",le=\"0.1\"} 1
+test_seconds_bucket{brain=\"museun\",le=\"1\"} 2
+test_seconds_bucket{brain=\"museun\",le=\"+Inf\"} 3
+test_seconds_sum{brain=\"museun\"} 5.55
+test_seconds_count{brain=\"museun\"} 3
+"
+    );
+}
+
+#[test]
+fn render() {
+    let (first, second) = (Metrics::default(), Metrics::default());
+    first.trained_lines.store(10, Ordering::Relaxed);
+    second.size.lines.store(3, Ordering::Relaxed);
+
+    let out = super::render([("zzz", &first), ("a\"b", &second)]);
+    assert!(out.contains("# TYPE brain_trained_lines_total counter\n"));
+    assert!(out.contains("# TYPE brain_generate_seconds histogram\n"));
+    assert!(out.contains("brain_trained_lines_total{brain=\"zzz\"} 10\n"));
+    assert!(out.contains("brain_lines{brain=\"a\\\"b\"} 3\n"));
+
+    // brains are sorted by name within each metric
+    let a = out.find("brain_lines{brain=\"a").unwrap();
+    let z = out.find("brain_lines{brain=\"zzz").unwrap();
+    assert!(a < z);
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use markov::{
+    format::{journal::Entry, SaveOptions},
+    Brain, BrainStats, GenerateOutcome, GenerateRequest, MappedBrain, Metadata,
+};
+
+use crate::BrainExt;
+
+/// A memory-mapped brain was asked to change in a way only a decoded brain can
+#[derive(Debug)]
+pub struct IsMapped {
+    pub name: String,
+}
+
+impl std::fmt::Display for IsMapped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is memory-mapped and can only be trained", self.name)
+    }
+}
+
+impl std::error::Error for IsMapped {}
+
+/// A brain owned by a brain thread
+pub enum Model {
+    /// Entirely decoded into memory
+    Owned(Brain),
+    /// Memory-mapped, training is kept in memory until the next save
+    Mapped(MappedBrain),
+}
+
+impl Model {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Owned(brain) => brain.name(),
+            Self::Mapped(brain) => brain.name(),
+        }
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        match self {
+            Self::Owned(brain) => brain.metadata().clone(),
+            Self::Mapped(brain) => brain.metadata(),
+        }
+    }
+
+    /// Applies an entry replayed from the brain's journal
+    ///
+    /// Mapped brains can't forget, so they never journal it.
+    pub fn apply(&mut self, entry: Entry<'_>) {
+        match (entry, self) {
+            (Entry::Train(data), brain) => brain.train(data),
+            (Entry::Forget(data), Self::Owned(brain)) => {
+                brain.forget(data);
+            }
+            (Entry::Forget(..), Self::Mapped(..)) => {}
+        }
+    }
+
+    pub fn train(&mut self, data: &str) {
+        match self {
+            Self::Owned(brain) => brain.train(data),
+            Self::Mapped(brain) => brain.train(data),
+        }
+    }
+
+    pub fn generate(&self, req: &GenerateRequest) -> GenerateOutcome {
+        match self {
+            Self::Owned(brain) => brain.generate(req),
+            Self::Mapped(brain) => brain.generate(req),
+        }
+    }
+
+    /// Summarizes an owned brain, mapped brains would have to be decoded first
+    pub fn stats(&self, top: usize) -> Option<BrainStats> {
+        match self {
+            Self::Owned(brain) => Some(brain.stats(top)),
+            Self::Mapped(..) => None,
+        }
+    }
+
+    /// The brain, if it can be modified in place
+    pub fn owned(&mut self) -> anyhow::Result<&mut Brain> {
+        match self {
+            Self::Owned(brain) => Ok(brain),
+            Self::Mapped(brain) => {
+                let name = brain.name().to_string();
+                Err(IsMapped { name }.into())
+            }
+        }
+    }
+
+    /// Saves an owned brain, or compacts a mapped brain if it has been trained since it was last saved
+    ///
+    /// The brain is only read, a compacted brain is returned to replace it with.
+    pub fn save(&self, path: &Path, options: &SaveOptions) -> anyhow::Result<Option<Self>> {
+        match self {
+            Self::Owned(brain) => brain.save(path, options).map(|_| None),
+            Self::Mapped(brain) if brain.pending() > 0 => {
+                brain.save_file(path, options.backups)?;
+                Ok(Some(Self::Mapped(MappedBrain::open(path)?)))
+            }
+            Self::Mapped(..) => Ok(None),
+        }
+    }
+}
+
+impl From<Brain> for Model {
+    fn from(brain: Brain) -> Self {
+        Self::Owned(brain)
+    }
+}
+
+impl From<MappedBrain> for Model {
+    fn from(brain: MappedBrain) -> Self {
+        Self::Mapped(brain)
+    }
+}
@@ -0,0 +1,433 @@
+//! An OpenAPI 3 description of every endpoint, served at `/openapi.json`
+//!
+//! Schemas are named for the type they describe, like `request.Generate`.
+use serde_json::{json, Value};
+
+use crate::{DEFAULT_DEPTH, MAX_CANDIDATES, MAX_DEPTH, MAX_RETRIES, MAX_WORDS};
+
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "serve_brain",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Markov chain brains, trained and generated from over HTTP. \
+                Every error is a `response.Error`. A change the brain doesn't get to in time \
+                is answered with a `202` (and a `response.Error`), it's still applied, so it \
+                shouldn't be sent again.",
+        },
+        "security": [{ "bearer": [] }, { "apiKey": [] }],
+        "paths": paths(),
+        "components": {
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+            },
+            "parameters": {
+                "name": {
+                    "name": "name",
+                    "in": "path",
+                    "required": true,
+                    "description": "The brain's name",
+                    "schema": { "type": "string" },
+                },
+            },
+            "responses": {
+                "Ok": { "description": "Done, without a body" },
+                "Error": {
+                    "description": "Something went wrong",
+                    "content": { "application/json": { "schema": schema("response.Error") } },
+                },
+            },
+            "schemas": schemas(),
+        },
+    })
+}
+
+fn paths() -> Value {
+    json!({
+        "/{name}/generate": {
+            "get": optional(op(
+                "Generates a sentence",
+                Some("request.Generate"),
+                ok("response.Generate"),
+            )),
+        },
+        "/{name}/generate/candidates": {
+            "get": optional(op(
+                "Generates several sentences, the ones that best match the query first. \
+                    They share the time a single sentence has, so fewer than `count` can be sent",
+                Some("request.Candidates"),
+                ok("response.Candidates"),
+            )),
+        },
+        "/{name}/train": {
+            "post": op(
+                "Trains a line, which is filtered first",
+                Some("request.Train"),
+                empty(),
+            ),
+        },
+        "/{name}/train/batch": {
+            "post": {
+                "summary": "Trains many lines, as JSON or as newline-delimited text",
+                "parameters": [name()],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": { "schema": schema("request.Batch") },
+                        "text/plain": { "schema": { "type": "string" } },
+                    },
+                },
+                "responses": { "200": ok("response.Trained"), "default": error() },
+            },
+        },
+        "/{name}/create": {
+            "post": op("Creates an empty brain", Some("request.Create"), empty()),
+        },
+        "/{name}/forget": {
+            "post": op("Forgets a line that was trained", Some("request.Forget"), empty()),
+        },
+        "/{name}/prune": {
+            "post": op(
+                "Removes links seen fewer than `min_count` times",
+                Some("request.Prune"),
+                ok("response.Removed"),
+            ),
+        },
+        "/{name}/decay": {
+            "post": op(
+                "Scales every link's count down by `factor`",
+                Some("request.Decay"),
+                ok("response.Removed"),
+            ),
+        },
+        "/{name}": {
+            "get": {
+                "summary": "Describes a brain",
+                "parameters": [
+                    name(),
+                    {
+                        "name": "top",
+                        "in": "query",
+                        "description": "How many of the most common words to include, 10 if not given",
+                        "schema": { "type": "integer", "minimum": 0 },
+                    },
+                ],
+                "responses": { "200": ok("response.Info"), "default": error() },
+            },
+            "delete": op("Removes a brain and its journal, keeping its backups", None, empty()),
+        },
+        "/{name}/rename": {
+            "post": op("Renames a brain, and its file", Some("request.Rename"), empty()),
+        },
+        "/{name}/reload": {
+            "post": op("Loads a brain from disk again, replaying its journal", None, empty()),
+        },
+        "/{name}/save": {
+            "post": op("Saves a brain now, emptying its journal", None, empty()),
+        },
+        "/{name}/stream": {
+            "get": {
+                "summary": "Streams every line the brain is trained on, and everything generated from it",
+                "description": "Each event is named for its kind, with a `response.Event` as its data. \
+                    A client that falls too far behind is sent a `lagged` event with how many events it missed.",
+                "parameters": [name()],
+                "responses": {
+                    "200": {
+                        "description": "Server-sent events",
+                        "content": { "text/event-stream": { "schema": { "type": "string" } } },
+                    },
+                    "default": error(),
+                },
+            },
+        },
+        "/brains": {
+            "get": {
+                "summary": "Lists every brain the key can use",
+                "responses": { "200": ok("response.Brains"), "default": error() },
+            },
+        },
+        "/metrics": {
+            "get": {
+                "summary": "Every brain's metrics, in the Prometheus text format",
+                "responses": {
+                    "200": {
+                        "description": "Metrics",
+                        "content": { "text/plain": { "schema": { "type": "string" } } },
+                    },
+                    "default": error(),
+                },
+            },
+        },
+        "/openapi.json": {
+            "get": {
+                "summary": "This document",
+                "security": [],
+                "responses": {
+                    "200": {
+                        "description": "An OpenAPI document",
+                        "content": { "application/json": { "schema": { "type": "object" } } },
+                    },
+                },
+            },
+        },
+    })
+}
+
+fn schemas() -> Value {
+    let words = json!({ "type": "integer", "minimum": 1, "maximum": MAX_WORDS });
+    let list = |description: &str| {
+        json!({
+            "type": "array",
+            "items": { "type": "string" },
+            "maxItems": MAX_WORDS,
+            "description": description,
+        })
+    };
+    let line = json!({
+        "type": "object",
+        "required": ["data"],
+        "properties": { "data": { "type": "string" } },
+    });
+
+    json!({
+        "request.Generate": {
+            "type": "object",
+            "required": ["min", "max"],
+            "properties": {
+                "min": words,
+                "max": words,
+                "query": {
+                    "type": "string",
+                    "description": "Words the sentence should contain",
+                },
+                "seed": {
+                    "type": "integer",
+                    "format": "uint64",
+                    "description": "The same seed generates the same sentence",
+                },
+                "sampling": {
+                    "type": "object",
+                    "description": "How the next word is picked, see `markov::SamplingConfig`",
+                    "properties": {
+                        "temperature": { "type": "number", "default": 1.0 },
+                        "top_k": { "type": "integer", "minimum": 1 },
+                        "top_p": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+                        "backoff": { "description": "How much each context width contributes" },
+                        "min_context": { "type": "integer", "minimum": 1, "default": 1 },
+                    },
+                },
+                "required": list(
+                    "Words the sentence must contain, woven in like the query. \
+                    There can't be more of them than `max`",
+                ),
+                "forbidden": list("Words the sentence must not contain"),
+                "blocklist": list("Words that are never picked while generating"),
+                "max_chars": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "The most characters the sentence can have, \
+                        words that don't fit are dropped from the end",
+                },
+                "retries": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": MAX_RETRIES,
+                    "default": 0,
+                    "description": "How many more times to try when a sentence doesn't meet these options, \
+                        or repeats a line the brain was trained on. A repeat is only sent if every try was one",
+                },
+                "sentence_end": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Whether the sentence must end where a trained line did",
+                },
+            },
+            "description": "Words in `required`, `forbidden` and `blocklist` are compared ignoring ASCII case. \
+                A required (or query) word can't also be forbidden or blocklisted. \
+                A `404` is sent if the brain is empty or knows none of the query's words, \
+                a `422` if no sentence met the options and a `504` if it ran out of time.",
+        },
+        "request.Candidates": {
+            "allOf": [
+                schema("request.Generate"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "count": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": MAX_CANDIDATES,
+                            "default": 5,
+                        },
+                    },
+                },
+            ],
+        },
+        "request.Train": line,
+        "request.Forget": line,
+        "request.Batch": {
+            "oneOf": [
+                { "type": "array", "items": { "type": "string" } },
+                {
+                    "type": "object",
+                    "required": ["data"],
+                    "properties": { "data": { "type": "array", "items": { "type": "string" } } },
+                },
+            ],
+        },
+        "request.Prune": {
+            "type": "object",
+            "required": ["min_count"],
+            "properties": { "min_count": { "type": "integer", "minimum": 0 } },
+        },
+        "request.Decay": {
+            "type": "object",
+            "required": ["factor"],
+            "properties": { "factor": { "type": "number", "minimum": 0.0, "maximum": 1.0 } },
+        },
+        "request.Create": {
+            "type": "object",
+            "properties": {
+                "depth": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_DEPTH,
+                    "default": DEFAULT_DEPTH,
+                },
+            },
+        },
+        "request.Rename": {
+            "type": "object",
+            "required": ["name"],
+            "properties": { "name": { "type": "string" } },
+        },
+        "response.Generate": line,
+        "response.Candidates": {
+            "type": "object",
+            "required": ["candidates"],
+            "properties": {
+                "candidates": { "type": "array", "items": schema("response.Candidate") },
+            },
+        },
+        "response.Candidate": {
+            "type": "object",
+            "required": ["data", "seed", "words", "matched", "elapsed_ms"],
+            "properties": {
+                "data": { "type": "string" },
+                "seed": {
+                    "type": "integer",
+                    "format": "uint64",
+                    "description": "Generating with this seed produces the same sentence again",
+                },
+                "words": { "type": "integer" },
+                "matched": {
+                    "type": "integer",
+                    "description": "How many of the query's words the sentence contains",
+                },
+                "elapsed_ms": { "type": "integer" },
+            },
+        },
+        "response.Trained": {
+            "type": "object",
+            "required": ["trained", "skipped"],
+            "properties": {
+                "trained": { "type": "integer" },
+                "skipped": {
+                    "type": "integer",
+                    "description": "Lines that were empty once they were filtered",
+                },
+            },
+        },
+        "response.Removed": {
+            "type": "object",
+            "required": ["links"],
+            "properties": { "links": { "type": "integer" } },
+        },
+        "response.Brains": {
+            "type": "object",
+            "required": ["brains"],
+            "properties": { "brains": { "type": "array", "items": { "type": "string" } } },
+        },
+        "response.Info": {
+            "type": "object",
+            "required": ["metadata", "path", "mapped", "journaled"],
+            "properties": {
+                "metadata": { "type": "object", "description": "See `markov::Metadata`" },
+                "path": { "type": "string" },
+                "mapped": { "type": "boolean", "description": "Whether the brain is memory-mapped" },
+                "journaled": {
+                    "type": "integer",
+                    "description": "Lines trained (or forgotten) since the brain was last saved",
+                },
+                "stats": {
+                    "type": "object",
+                    "nullable": true,
+                    "description": "See `markov::BrainStats`, only available for brains that aren't memory-mapped",
+                },
+            },
+        },
+        "response.Event": {
+            "type": "object",
+            "required": ["kind", "data"],
+            "properties": {
+                "kind": { "type": "string", "enum": ["trained", "generated"] },
+                "data": { "type": "string" },
+            },
+        },
+        "response.Error": {
+            "type": "object",
+            "required": ["msg"],
+            "properties": { "msg": { "type": "string" } },
+        },
+    })
+}
+
+// an operation on a single brain, with an optional JSON body
+fn op(summary: &str, body: Option<&str>, resp: Value) -> Value {
+    let mut op = json!({
+        "summary": summary,
+        "parameters": [name()],
+        "responses": { "200": resp, "default": error() },
+    });
+    if let Some(body) = body {
+        op["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema(body) } },
+        });
+    }
+    op
+}
+
+// the defaults are used without a body
+fn optional(mut op: Value) -> Value {
+    op["requestBody"]["required"] = json!(false);
+    op
+}
+
+fn ok(name: &str) -> Value {
+    json!({
+        "description": "Done",
+        "content": { "application/json": { "schema": schema(name) } },
+    })
+}
+
+fn empty() -> Value {
+    json!({ "$ref": "#/components/responses/Ok" })
+}
+
+fn error() -> Value {
+    json!({ "$ref": "#/components/responses/Error" })
+}
+
+fn name() -> Value {
+    json!({ "$ref": "#/components/parameters/name" })
+}
+
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{Method, Request, StatusCode},
+    Extension, Router,
+};
+use markov::format::SaveOptions;
+use tower::ServiceExt;
+
+use super::*;
+use crate::{auth::Keys, state::State};
+
+// every `$ref` in `value`
+fn refs<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(map) => {
+            out.extend(map.get("$ref").and_then(Value::as_str));
+            map.values().for_each(|value| refs(value, out));
+        }
+        Value::Array(values) => values.iter().for_each(|value| refs(value, out)),
+        _ => {}
+    }
+}
+
+#[test]
+fn refs_resolve() {
+    let doc = document();
+    let mut out = vec![];
+    refs(&doc, &mut out);
+    assert!(!out.is_empty());
+
+    for reference in out {
+        let pointer = reference.strip_prefix('#').unwrap();
+        assert!(doc.pointer(pointer).is_some(), "{reference} is missing");
+    }
+}
+
+#[tokio::test]
+async fn served_without_a_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = crate::router(State {
+        directory: dir.path().to_path_buf(),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::new(Keys::new(["s3cret:admin".parse().unwrap()])),
+        limits: Default::default(),
+        shutdown: Default::default(),
+    });
+
+    let req = Request::get("/openapi.json").body(Body::empty()).unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = resp.into_body().data().await.unwrap().unwrap();
+    let doc: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(doc, document());
+}
+
+#[tokio::test]
+async fn routes_are_documented() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = State {
+        directory: dir.path().to_path_buf(),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits: Default::default(),
+        shutdown: Default::default(),
+    };
+    let methods = [
+        Method::GET,
+        Method::POST,
+        Method::PUT,
+        Method::PATCH,
+        Method::DELETE,
+    ];
+
+    // a route doesn't say what it serves, so it's asked for each method
+    let mut routed = vec![];
+    for (path, route) in crate::routes() {
+        let app = Router::new()
+            .route(path, route)
+            .layer(Extension(state.clone()));
+        let uri = path.replace(":name", "museun");
+        for method in &methods {
+            let req = Request::builder()
+                .method(method)
+                .uri(&uri)
+                .body(Body::empty())
+                .unwrap();
+            let resp = app.clone().oneshot(req).await.unwrap();
+            if resp.status() != StatusCode::METHOD_NOT_ALLOWED {
+                routed.push(format!("{method} {}", path.replace(":name", "{name}")));
+            }
+        }
+    }
+
+    let doc = document();
+    let mut documented = vec![];
+    for (path, item) in doc["paths"].as_object().unwrap() {
+        for method in &methods {
+            if item.get(method.as_str().to_lowercase()).is_some() {
+                documented.push(format!("{method} {path}"));
+            }
+        }
+    }
+
+    routed.sort();
+    documented.sort();
+    assert_eq!(routed, documented);
+}
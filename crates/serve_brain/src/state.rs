@@ -1,18 +1,134 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use std::collections::HashMap;
-use tokio::sync::Mutex;
+use filters::Filters;
+use markov::format::SaveOptions;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{watch, Mutex};
 
-use crate::messaging::Messaging;
+use crate::{auth::Keys, messaging::Messaging, GENERATE_TIMEOUT};
 
 #[derive(Clone)]
 pub struct State {
+    /// Where every brain is stored, as `<name>.sdb`
+    pub directory: PathBuf,
     pub brains: Arc<Mutex<HashMap<String, Messaging>>>,
+    /// Names that are being taken (e.g. by a rename), so nothing else can take them meanwhile
+    pub reserved: Arc<std::sync::Mutex<HashSet<String>>>,
+    /// How every brain is written to disk
+    pub save_options: SaveOptions,
+    /// How lines are cleaned up before they are trained
+    pub filters: Arc<Filters>,
+    /// Who can do what, everyone can do everything if this is empty
+    pub keys: Arc<Keys>,
+    /// How much a single request, or a single brain, can take on
+    pub limits: Limits,
+    /// Ends responses that would otherwise never end, like `/:name/stream`
+    pub shutdown: Shutdown,
 }
 
 impl State {
     pub async fn try_get(&self, name: &str) -> Option<Messaging> {
         let brains = self.brains.lock().await;
-        brains.get(name).map(Clone::clone)
+        brains.get(name).cloned()
     }
+
+    /// Claims `name` until the reservation is dropped, or nothing if it's already claimed
+    pub fn reserve(&self, name: &str) -> Option<Reservation> {
+        let reserved = self.reserved.lock().unwrap().insert(name.to_string());
+        reserved.then(|| Reservation {
+            names: self.reserved.clone(),
+            name: name.to_string(),
+        })
+    }
+
+    pub fn is_reserved(&self, name: &str) -> bool {
+        self.reserved.lock().unwrap().contains(name)
+    }
+
+    /// Where the brain called `name` is stored, or nothing if `name` isn't valid
+    pub fn path_for(&self, name: &str) -> Option<PathBuf> {
+        is_valid_name(name).then(|| self.directory.join(format!("{name}.sdb")))
+    }
+}
+
+/// A name that is being taken, see [`State::reserve`]
+pub struct Reservation {
+    names: Arc<std::sync::Mutex<HashSet<String>>>,
+    name: String,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.names.lock().unwrap().remove(&self.name);
+    }
+}
+
+/// Tells every open stream the server is shutting down, so it can stop waiting on them
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<bool>>);
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Waits until the server is shutting down, or forever if it never does
+    pub async fn wait(&self) {
+        let mut shutdown = self.0.subscribe();
+        while !*shutdown.borrow_and_update() {
+            if shutdown.changed().await.is_err() {
+                std::future::pending().await
+            }
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+}
+
+/// Limits that keep one client from tying up the server, or a brain
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest request body, in bytes
+    pub body: usize,
+    /// How many changes can wait for a brain, more than this are turned away
+    pub queue: usize,
+    /// How many requests can generate from a brain at once, more than this are turned away
+    pub readers: usize,
+    /// How long a request waits for its brain to respond
+    pub timeout: Duration,
+    /// How long a brain tries to generate a single sentence
+    pub generate: Duration,
 }
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            body: 8 * 1024 * 1024,
+            queue: 16,
+            readers: 8,
+            timeout: Duration::from_secs(30),
+            generate: GENERATE_TIMEOUT,
+        }
+    }
+}
+
+/// Names that would be shadowed by another endpoint
+pub const RESERVED_NAMES: &[&str] = &["brains", "metrics"];
+
+/// Names are up to 64 ASCII letters, digits, `_` or `-`, so they can't escape the directory
+///
+/// The [reserved names](RESERVED_NAMES) aren't valid either.
+pub fn is_valid_name(name: &str) -> bool {
+    !RESERVED_NAMES.contains(&name)
+        && (1..=64).contains(&name.len())
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-'))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,67 @@
+use super::*;
+
+#[test]
+fn names() {
+    for name in ["museun", "some_channel", "brain-2", "A"] {
+        assert!(is_valid_name(name), "{name}");
+    }
+    for name in [
+        "",
+        "..",
+        "../etc",
+        "a/b",
+        "a\\b",
+        "with space",
+        "dot.sdb",
+        "caf\u{e9}",
+        "brains",
+        "metrics",
+        "openapi.json",
+    ] {
+        assert!(!is_valid_name(name), "{name}");
+    }
+    assert!(!is_valid_name(&"a".repeat(65)));
+}
+
+#[test]
+fn path_for() {
+    let state = State {
+        directory: PathBuf::from("brains"),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits: Limits::default(),
+        shutdown: Shutdown::default(),
+    };
+    assert_eq!(
+        state.path_for("museun"),
+        Some(PathBuf::from("brains").join("museun.sdb"))
+    );
+    assert_eq!(state.path_for("../museun"), None);
+}
+
+#[test]
+fn reserve() {
+    let state = State {
+        directory: PathBuf::from("brains"),
+        brains: Arc::default(),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits: Limits::default(),
+        shutdown: Shutdown::default(),
+    };
+
+    let reservation = state.reserve("museun").unwrap();
+    assert!(state.is_reserved("museun"));
+    assert!(state.reserve("museun").is_none());
+    assert!(state.reserve("other").is_some());
+    assert!(!state.is_reserved("other"));
+
+    drop(reservation);
+    assert!(!state.is_reserved("museun"));
+    assert!(state.reserve("museun").is_some());
+}
@@ -0,0 +1,148 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use markov::{
+    format::{journal::Journal, SaveOptions},
+    Brain,
+};
+
+use crate::{
+    messaging::{Request, Response},
+    spawn_brain,
+    state::{Limits, Shutdown, State},
+    Messaging,
+};
+
+fn brain(dir: &Path, name: &str) -> Messaging {
+    let path = dir.join(format!("{name}.sdb"));
+    let brain = Brain::new(name, 2);
+    let journal = Journal::create(Journal::path_for(&path), brain.metadata()).unwrap();
+    spawn_brain(
+        brain,
+        path,
+        journal,
+        SaveOptions::default(),
+        Limits::default(),
+    )
+}
+
+#[tokio::test]
+async fn save_all() {
+    let (good, bad) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    let brains = [
+        ("good".to_string(), brain(good.path(), "good")),
+        ("bad".to_string(), brain(bad.path(), "bad")),
+    ];
+    for (_, brain) in &brains {
+        let data = "hello world".to_string();
+        assert!(matches!(
+            brain.send(Request::Train { data }).await,
+            Response::Nothing
+        ));
+    }
+
+    // nothing can be written where the bad brain lives anymore
+    let bad_path = bad.path().to_path_buf();
+    drop(bad);
+
+    let state = State {
+        directory: good.path().to_path_buf(),
+        brains: Arc::new(tokio::sync::Mutex::new(brains.into_iter().collect())),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits: Limits::default(),
+        shutdown: Shutdown::default(),
+    };
+
+    let failed = crate::save_all(&state, Duration::from_secs(5)).await;
+    assert_eq!(
+        failed.iter().map(|(name, _)| &**name).collect::<Vec<_>>(),
+        ["bad"]
+    );
+    assert!(!bad_path.exists());
+
+    let saved = markov::format::load_file(good.path().join("good.sdb")).unwrap();
+    assert_eq!(saved.metadata().lines, 1);
+}
+
+#[tokio::test]
+async fn open_brain() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("museun.sdb");
+
+    let brain = crate::open_brain(&path, "museun", SaveOptions::default(), Limits::default())
+        .await
+        .unwrap();
+    // a new brain is saved before it's trained, so its journal is kept when it's reopened
+    let saved = markov::format::load_file(&path).unwrap();
+    assert_eq!(saved.metadata().lines, 0);
+
+    let data = "hello world".to_string();
+    assert!(matches!(
+        brain.send(Request::Train { data }).await,
+        Response::Nothing
+    ));
+    drop(brain);
+
+    // the line was either saved or journaled, either way it's there
+    let brain = crate::open_brain(&path, "museun", SaveOptions::default(), Limits::default())
+        .await
+        .unwrap();
+    assert!(matches!(
+        brain.send(Request::ForceSave).await,
+        Response::Nothing
+    ));
+
+    let saved = markov::format::load_file(&path).unwrap();
+    assert_eq!(saved.metadata().lines, 1);
+}
+
+#[tokio::test]
+async fn shutdown_ends_streams() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = State {
+        directory: dir.path().to_path_buf(),
+        brains: Arc::new(tokio::sync::Mutex::new(
+            [("museun".to_string(), brain(dir.path(), "museun"))]
+                .into_iter()
+                .collect(),
+        )),
+        reserved: Arc::default(),
+        save_options: SaveOptions::default(),
+        filters: Arc::default(),
+        keys: Arc::default(),
+        limits: Limits::default(),
+        shutdown: Shutdown::default(),
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (stop, signal) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(crate::serve(
+        listener,
+        state,
+        Duration::from_secs(5),
+        async move {
+            let _ = signal.await;
+        },
+    ));
+
+    let uri = format!("http://{addr}/museun/stream").parse().unwrap();
+    let resp = hyper::Client::new().get(uri).await.unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // the subscriber is still connected when the server is told to stop
+    stop.send(()).unwrap();
+    let body = tokio::time::timeout(Duration::from_secs(5), hyper::body::to_bytes(resp))
+        .await
+        .expect("the stream never ended");
+    body.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("the server never shut down")
+        .unwrap()
+        .unwrap();
+    assert!(dir.path().join("museun.sdb").exists());
+}
@@ -5,7 +5,7 @@ use shaken::{
     error::DontCare,
     help::HelpRegistry,
     irc,
-    modules::{AnotherViewer, Builtin, Crates, Spotify, UserDefined},
+    modules::{open_backend, AnotherViewer, Builtin, Crates, Spotify, UserDefined},
     twitch::{data::EmoteMap, HelixClient, OAuth},
     Arguments, Callable, Request, ResponseKind, SharedState, State,
 };
@@ -53,12 +53,16 @@ async fn main() -> anyhow::Result<()> {
     .await?;
     log::info!("connected");
 
+    log::trace!("opening the brain");
+    let brain = open_backend(&config.brain).await?;
+
     let mut state = State::default();
     state.insert(identity);
     state.insert(config);
     state.insert(twitch_oauth);
     state.insert(twitch_client);
     state.insert(emote_map);
+    state.insert(brain);
 
     let state = SharedState::new(state);
 
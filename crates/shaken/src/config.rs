@@ -1,6 +1,7 @@
 use std::{
     fmt::{Debug, Display},
     ops::Deref,
+    path::PathBuf,
 };
 
 macro_rules! make_key {
@@ -24,6 +25,12 @@ make_key! {
     // spotify api
     SHAKEN_SPOTIFY_CLIENT_ID
     SHAKEN_SPOTIFY_CLIENT_SECRET
+    // serve_brain
+    SHAKEN_BRAIN_BACKEND
+    SHAKEN_BRAIN_NAME
+    SHAKEN_BRAIN_ADDRESS
+    SHAKEN_BRAIN_KEY
+    SHAKEN_BRAIN_PATH
 }
 
 #[derive(Debug)]
@@ -31,6 +38,7 @@ pub struct Config {
     pub irc: Irc,
     pub twitch: Twitch,
     pub spotify: Spotify,
+    pub brain: Brain,
 }
 
 impl Config {
@@ -50,6 +58,7 @@ impl Config {
                 client_id: get_var(SHAKEN_SPOTIFY_CLIENT_ID)?,
                 client_secret: get_var(SHAKEN_SPOTIFY_CLIENT_SECRET).map(Secret)?,
             },
+            brain: Brain::load_from_env()?,
         })
     }
 }
@@ -82,6 +91,45 @@ impl Debug for Spotify {
     }
 }
 
+#[derive(Debug)]
+pub struct Brain {
+    pub name: String,
+    pub backend: Backend,
+}
+
+impl Brain {
+    fn load_from_env() -> anyhow::Result<Self> {
+        let name = get_var_or(SHAKEN_BRAIN_NAME, || "museun")?;
+        let backend = match &*get_var_or(SHAKEN_BRAIN_BACKEND, || "http")? {
+            "http" => Backend::Http {
+                addr: get_var_or(SHAKEN_BRAIN_ADDRESS, || "http://localhost:50000")?,
+                key: get_var(SHAKEN_BRAIN_KEY).ok().map(Secret),
+            },
+            "local" => Backend::Local {
+                path: get_var(SHAKEN_BRAIN_PATH)
+                    .unwrap_or_else(|_| format!("{name}.sdb"))
+                    .into(),
+            },
+            backend => anyhow::bail!(
+                "env var `{SHAKEN_BRAIN_BACKEND}` must be `http` or `local`, not `{backend}`"
+            ),
+        };
+        Ok(Self { name, backend })
+    }
+}
+
+/// Where the brain lives
+#[derive(Debug)]
+pub enum Backend {
+    /// Talks to `serve_brain`, at an address like `http://localhost:50000`
+    Http {
+        addr: String,
+        key: Option<Secret<String>>,
+    },
+    /// Runs the brain in this process, saving it to `path`
+    Local { path: PathBuf },
+}
+
 fn get_var(key: &str) -> anyhow::Result<String> {
     anyhow::Context::with_context(std::env::var(key), || {
         anyhow::anyhow!("env var `{key}` must be set")
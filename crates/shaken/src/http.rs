@@ -63,42 +63,6 @@ impl Client {
     // }
 
     // TODO Request builder
-    pub async fn get_with_body(
-        &self,
-        ep: &str,
-        body: impl serde::Serialize + Send + 'static,
-    ) -> anyhow::Result<String> {
-        let req = Self::build(ep, std::iter::empty(), std::iter::empty(), |ep| {
-            self.0.get(ep)
-        });
-
-        tokio::task::spawn_blocking(move || {
-            req.send_json(body)
-                .map_err(anyhow::Error::from)?
-                .into_string()
-                .map_err(anyhow::Error::from)
-        })
-        .await?
-    }
-
-    pub async fn post_with_body(
-        &self,
-        ep: &str,
-        body: impl serde::Serialize + Send + 'static,
-    ) -> anyhow::Result<String> {
-        let req = Self::build(ep, std::iter::empty(), std::iter::empty(), |ep| {
-            self.0.post(ep)
-        });
-
-        tokio::task::spawn_blocking(move || {
-            req.send_json(body)
-                .map_err(anyhow::Error::from)?
-                .into_string()
-                .map_err(anyhow::Error::from)
-        })
-        .await?
-    }
-
     pub async fn get<'qk, 'qv, 'hk, 'hv, T, Q, H>(
         &self,
         ep: &str,
@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use tokio::{
     io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufStream},
     net::TcpStream,
@@ -7,7 +5,9 @@ use tokio::{
 
 use anyhow::Context as _;
 
-use super::{Identity, Privmsg, Tags};
+use irc_message::Message;
+
+use super::{Identity, Privmsg};
 
 pub struct Conn {
     pub(in crate::irc) stream: BufStream<TcpStream>,
@@ -28,26 +28,33 @@ impl Conn {
             self.buf.clear();
 
             let n = self.stream.read_line(&mut self.buf).await?;
-            let line = &self.buf[..n];
+            anyhow::ensure!(n > 0, "the connection was closed");
 
-            let (tags, prefix, cmd, args, data) = Self::parse(line);
-            let prefix = prefix.map(Arc::<str>::from);
-            let data = data.map(Arc::<str>::from);
+            let msg = match Message::parse(&self.buf[..n]) {
+                Some(msg) => msg,
+                None => {
+                    log::warn!("malformed message: {:?}", &self.buf[..n]);
+                    continue;
+                }
+            };
 
-            match cmd {
+            match msg.command {
                 "PING" => {
-                    let resp = format!("PONG :{}\r\n", data.unwrap());
+                    let resp = format!("PONG :{}\r\n", msg.data.unwrap_or_default());
                     self.stream.write_all(resp.as_bytes()).await?;
                     self.stream.flush().await?;
                 }
-                "ERROR" => anyhow::bail!("error: {:?}", data),
+                "ERROR" => anyhow::bail!("error: {:?}", msg.data),
                 "PRIVMSG" => {
-                    return Ok(Privmsg {
-                        tags,
-                        user: prefix.expect("prefix attached"),
-                        target: args[0].into(),
-                        data: data.expect("malformed message"),
-                    });
+                    if let (Some(user), Some((target, data))) = (msg.prefix, msg.privmsg()) {
+                        return Ok(Privmsg {
+                            user: user.into(),
+                            target: target.into(),
+                            data: data.into(),
+                            tags: msg.tags,
+                        });
+                    }
+                    log::warn!("malformed message: {:?}", &self.buf[..n]);
                 }
                 _ => {}
             }
@@ -68,88 +75,30 @@ impl Conn {
     ) -> anyhow::Result<Identity> {
         loop {
             let n = stream.read_line(buf).await?;
-            let mut raw = &buf[..n - 2];
-
-            let tags = raw
-                .starts_with('@')
-                .then(|| Tags::parse(&mut raw))
-                .flatten()
-                .unwrap_or_default();
-
-            match raw.split_once(' ') {
-                Some(("PING", tail)) => {
-                    let token = tail
-                        .rsplit_terminator(':')
-                        .next()
-                        .with_context(|| "PING must have a token")?;
-                    let out = format!("PONG :{token}\r\n");
-                    stream.write_all(out.as_bytes()).await?;
+            anyhow::ensure!(n > 0, "the connection was closed");
+
+            if let Some(msg) = Message::parse(&buf[..n]) {
+                match msg.command {
+                    "PING" => {
+                        let token = msg.data.with_context(|| "PING must have a token")?;
+                        let out = format!("PONG :{token}\r\n");
+                        stream.write_all(out.as_bytes()).await?;
+                    }
+                    "GLOBALUSERSTATE" => {
+                        let name = msg.tags.get("display-name").unwrap_or(default_name).into();
+                        let user_id = msg.tags.get_parsed("user-id")?;
+                        let identity = Identity { name, user_id };
+                        return Ok(identity);
+                    }
+                    "ERROR" => anyhow::bail!("{}", msg.data.unwrap_or_default()),
+                    _ => {}
                 }
-                Some((.., "GLOBALUSERSTATE")) => {
-                    let name = tags.get("display-name").unwrap_or(default_name).into();
-                    let user_id = tags.get_parsed("user-id")?;
-                    let identity = Identity { name, user_id };
-                    return Ok(identity);
-                }
-                Some(("ERROR", tail)) => anyhow::bail!("{tail}"),
-                _ => {}
             }
 
             buf.clear();
         }
     }
 
-    fn prefix<'a>(input: &mut &'a str) -> Option<&'a str> {
-        if input.starts_with(':') {
-            let (head, tail) = input.split_once(' ').expect("malformed message");
-            *input = tail;
-            return head[1..].split_terminator('!').next();
-        }
-        None
-    }
-
-    fn command<'a>(input: &mut &'a str) -> &'a str {
-        // TODO we got a panic ehre
-        let (head, tail) = input.split_once(' ').expect("malformed message");
-        *input = tail;
-        head
-    }
-
-    fn args<'a>(input: &mut &'a str) -> Vec<&'a str> {
-        if let Some((head, tail)) = input.split_once(':') {
-            *input = tail;
-            head.split_ascii_whitespace().collect()
-        } else {
-            vec![]
-        }
-    }
-
-    fn data<'a>(input: &mut &'a str) -> Option<&'a str> {
-        Some(input.trim_end()).filter(|s| !s.is_empty())
-    }
-
-    fn parse(mut line: &str) -> (Tags, Option<&str>, &str, Vec<&str>, Option<&str>) {
-        let line = &mut line;
-        let tags = if line.starts_with('@') {
-            Tags::parse(line)
-        } else {
-            None
-        }
-        .unwrap_or_default();
-
-        let prefix = if line.starts_with(':') {
-            Self::prefix(line).map(Into::into)
-        } else {
-            None
-        };
-
-        let command = Self::command(line);
-        let args = Self::args(line);
-        let data = Self::data(line).map(Into::into);
-
-        (tags, prefix, command, args, data)
-    }
-
     /*
     pub async fn write_raw(&self, data: impl AsRef<[u8]>) -> std::io::Result<()> {
         { &self.write }.write_all(data.as_ref()).await?;
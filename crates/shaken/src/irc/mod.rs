@@ -1,7 +1,6 @@
 pub const TWITCH_NO_TLS: &str = "irc.chat.twitch.tv:6667";
 
-mod tags;
-pub use tags::Tags;
+pub use irc_message::Tags;
 
 mod conn;
 pub use conn::Conn;
@@ -0,0 +1,126 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use brain::{Messaging, Request, Response};
+use brain_client::{request, BrainClient, StatusCode};
+
+use crate::{config, BoxedFuture};
+
+/// Where [`AnotherViewer`](super::AnotherViewer) trains and generates
+pub trait BrainBackend: Send + Sync {
+    fn generate(&self, opts: request::Generate) -> BoxedFuture<'_, anyhow::Result<String>>;
+    /// Trains a line that has already been filtered
+    fn train(&self, data: String) -> BoxedFuture<'_, anyhow::Result<()>>;
+}
+
+/// Opens the backend the config asks for
+pub async fn open_backend(config: &config::Brain) -> anyhow::Result<Arc<dyn BrainBackend>> {
+    Ok(match &config.backend {
+        config::Backend::Http { addr, key } => {
+            let mut client = BrainClient::new(addr).with_timeout(HttpBackend::TIMEOUT);
+            if let Some(key) = key {
+                client = client.with_key(&**key);
+            }
+            Arc::new(HttpBackend::new(client, &config.name))
+        }
+        config::Backend::Local { path } => {
+            Arc::new(LocalBackend::open(path.clone(), &config.name).await?)
+        }
+    })
+}
+
+/// A brain served by `serve_brain`
+pub struct HttpBackend {
+    client: BrainClient,
+    name: String,
+}
+
+impl HttpBackend {
+    // the server gives up on a single sentence after 5 seconds
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new(client: BrainClient, name: impl ToString) -> Self {
+        Self {
+            client,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl BrainBackend for HttpBackend {
+    fn generate(&self, opts: request::Generate) -> BoxedFuture<'_, anyhow::Result<String>> {
+        Box::pin(async move {
+            let resp = self.client.generate(&self.name, &opts).await?;
+            Ok(resp.data)
+        })
+    }
+
+    fn train(&self, data: String) -> BoxedFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            // the brain usually exists already, and a key that can only train can't create it
+            match self.client.train(&self.name, &*data).await {
+                Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => {}
+                resp => return Ok(resp?),
+            }
+
+            match self.client.create(&self.name, None).await {
+                Err(err) if err.status() != Some(StatusCode::CONFLICT) => return Err(err.into()),
+                _ => {}
+            }
+            Ok(self.client.train(&self.name, data).await?)
+        })
+    }
+}
+
+/// A brain running in this process, on the same thread `serve_brain` gives each brain
+///
+/// Every line is journaled as it's trained, and the brain is saved every
+/// [`brain::SAVE_DURATION`], so nothing is lost if the process stops.
+pub struct LocalBackend {
+    brain: Messaging,
+}
+
+impl LocalBackend {
+    /// Opens the brain saved at `path`, or starts a new one called `name`
+    pub async fn open(path: PathBuf, name: &str) -> anyhow::Result<Self> {
+        let brain = brain::open_brain(&path, name, Default::default(), Default::default()).await?;
+        log::info!("opened the brain at {}", path.display());
+
+        tokio::task::spawn({
+            let brain = brain.clone();
+            async move {
+                let mut interval = tokio::time::interval(brain::SAVE_DURATION);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if let Response::Error { error } = brain.send(Request::Save).await {
+                        log::warn!("cannot save the brain: {error}");
+                    }
+                }
+            }
+        });
+
+        Ok(Self { brain })
+    }
+}
+
+impl BrainBackend for LocalBackend {
+    fn generate(&self, opts: request::Generate) -> BoxedFuture<'_, anyhow::Result<String>> {
+        Box::pin(async move {
+            opts.validate().map_err(anyhow::Error::msg)?;
+            match self.brain.generate(opts).await? {
+                Response::Generated { data } => Ok(data),
+                Response::Error { error } => Err(error),
+                _ => anyhow::bail!("the brain didn't generate anything"),
+            }
+        })
+    }
+
+    fn train(&self, data: String) -> BoxedFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            match self.brain.request(Request::Train { data }).await? {
+                Response::Error { error } => Err(error),
+                _ => Ok(()),
+            }
+        })
+    }
+}
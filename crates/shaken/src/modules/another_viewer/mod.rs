@@ -3,23 +3,29 @@ use std::{
     time::{Duration, Instant},
 };
 
+use brain_client::request;
 use fastrand_ext::IterExt;
+use filters::Filters;
 use tokio::sync::Mutex;
 
 use crate::{
-    error::ErrorExt, http, twitch::data::EmoteMap, util::IterExt as _, Binding, Request, Response,
+    error::ErrorExt, twitch::data::EmoteMap, util::IterExt as _, Binding, Request, Response,
     SharedState,
 };
 
-struct Config {
+mod backend;
+pub use backend::{open_backend, BrainBackend, HttpBackend, LocalBackend};
+
+struct Settings {
     max: usize,
     min: usize,
     cooldown: Duration,
     kappa_chance: f32,
     context_chance: f32,
+    filters: Filters,
 }
 
-impl Default for Config {
+impl Default for Settings {
     fn default() -> Self {
         Self {
             max: 30,
@@ -27,20 +33,34 @@ impl Default for Config {
             cooldown: Duration::from_secs(30),
             kappa_chance: 0.5,
             context_chance: 0.7,
+            // TODO make the names configurable
+            filters: Filters {
+                names: vec!["shaken_bot".into(), "shaken".into()],
+                emote_only: true,
+                max_repeat: Some(6),
+                ..Filters::standard()
+            },
         }
     }
 }
 
-#[derive(Default)]
 pub struct AnotherViewer {
-    client: http::Client,
-    config: Config,
+    brain: Arc<dyn BrainBackend>,
+    config: Settings,
     last: Mutex<Option<Instant>>,
 }
 
 impl AnotherViewer {
-    pub async fn create(_: SharedState) -> anyhow::Result<Binding<Self>> {
-        Binding::create(Self::default())
+    /// Uses the `Arc<dyn BrainBackend>` in the state, see [`open_backend`]
+    pub async fn create(state: SharedState) -> anyhow::Result<Binding<Self>> {
+        let brain = Arc::clone(&*state.get::<Arc<dyn BrainBackend>>().await);
+        let this = Self {
+            brain,
+            config: Settings::default(),
+            last: Mutex::default(),
+        };
+
+        Binding::create(this)
             .bind_this(
                 "!speak <context..>",
                 "tries to speak like a twitch viewer, with optional context",
@@ -58,42 +78,23 @@ impl AnotherViewer {
         Response::nothing()
     }
 
-    // TODO pick this from the channel
-    const CREATE: &'static str = "http://localhost:50000/museun/create";
-    const TRAIN: &'static str = "http://localhost:50000/museun/train";
-    const GENERATE: &'static str = "http://localhost:50000/museun/generate";
-
     async fn train(self: Arc<Self>, req: Request) -> anyhow::Result<Response> {
         check!(!req.data.starts_with('!'));
 
-        let data = filters::filter(req.data());
-        if data.is_empty() {
-            return Response::nothing();
-        }
-
-        #[derive(serde::Serialize)]
-        struct Train {
-            data: String,
-        }
-
-        #[derive(serde::Serialize)]
-        struct Create {
-            path: String,
-            depth: usize,
-        }
-
-        let body = Create {
-            path: "db/museun.sdb".to_string(),
-            depth: 5,
+        let data = {
+            let map = req.state.get::<EmoteMap>().await;
+            self.config
+                .filters
+                .apply_with(req.data(), |word| map.has(word))
+        };
+        let data = match data {
+            Some(data) => data,
+            None => return Response::nothing(),
         };
-        // TODO check this error
-        let _ = self.client.post_with_body(Self::CREATE, body).await;
 
-        let body = Train { data };
-        self.client
-            .post_with_body(Self::TRAIN, body)
-            .await
-            .dont_care()?;
+        if let Err(err) = self.brain.train(data).await {
+            log::warn!("cannot train the brain: {err}");
+        }
 
         Response::nothing()
     }
@@ -184,28 +185,21 @@ impl AnotherViewer {
         None
     }
 
-    // TODO time this out incase the server is stuck in a loop
     async fn generate(&self, query: Option<impl ToString + Send>) -> Option<String> {
-        #[derive(serde::Serialize)]
-        struct Opts {
-            min: usize,
-            max: usize,
-            query: Option<String>,
-        }
-
-        let opts = Opts {
+        let opts = request::Generate {
             min: self.config.min,
             max: self.config.max,
             query: query.map(|s| s.to_string()),
+            ..request::Generate::default()
         };
 
-        let data = self.client.get_with_body(Self::GENERATE, opts).await.ok()?;
-
-        #[derive(serde::Deserialize)]
-        struct Generate {
-            data: String,
-        }
-        let Generate { data } = serde_json::from_str(&data).ok()?;
+        let data = match self.brain.generate(opts).await {
+            Ok(data) => data,
+            Err(err) => {
+                log::debug!("cannot generate: {err}");
+                return None;
+            }
+        };
 
         self.update_last_seen().await;
         Some(Self::filter_response(data))
@@ -235,63 +229,5 @@ impl AnotherViewer {
     }
 }
 
-mod filters {
-    use regex::Regex;
-
-    use crate::util::IterExt;
-
-    pub fn filter(input: &str) -> String {
-        [
-            remove_name, //
-            remove_links,
-            remove_garbage,
-            filter_commands,
-            filter_mentions,
-        ]
-        .into_iter()
-        .fold(input.to_string(), |input, func| func(&*input))
-    }
-
-    fn remove_name(input: &str) -> String {
-        static PATTERN: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
-            Regex::new(
-                r#"(?x)
-            ['"\(\[@\#]*?        # leading
-            (shaken|shaken_bot) # name
-            [,.!?:'"\)\]]*?     # trailing
-            "#,
-            )
-            .unwrap()
-        });
-
-        PATTERN.replace_all(input, "").to_string()
-    }
-
-    fn filter_commands(input: &str) -> String {
-        input
-            .split_ascii_whitespace()
-            .filter(|c| !c.starts_with('!'))
-            .join_with(' ')
-    }
-
-    fn filter_mentions(input: &str) -> String {
-        input
-            .split_ascii_whitespace()
-            .filter(|c| !c.starts_with('@'))
-            .join_with(' ')
-    }
-
-    fn remove_links(input: &str) -> String {
-        input
-            .split_ascii_whitespace()
-            .filter(|c| url::Url::parse(c).is_err())
-            .join_with(' ')
-    }
-
-    fn remove_garbage(input: &str) -> String {
-        input
-            .split_ascii_whitespace()
-            .filter(|c| c.chars().all(|c| c.is_ascii()))
-            .join_with(' ')
-    }
-}
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,46 @@
+use super::*;
+use crate::{testing::Mock, ResponseKind, State};
+
+async fn local(dir: &std::path::Path) -> Arc<dyn BrainBackend> {
+    let backend = LocalBackend::open(dir.join("test.sdb"), "test")
+        .await
+        .unwrap();
+    Arc::new(backend)
+}
+
+#[tokio::test]
+async fn local_backend() {
+    let dir = tempfile::tempdir().unwrap();
+    let brain = local(dir.path()).await;
+    brain.train("hello world".into()).await.unwrap();
+
+    let opts = request::Generate {
+        min: 1,
+        max: 2,
+        ..request::Generate::default()
+    };
+    assert_eq!(brain.generate(opts).await.unwrap(), "hello world");
+
+    let opts = request::Generate {
+        min: 0,
+        ..request::Generate::default()
+    };
+    assert!(brain.generate(opts).await.is_err());
+}
+
+#[tokio::test]
+async fn speak() {
+    let dir = tempfile::tempdir().unwrap();
+    let brain = local(dir.path()).await;
+    brain.train("hello world".into()).await.unwrap();
+
+    let mut mock = AnotherViewer::create
+        .mock_with_state(State::default().with(brain))
+        .await;
+
+    mock.send_message("!speak").await;
+    match &*mock.get_response().kind {
+        [ResponseKind::Say(data)] => assert!(data.starts_with("hello world"), "{data}"),
+        resp => panic!("unexpected response: {resp:?}"),
+    }
+}
@@ -8,7 +8,7 @@ mod crates;
 pub use crates::Crates;
 
 mod another_viewer;
-pub use another_viewer::AnotherViewer;
+pub use another_viewer::{open_backend, AnotherViewer, BrainBackend, HttpBackend, LocalBackend};
 
 mod builtin;
 pub use builtin::Builtin;
@@ -134,17 +134,11 @@ pub struct TestBinding<T> {
 
 impl<T> TestBinding<T> {
     fn insert_badge(&mut self, key: &str, val: &str) {
-        use std::collections::hash_map::Entry::*;
-        match self.tags.map.entry(Box::from("badges")) {
-            Occupied(mut e) => {
-                let mut s = e.get().to_string();
-                s.push_str(&format!(",{key}/{val}"));
-                *e.get_mut() = s.into_boxed_str();
-            }
-            Vacant(e) => {
-                e.insert(format!("{key}/{val}").into_boxed_str());
-            }
-        }
+        let badges = match self.tags.get("badges") {
+            Ok(badges) => format!("{badges},{key}/{val}"),
+            Err(..) => format!("{key}/{val}"),
+        };
+        self.tags.insert("badges", badges);
     }
 
     pub fn get_inner(&self) -> &T
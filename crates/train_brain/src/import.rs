@@ -0,0 +1,190 @@
+use std::{collections::HashSet, io::BufRead};
+
+use anyhow::Context as _;
+use filters::Filters;
+use irc_message::Message;
+
+use crate::input::Input;
+
+/// Accounts that are commonly used by chat bots
+const KNOWN_BOTS: &[&str] = &[
+    "fossabot",
+    "moobot",
+    "nightbot",
+    "soundalerts",
+    "streamelements",
+    "streamlabs",
+    "wizebot",
+];
+
+/// How each input is laid out
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// A message per line
+    #[default]
+    Raw,
+    /// Raw Twitch IRC lines, only `PRIVMSG`s are used
+    Irc,
+    /// A JSON object per line
+    Jsonl,
+    /// CSV with a header row
+    Csv,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "raw" => Self::Raw,
+            "irc" => Self::Irc,
+            "jsonl" => Self::Jsonl,
+            "csv" => Self::Csv,
+            s => {
+                return Err(format!(
+                    "unknown format: {s} (expected raw, irc, jsonl or csv)"
+                ))
+            }
+        })
+    }
+}
+
+/// Which messages are skipped
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Lowercased names of users to skip
+    pub users: HashSet<String>,
+    /// Skip messages starting with this
+    pub command_prefix: Option<String>,
+}
+
+impl Filter {
+    pub fn skip_bots(&mut self) {
+        self.users
+            .extend(KNOWN_BOTS.iter().map(|name| name.to_string()))
+    }
+
+    fn allows(&self, user: Option<&str>, data: &str) -> bool {
+        if let Some(prefix) = &self.command_prefix {
+            if data.starts_with(&**prefix) {
+                return false;
+            }
+        }
+
+        let user = user.map(|user| user.trim_start_matches('@').to_lowercase());
+        !matches!(user, Some(user) if self.users.contains(&user))
+    }
+}
+
+/// Reads messages out of the inputs
+#[derive(Debug)]
+pub struct Importer {
+    pub format: Format,
+    /// The JSON field (a `.` separated path) or CSV column (a name or an index) of the message
+    pub field: String,
+    /// Where the user is, like `field`
+    pub user_field: Option<String>,
+    pub filter: Filter,
+    /// How the text of each message is cleaned up
+    pub filters: Filters,
+}
+
+impl Importer {
+    /// Calls `f` with every message of every input that isn't filtered out, in order
+    pub fn for_each_message(
+        &self,
+        inputs: &[Input],
+        mut f: impl FnMut(String) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        for input in inputs {
+            let reader = input.open()?;
+            let mut f = |user: Option<&str>, data: String| {
+                if !self.filter.allows(user, &data) {
+                    return Ok(());
+                }
+                match self.filters.apply(&data) {
+                    Some(data) => f(data),
+                    None => Ok(()),
+                }
+            };
+
+            if self.format == Format::Csv {
+                self.read_csv(reader, &mut f)
+                    .with_context(|| format!("cannot read {input}"))?;
+                continue;
+            }
+
+            for (n, line) in reader.lines().enumerate() {
+                let line = line.with_context(|| format!("cannot read {input}"))?;
+                match self.format {
+                    Format::Raw => f(None, line)?,
+                    Format::Irc => {
+                        if let Some((user, data)) = irc(&line) {
+                            f(Some(user), data.to_string())?
+                        }
+                    }
+                    Format::Jsonl if line.trim().is_empty() => {}
+                    Format::Jsonl => {
+                        let value = serde_json::from_str::<serde_json::Value>(&line)
+                            .with_context(|| format!("invalid json at {input}:{}", n + 1))?;
+                        let field = |path: &str| lookup(&value, path).and_then(|v| v.as_str());
+                        if let Some(data) = field(&self.field) {
+                            let user = self.user_field.as_deref().and_then(field);
+                            f(user, data.to_string())?
+                        }
+                    }
+                    Format::Csv => unreachable!(),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_csv(
+        &self,
+        reader: impl BufRead,
+        f: &mut impl FnMut(Option<&str>, String) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .or_else(|| name.parse().ok())
+                .with_context(|| format!("unknown column: {name}"))
+        };
+
+        let field = column(&self.field)?;
+        let user = self.user_field.as_deref().map(column).transpose()?;
+
+        for record in reader.records() {
+            let record = record?;
+            if let Some(data) = record.get(field) {
+                let user = user.and_then(|user| record.get(user));
+                f(user, data.to_string())?
+            }
+        }
+        Ok(())
+    }
+}
+
+// the user and message of a PRIVMSG, with the `/me` wrapping removed
+fn irc(line: &str) -> Option<(&str, &str)> {
+    let msg = Message::parse(line)?;
+    let (_, data) = msg.privmsg()?;
+    let data = data
+        .strip_prefix("\x01ACTION ")
+        .map(|data| data.trim_end_matches('\x01'))
+        .unwrap_or(data);
+    Some((msg.prefix?, data))
+}
+
+fn lookup<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        serde_json::Value::Array(list) => list.get(key.parse::<usize>().ok()?),
+        value => value.get(key),
+    })
+}
+
+#[cfg(test)]
+mod tests;
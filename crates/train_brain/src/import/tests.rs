@@ -0,0 +1,71 @@
+use super::*;
+
+#[test]
+fn irc_privmsg() {
+    let line = "@badges=;display-name=Someone :someone!someone@someone.tmi.twitch.tv PRIVMSG #museun :hello world";
+    assert_eq!(irc(line), Some(("someone", "hello world")));
+
+    let action = ":someone!someone@someone.tmi.twitch.tv PRIVMSG #museun :\x01ACTION waves\x01";
+    assert_eq!(irc(action), Some(("someone", "waves")));
+
+    assert_eq!(irc("PING :tmi.twitch.tv"), None);
+    assert_eq!(irc(":tmi.twitch.tv 001 shaken :Welcome, GLHF!"), None);
+}
+
+#[test]
+fn json_lookup() {
+    let value = serde_json::json!({
+        "message": { "body": "hello" },
+        "emotes": [{ "name": "Kappa" }]
+    });
+    assert_eq!(lookup(&value, "message.body"), Some(&"hello".into()));
+    assert_eq!(lookup(&value, "emotes.0.name"), Some(&"Kappa".into()));
+    assert_eq!(lookup(&value, "message.missing"), None);
+    assert_eq!(lookup(&value, "emotes.x"), None);
+}
+
+#[test]
+fn filter() {
+    let mut filter = Filter {
+        users: ["someone".to_string()].into_iter().collect(),
+        command_prefix: Some("!".into()),
+    };
+    filter.skip_bots();
+
+    assert!(filter.allows(Some("museun"), "hello"));
+    assert!(filter.allows(None, "hello"));
+    assert!(!filter.allows(Some("SomeOne"), "hello"));
+    assert!(!filter.allows(Some("@Nightbot"), "hello"));
+    assert!(!filter.allows(Some("museun"), "!uptime"));
+}
+
+#[test]
+fn csv_columns() {
+    let data = "time,user,message\n1,museun,hello\n2,nightbot,\"a, quoted\nline\"\n";
+    let read = |field: &str, user_field: Option<&str>| {
+        let importer = Importer {
+            format: Format::Csv,
+            field: field.into(),
+            user_field: user_field.map(Into::into),
+            filter: Filter::default(),
+            filters: Filters::default(),
+        };
+        let mut out = vec![];
+        importer
+            .read_csv(data.as_bytes(), &mut |user, data| {
+                out.push((user.map(ToString::to_string), data));
+                Ok(())
+            })
+            .map(|_| out)
+    };
+
+    assert_eq!(
+        read("message", Some("user")).unwrap(),
+        vec![
+            (Some("museun".into()), "hello".into()),
+            (Some("nightbot".into()), "a, quoted\nline".into())
+        ]
+    );
+    assert_eq!(read("2", None).unwrap()[0], (None, "hello".into()));
+    assert!(read("missing", None).is_err());
+}
@@ -0,0 +1,85 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::PathBuf,
+};
+
+use anyhow::Context as _;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Somewhere to read lines from
+#[derive(Debug, Clone)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    /// Expands `-` to stdin, and every other pattern as a glob
+    ///
+    /// Nothing at all also reads from stdin.
+    pub fn expand(patterns: &[String]) -> anyhow::Result<Vec<Self>> {
+        if patterns.is_empty() {
+            return Ok(vec![Self::Stdin]);
+        }
+
+        let mut inputs = vec![];
+        for pattern in patterns {
+            if pattern == "-" {
+                inputs.push(Self::Stdin);
+                continue;
+            }
+
+            let start = inputs.len();
+            for path in glob::glob(pattern).with_context(|| format!("invalid glob: {pattern}"))? {
+                inputs.push(Self::File(path?));
+            }
+            anyhow::ensure!(inputs.len() > start, "no files match: {pattern}");
+        }
+        Ok(inputs)
+    }
+
+    /// Opens the input, transparently decompressing gzip and zstd
+    pub fn open(&self) -> anyhow::Result<Box<dyn BufRead>> {
+        let reader: Box<dyn Read> = match self {
+            Self::Stdin => Box::new(std::io::stdin().lock()),
+            Self::File(path) => Box::new(
+                File::open(path).with_context(|| format!("cannot open {}", path.display()))?,
+            ),
+        };
+
+        let mut reader = BufReader::new(reader);
+        let magic = reader.fill_buf()?;
+        Ok(if magic.starts_with(&GZIP_MAGIC) {
+            Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+        } else if magic.starts_with(&ZSTD_MAGIC) {
+            Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+        } else {
+            Box::new(reader)
+        })
+    }
+
+    /// Calls `f` with every line of every input, in order
+    pub fn for_each_line(
+        inputs: &[Self],
+        mut f: impl FnMut(String) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        for input in inputs {
+            for line in input.open()?.lines() {
+                f(line.with_context(|| format!("cannot read {input}"))?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stdin => f.write_str("stdin"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
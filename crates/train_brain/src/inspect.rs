@@ -0,0 +1,221 @@
+use std::path::PathBuf;
+
+use markov::{GenerateOutcome, GenerateRequest, SamplingConfig};
+
+use crate::load;
+
+#[derive(Debug, gumdrop::Options)]
+pub struct Stats {
+    #[options(help = "print help message")]
+    help: bool,
+
+    #[options(free, required, help = "the brain to summarize")]
+    brain: PathBuf,
+
+    #[options(
+        help = "number of most frequent words to show",
+        short = "t",
+        default = "10",
+        meta = "int"
+    )]
+    top: usize,
+
+    #[options(help = "print json instead of text", short = "j")]
+    json: bool,
+}
+
+#[derive(Debug, gumdrop::Options)]
+pub struct Generate {
+    #[options(help = "print help message")]
+    help: bool,
+
+    #[options(free, required, help = "the brain to generate from")]
+    brain: PathBuf,
+
+    #[options(
+        help = "seed for the first sentence, each one after that uses the next seed",
+        short = "s",
+        meta = "int"
+    )]
+    seed: Option<u64>,
+
+    #[options(
+        help = "words to weave into the sentences",
+        short = "q",
+        meta = "string"
+    )]
+    query: Option<String>,
+
+    #[options(
+        help = "number of sentences to generate",
+        short = "n",
+        default = "5",
+        meta = "int"
+    )]
+    count: usize,
+
+    #[options(
+        help = "minimum number of words",
+        no_short,
+        default = "3",
+        meta = "int"
+    )]
+    min: usize,
+
+    #[options(
+        help = "maximum number of words",
+        no_short,
+        default = "20",
+        meta = "int"
+    )]
+    max: usize,
+
+    #[options(
+        help = "below 1.0 favors common words, above 1.0 flattens the distribution",
+        short = "t",
+        default = "1.0",
+        meta = "float"
+    )]
+    temperature: f64,
+
+    #[options(
+        help = "only sample from the k most likely words",
+        short = "k",
+        meta = "int"
+    )]
+    top_k: Option<usize>,
+
+    #[options(
+        help = "only sample from the most likely words whose probabilities add up to p",
+        short = "p",
+        meta = "float"
+    )]
+    top_p: Option<f64>,
+
+    #[options(
+        help = "the shortest context width that is consulted",
+        short = "c",
+        default = "1",
+        meta = "int"
+    )]
+    min_context: usize,
+}
+
+#[derive(Debug, gumdrop::Options)]
+pub struct Dump {
+    #[options(help = "print help message")]
+    help: bool,
+
+    #[options(free, required, help = "the brain to look in")]
+    brain: PathBuf,
+
+    #[options(free, required, help = "the word to look for")]
+    word: String,
+
+    #[options(help = "print json instead of text", short = "j")]
+    json: bool,
+}
+
+#[derive(Debug, gumdrop::Options)]
+pub struct Diff {
+    #[options(help = "print help message")]
+    help: bool,
+
+    #[options(free, required, help = "the older brain")]
+    old: PathBuf,
+
+    #[options(free, required, help = "the newer brain")]
+    new: PathBuf,
+
+    #[options(
+        help = "number of most frequent added and removed words to show",
+        short = "t",
+        default = "10",
+        meta = "int"
+    )]
+    top: usize,
+
+    #[options(help = "print json instead of text", short = "j")]
+    json: bool,
+}
+
+/// Prints statistics about a brain
+pub fn stats(config: Stats) -> anyhow::Result<()> {
+    let stats = load(&config.brain)?.stats(config.top);
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{stats}");
+    }
+    Ok(())
+}
+
+/// Prints sentences generated from a brain
+pub fn generate(config: Generate) -> anyhow::Result<()> {
+    anyhow::ensure!(config.min <= config.max, "min must not be more than max");
+
+    let brain = load(&config.brain)?;
+    let mut req = GenerateRequest {
+        min: config.min,
+        max: config.max,
+        query: config.query,
+        seed: config.seed,
+        sampling: SamplingConfig {
+            temperature: config.temperature,
+            top_k: config.top_k,
+            top_p: config.top_p,
+            min_context: config.min_context,
+            ..SamplingConfig::default()
+        },
+        ..GenerateRequest::default()
+    };
+
+    for _ in 0..config.count {
+        match brain.generate(&req) {
+            GenerateOutcome::Generated(data) => println!("{data}"),
+            // this one sentence took too long, the next one might not
+            outcome @ GenerateOutcome::TimedOut => eprintln!("{outcome}"),
+            outcome => anyhow::bail!("{outcome}"),
+        }
+        req.seed = req.seed.map(|seed| seed.wrapping_add(1));
+    }
+    Ok(())
+}
+
+/// Prints every context a word appears in, and what can follow it
+pub fn dump(config: Dump) -> anyhow::Result<()> {
+    let contexts = load(&config.brain)?.contexts(&config.word);
+    anyhow::ensure!(
+        !contexts.is_empty(),
+        "{} doesn't contain {}",
+        config.brain.display(),
+        config.word
+    );
+
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&contexts)?);
+    } else {
+        contexts.iter().for_each(|context| println!("{context}"));
+    }
+    Ok(())
+}
+
+/// Prints what changed between two brains
+pub fn diff(config: Diff) -> anyhow::Result<()> {
+    let (old, new) = (load(&config.old)?, load(&config.new)?);
+    if old.depth() != new.depth() {
+        eprintln!(
+            "the brains have different depths ({} and {}), longer contexts won't match",
+            old.depth(),
+            new.depth()
+        );
+    }
+
+    let diff = old.diff(&new, config.top);
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{diff}");
+    }
+    Ok(())
+}
@@ -1,105 +1,91 @@
-use std::{fs::File, path::Path};
+use std::path::Path;
 
 use gumdrop::Options;
-use indicatif::{ProgressBar, ProgressStyle};
-use markov::Brain;
-use snap::write::FrameEncoder;
+use markov::{format, Brain, FrozenBrain};
 
-#[derive(Debug, gumdrop::Options)]
-struct Config {
+mod import;
+mod input;
+mod inspect;
+mod train;
+
+#[derive(Debug, Options)]
+struct Args {
     #[options(help = "print help message")]
     help: bool,
 
-    #[options(
-        help = "raw input file to train from",
-        short = "i",
-        required,
-        meta = "path"
-    )]
-    input: String,
-
-    #[options(
-        help = "output path",
-        short = "o",
-        default = "output.sdb",
-        meta = "path"
-    )]
-    output: String,
-
-    #[options(
-        help = "name of the brain",
-        short = "n",
-        default = "corpora",
-        meta = "string"
-    )]
-    name: String,
-
-    #[options(help = "display a progress bar", short = "p", default = "false")]
-    progress: bool,
-
-    #[options(help = "ngram size", short = "d", default = "5", meta = "int")]
-    depth: usize,
+    #[options(command)]
+    command: Option<Command>,
 }
 
-#[derive(Default)]
-enum Progress {
-    Show(ProgressBar),
-    #[default]
-    Hide,
+#[derive(Debug, Options)]
+enum Command {
+    #[options(help = "train a brain, this is the default when no command is given")]
+    Train(train::Config),
+    #[options(help = "print statistics about a brain")]
+    Stats(inspect::Stats),
+    #[options(help = "generate sentences from a brain")]
+    Generate(inspect::Generate),
+    #[options(help = "print the contexts a word appears in")]
+    Dump(inspect::Dump),
+    #[options(help = "compare two brains")]
+    Diff(inspect::Diff),
 }
 
-impl Progress {
-    fn show(max: u64) -> Self {
-        let template = "{percent:>3}% {wide_bar:.red/white.dim} {pos}/{len}";
-        let display = "══━";
-
-        let bar = ProgressBar::new(max);
-        let style = ProgressStyle::with_template(template)
-            .unwrap()
-            .progress_chars(display);
-        bar.set_style(style);
+fn main() -> anyhow::Result<()> {
+    match parse_args().command {
+        Some(Command::Train(config)) => train::run(config),
+        Some(Command::Stats(config)) => inspect::stats(config),
+        Some(Command::Generate(config)) => inspect::generate(config),
+        Some(Command::Dump(config)) => inspect::dump(config),
+        Some(Command::Diff(config)) => inspect::diff(config),
+        None => unreachable!("train is the default command"),
+    }
+}
 
-        Self::Show(bar)
+// like `Options::parse_args_default_or_exit`, but a missing command means `train`
+fn parse_args() -> Args {
+    const COMMANDS: &[&str] = &["train", "stats", "generate", "dump", "diff"];
+
+    let mut args = std::env::args();
+    let program = args.next().unwrap_or_else(|| "train_brain".into());
+    let mut args = args.collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("-h" | "--help") => {}
+        Some(command) if COMMANDS.contains(&command) => {}
+        _ => args.insert(0, "train".into()),
     }
-    fn tick(&self) {
-        if let Self::Show(bar) = self {
-            bar.inc(1)
+
+    let opts = Args::parse_args_default(&args).unwrap_or_else(|err| {
+        eprintln!("{program}: {err}");
+        std::process::exit(2)
+    });
+
+    if opts.help_requested() {
+        let (usage, name) = match opts.command() {
+            Some(command) => (command.self_usage(), command.command_name()),
+            None => (opts.self_usage(), None),
+        };
+        match name {
+            Some(name) => eprintln!("Usage: {program} {name} [OPTIONS]"),
+            None => eprintln!("Usage: {program} [COMMAND] [OPTIONS]"),
         }
-    }
-    fn done(&self) {
-        if let Self::Show(bar) = self {
-            bar.finish();
+        eprintln!();
+        eprintln!("{usage}");
+        if let Some(commands) = opts.self_command_list().filter(|_| name.is_none()) {
+            eprintln!();
+            eprintln!("Available commands:");
+            eprintln!("{commands}");
         }
+        std::process::exit(0)
     }
-}
 
-fn save(brain: &Brain, path: impl AsRef<Path>) -> anyhow::Result<()> {
-    let path = path.as_ref();
-    let writer = File::create(path)?;
-    let mut writer = FrameEncoder::new(writer);
-    bincode::serialize_into(&mut writer, brain)?;
-    Ok(())
+    opts
 }
 
-fn main() -> anyhow::Result<()> {
-    let config = Config::parse_args_default_or_exit();
-    let mut brain = Brain::new(&config.name, config.depth);
-
-    let data = std::fs::read_to_string(&config.input)?;
-    let max = data.lines().count();
-
-    let bar = config
-        .progress
-        .then(|| Progress::show(max as _))
-        .unwrap_or_default();
-
-    for line in data.lines() {
-        bar.tick();
-        brain.train(line);
+/// Loads a brain from either layout
+fn load(path: &Path) -> anyhow::Result<Brain> {
+    if path.extension().and_then(|s| s.to_str()) == Some("sdbm") {
+        return Ok(FrozenBrain::open(path)?.thaw());
     }
-    bar.done();
-
-    save(&brain, &config.output)?;
-
-    Ok(())
+    Ok(format::load_file(path)?)
 }
@@ -0,0 +1,325 @@
+use std::{
+    path::Path,
+    sync::{mpsc::Receiver, Mutex},
+};
+
+use anyhow::Context as _;
+use filters::Filters;
+use indicatif::{ProgressBar, ProgressStyle};
+use markov::{
+    format::{self, Compression, SaveOptions},
+    Brain, FrozenBrain,
+};
+
+use crate::{
+    import::{Filter, Format, Importer},
+    input::Input,
+    load,
+};
+
+#[derive(Debug, gumdrop::Options)]
+pub struct Config {
+    #[options(help = "print help message")]
+    help: bool,
+
+    #[options(
+        help = "input files or globs to train from, gzip and zstd are decompressed. - (or none) reads stdin",
+        short = "i",
+        meta = "path"
+    )]
+    input: Vec<String>,
+
+    #[options(
+        help = "format of the input: raw, irc, jsonl or csv",
+        no_short,
+        default = "raw",
+        meta = "format"
+    )]
+    format: Format,
+
+    #[options(
+        help = "jsonl field (e.g. message.body) or csv column (a name or an index) of the message",
+        no_short,
+        default = "message",
+        meta = "field"
+    )]
+    field: String,
+
+    #[options(
+        help = "jsonl field or csv column of the user, used to skip users",
+        no_short,
+        meta = "field"
+    )]
+    user_field: Option<String>,
+
+    #[options(help = "skip messages from this user", no_short, meta = "name")]
+    skip_user: Vec<String>,
+
+    #[options(help = "skip messages from well-known chat bots", no_short)]
+    skip_bots: bool,
+
+    #[options(
+        help = "skip messages that look like commands (starting with !)",
+        no_short
+    )]
+    skip_commands: bool,
+
+    #[options(
+        help = "comma separated filters for the text, e.g. standard,name=shaken,max-repeat=4,min-words=2",
+        no_short,
+        meta = "filters"
+    )]
+    filters: Option<Filters>,
+
+    #[options(
+        help = "file of emotes (separated by whitespace) for the emote-only filter",
+        no_short,
+        meta = "path"
+    )]
+    emotes: Option<String>,
+
+    #[options(
+        help = "output path, a .sdbm extension writes the memory-mapped layout",
+        short = "o",
+        default = "output.sdb",
+        meta = "path"
+    )]
+    output: String,
+
+    #[options(
+        help = "name of the brain",
+        short = "n",
+        default = "corpora",
+        meta = "string"
+    )]
+    name: String,
+
+    #[options(help = "display progress", short = "p", default = "false")]
+    progress: bool,
+
+    #[options(
+        help = "number of threads to train with",
+        short = "j",
+        default = "1",
+        meta = "int"
+    )]
+    threads: usize,
+
+    #[options(
+        help = "continue training the existing output, keeping its name and depth",
+        short = "r"
+    )]
+    resume: bool,
+
+    #[options(help = "ngram size", short = "d", default = "5", meta = "int")]
+    depth: usize,
+
+    #[options(
+        help = "file of lines to forget after training (this can be compressed)",
+        short = "f",
+        meta = "path"
+    )]
+    forget: Option<String>,
+
+    #[options(
+        help = "drop links seen fewer than this many times",
+        short = "m",
+        meta = "int"
+    )]
+    min_count: Option<usize>,
+
+    #[options(
+        help = "compression for the output: none, zstd or snappy",
+        short = "c",
+        default = "zstd",
+        meta = "kind"
+    )]
+    compression: Compression,
+
+    #[options(
+        help = "number of previous outputs to keep as backups",
+        short = "b",
+        default = "0",
+        meta = "int"
+    )]
+    backups: usize,
+}
+
+#[derive(Default)]
+enum Progress {
+    Show(ProgressBar),
+    #[default]
+    Hide,
+}
+
+impl Progress {
+    fn show() -> Self {
+        let template = "{spinner} {human_pos} lines ({per_sec}) {elapsed}";
+
+        let bar = ProgressBar::new_spinner();
+        let style = ProgressStyle::with_template(template).unwrap();
+        bar.set_style(style);
+
+        Self::Show(bar)
+    }
+    fn tick(&self) {
+        if let Self::Show(bar) = self {
+            bar.inc(1)
+        }
+    }
+    fn done(&self) {
+        if let Self::Show(bar) = self {
+            bar.finish();
+        }
+    }
+}
+
+/// Trains a brain from the inputs, writing it to the output
+pub fn run(config: Config) -> anyhow::Result<()> {
+    anyhow::ensure!(config.threads > 0, "at least one thread is needed");
+
+    let output = Path::new(&config.output);
+    let mut brain = if config.resume && output.exists() {
+        load(output)?
+    } else {
+        Brain::new(&config.name, config.depth)
+    };
+
+    let inputs = Input::expand(&config.input)?;
+    let importer = importer(&config)?;
+
+    let bar = if config.progress {
+        Progress::show()
+    } else {
+        Progress::default()
+    };
+
+    if config.threads == 1 {
+        importer.for_each_message(&inputs, |line| {
+            bar.tick();
+            brain.train(&line);
+            Ok(())
+        })?;
+    } else {
+        train_sharded(&mut brain, &importer, &inputs, config.threads, &bar)?;
+    }
+    bar.done();
+
+    if let Some(path) = &config.forget {
+        // every line was trained after it was filtered, so it's forgotten the same way
+        Input::for_each_line(&Input::expand(std::slice::from_ref(path))?, |line| {
+            if let Some(line) = importer.filters.apply(&line) {
+                brain.forget(&line);
+            }
+            Ok(())
+        })?;
+    }
+
+    if let Some(min_count) = config.min_count {
+        brain.prune(min_count);
+    }
+
+    let options = SaveOptions {
+        compression: config.compression,
+        backups: config.backups,
+    };
+    if config.output.ends_with(".sdbm") {
+        FrozenBrain::save_file(&brain, output, options.backups)?;
+    } else {
+        format::save_file(&brain, output, &options)?;
+    }
+
+    Ok(())
+}
+
+fn importer(config: &Config) -> anyhow::Result<Importer> {
+    let mut filter = Filter {
+        users: config.skip_user.iter().map(|s| s.to_lowercase()).collect(),
+        command_prefix: config.skip_commands.then(|| "!".to_string()),
+    };
+    if config.skip_bots {
+        filter.skip_bots()
+    }
+
+    let mut filters = config.filters.clone().unwrap_or_default();
+    if let Some(path) = &config.emotes {
+        let emotes = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read emotes from {path}"))?;
+        filters
+            .emotes
+            .extend(emotes.split_whitespace().map(ToString::to_string));
+    }
+
+    Ok(Importer {
+        format: config.format,
+        field: config.field.clone(),
+        user_field: config.user_field.clone(),
+        filter,
+        filters,
+    })
+}
+
+/// Trains a brain per thread, then merges them all into `brain`
+fn train_sharded(
+    brain: &mut Brain,
+    importer: &Importer,
+    inputs: &[Input],
+    threads: usize,
+    bar: &Progress,
+) -> anyhow::Result<()> {
+    const BATCH: usize = 1024;
+
+    let (tx, rx) = std::sync::mpsc::sync_channel::<Vec<String>>(threads * 2);
+    let rx = Mutex::new(rx);
+    let (name, depth) = (brain.name().to_string(), brain.depth());
+
+    std::thread::scope(|scope| {
+        let shards = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut shard = Brain::new(&name, depth);
+                    receive_batches(&rx, |batch| {
+                        for line in batch {
+                            shard.train(&line)
+                        }
+                    });
+                    shard
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut batch = Vec::with_capacity(BATCH);
+        let read = importer.for_each_message(inputs, |line| {
+            bar.tick();
+            batch.push(line);
+            if batch.len() == BATCH {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH));
+                tx.send(full)?;
+            }
+            Ok(())
+        });
+        if !batch.is_empty() {
+            let _ = tx.send(batch);
+        }
+        drop(tx);
+
+        for shard in shards {
+            let shard = shard.join().expect("training thread panicked");
+            brain.merge(&shard)?;
+        }
+        read
+    })
+}
+
+/// Hands each batch received on a shared `rx` to `each`, until every sender is gone
+fn receive_batches<T>(rx: &Mutex<Receiver<T>>, mut each: impl FnMut(T)) {
+    loop {
+        // the lock has to be released before the batch is handled, or only one shard trains at a time
+        let batch = rx.lock().unwrap().recv();
+        let Ok(batch) = batch else { break };
+        each(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests;
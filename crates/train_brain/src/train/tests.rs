@@ -0,0 +1,38 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use super::*;
+
+#[test]
+fn shards_receive_concurrently() {
+    let (tx, rx) = std::sync::mpsc::sync_channel(2);
+    let rx = Mutex::new(rx);
+    let (busy, overlapped) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+    std::thread::scope(|scope| {
+        for _ in 0..2 {
+            scope.spawn(|| {
+                receive_batches(&rx, |_: usize| {
+                    // wait for the other shard to be handling a batch at the same time
+                    busy.fetch_add(1, Ordering::SeqCst);
+                    let start = Instant::now();
+                    while start.elapsed() < Duration::from_secs(5) {
+                        if busy.load(Ordering::SeqCst) == 2 {
+                            overlapped.fetch_add(1, Ordering::SeqCst);
+                            break;
+                        }
+                        std::thread::yield_now();
+                    }
+                })
+            });
+        }
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        drop(tx);
+    });
+
+    assert_eq!(overlapped.load(Ordering::SeqCst), 2);
+}